@@ -0,0 +1,130 @@
+//////////// Standard Header file for the PIC16F883 device ////////////////
+///////////////////////////////////////////////////////////////////////////
+////        (C) Copyright 1996, 2014 Custom Computer Services          ////
+//// This source code may only be used by licensed users of the CCS C  ////
+//// compiler.  This source code may only be distributed to other      ////
+//// licensed users of the CCS C compiler.  No other use, reproduction ////
+//// or distribution is permitted without written permission.          ////
+//// Derivative programs created using this software in object code    ////
+//// form are not restricted in any way.                               ////
+///////////////////////////////////////////////////////////////////////////
+// Fixture: trimmed copy of the header for tests
+#device PIC16F883
+
+#nolist
+//////// Program memory: 4096x14  Data RAM: 256  Stack: 8
+//////// I/O: 25   Analog Pins: 11
+//////// Data EEPROM: 256
+//////// C Scratch area: 77   ID Location: 2000
+//////// Fuses: LP,XT,HS,EC_IO,INTRC_IO,INTRC,RC_IO,RC,NOWDT,WDT,NOPUT,PUT
+//////// Fuses: NOMCLR,MCLR,NOPROTECT,PROTECT,NOCPD,CPD,NOBROWNOUT,BROWNOUT
+//////// Fuses: BROWNOUT_NOSL,BROWNOUT_SW,NOIESO,IESO,NOFCMEN,FCMEN,NOLVP,LVP
+//////// Fuses: NODEBUG,DEBUG,NOWRT,WRT_1024,WRT_512,WRT_256,BORV40,BORV21
+//////// 
+////////////////////////////////////////////////////////////////// I/O
+// Discrete I/O Functions: SET_TRIS_x(), OUTPUT_x(), INPUT_x(),
+//                         PORT_x_PULLUPS(), INPUT(),
+//                         OUTPUT_LOW(), OUTPUT_HIGH(),
+//                         OUTPUT_FLOAT(), OUTPUT_BIT()
+// Constants used to identify pins in the above are:
+
+#define PIN_A0  40
+#define PIN_A1  41
+#define PIN_A2  42
+#define PIN_A3  43
+#define PIN_A4  44
+#define PIN_A5  45
+#define PIN_A6  46
+#define PIN_A7  47
+
+#define PIN_B0  48
+#define PIN_B1  49
+#define PIN_B2  50
+#define PIN_B3  51
+#define PIN_B4  52
+#define PIN_B5  53
+#define PIN_B6  54
+#define PIN_B7  55
+
+#define PIN_C0  56
+#define PIN_C1  57
+#define PIN_C2  58
+#define PIN_C3  59
+#define PIN_C4  60
+#define PIN_C5  61
+#define PIN_C6  62
+#define PIN_C7  63
+
+#define PIN_E3  75
+
+////////////////////////////////////////////////////////////////// Useful defines
+#define FALSE 0
+#define TRUE 1
+
+#define BYTE int8
+#define BOOLEAN int1
+
+#define getc getch
+#define fgetc getch
+#define getchar getch
+#define putc putchar
+#define fputc putchar
+#define fgets gets
+#define fputs puts
+
+////////////////////////////////////////////////////////////////// Registers
+#byte STATUS = 0x03
+#byte PORTA = 0x05
+#byte PORTB = 0x06
+#byte PORTC = 0x07
+#word TMR1 = 0x0E
+#bit C = 0x03.0
+#bit Z = STATUS.2
+
+////////////////////////////////////////////////////////////////// Control
+// Control Functions:  RESET_CPU(), SLEEP(), RESTART_CAUSE()
+// Constants returned from RESTART_CAUSE() are:
+
+#define WDT_FROM_SLEEP  3     
+#define WDT_TIMEOUT     11    
+#define MCLR_FROM_SLEEP 19    
+#define MCLR_FROM_RUN   27    
+#define NORMAL_POWER_UP 25    
+#define BROWNOUT_RESTART 26   
+
+////////////////////////////////////////////////////////////////// Timer 0
+// Timer 0 (AKA RTCC)Functions: SETUP_COUNTERS() or SETUP_TIMER_0(),
+//                              SET_TIMER0() or SET_RTCC(),
+//                              GET_TIMER0() or GET_RTCC()
+// Constants used for SETUP_TIMER_0() are:
+#define T0_INTERNAL   0
+#define T0_EXT_L_TO_H 32
+#define T0_EXT_H_TO_L 48
+
+#define T0_DIV_1      8
+#define T0_DIV_2      0
+#define T0_DIV_256    7
+
+////////////////////////////////////////////////////////////////// INT
+// Interrupt Functions: ENABLE_INTERRUPTS(), DISABLE_INTERRUPTS(),
+//                      CLEAR_INTERRUPT(), INTERRUPT_ACTIVE(),
+//                      EXT_INT_EDGE()
+//
+// Constants used in EXT_INT_EDGE() are:
+#define L_TO_H              0x40
+#define H_TO_L                 0
+// Constants used in ENABLE/DISABLE_INTERRUPTS() are:
+#define GLOBAL                    0x0BC0
+#define INT_RTCC                  0x000B20
+#define INT_RB                    0x01FF0B08
+#define INT_EXT_L2H               0x50000B10
+#define INT_EXT_H2L               0x60000B10
+#define INT_EXT                   0x000B10
+#define INT_AD                    0x008C40
+#define INT_TBE                   0x008C10
+#define INT_RDA                   0x008C20
+#define INT_TIMER1                0x008C01
+#define INT_TIMER2                0x008C02
+#define INT_TIMER0                0x000B20
+
+#list
@@ -0,0 +1,178 @@
+use tower_lsp::lsp_types::Range;
+use tree_sitter::{Node, Point, Tree};
+
+use crate::utils;
+
+/// An `asm_block` node, i.e. the lines from `#asm` to `#endasm`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AsmBlock {
+    /// Line of `#asm`
+    pub start_line: usize,
+    /// Line of `#endasm`
+    pub end_line: usize,
+}
+
+impl AsmBlock {
+    fn from_node(node: Node) -> AsmBlock {
+        AsmBlock {
+            start_line: node.start_position().row,
+            end_line: node.end_position().row,
+        }
+    }
+
+    pub fn contains_line(&self, line: usize) -> bool {
+        self.start_line <= line && line <= self.end_line
+    }
+
+    /// Whether `line` holds an instruction, i.e. lies between `#asm` and `#endasm`
+    pub fn contains_instruction_line(&self, line: usize) -> bool {
+        self.start_line < line && line < self.end_line
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmWord {
+    pub text: String,
+    pub range: Range,
+}
+
+impl AsmWord {
+    fn from_node(node: Node, source: &[u8]) -> Option<AsmWord> {
+        Some(AsmWord {
+            text: node.utf8_text(source).ok()?.to_owned(),
+            range: utils::get_range(&node),
+        })
+    }
+}
+
+/// A line of an asm block, e.g. `loop: decfsz count, F ; spin`
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct AsmLine {
+    pub label: Option<AsmWord>,
+    pub mnemonic: Option<AsmWord>,
+    pub operands: Vec<AsmWord>,
+}
+
+pub fn find_asm_blocks(tree: &Tree) -> Vec<AsmBlock> {
+    fn collect_blocks(node: Node, out: &mut Vec<AsmBlock>) {
+        if node.kind() == "asm_block" {
+            out.push(AsmBlock::from_node(node));
+            return;
+        }
+        let mut cursor = node.walk();
+        for child in node.named_children(&mut cursor) {
+            collect_blocks(child, out);
+        }
+    }
+
+    let mut out = vec![];
+    collect_blocks(tree.root_node(), &mut out);
+    out
+}
+
+/// Returns the asm line at `point` if it lies inside an asm block. Lines without label or
+/// instruction are returned empty
+pub fn find_asm_line(tree: &Tree, source: &[u8], point: Point) -> Option<AsmLine> {
+    let mut node = tree.root_node().descendant_for_point_range(point, point);
+    let block = loop {
+        let n = node?;
+        if n.kind() == "asm_block" {
+            break n;
+        }
+        node = n.parent();
+    };
+    if !AsmBlock::from_node(block).contains_instruction_line(point.row) {
+        return None;
+    }
+
+    let mut out = AsmLine::default();
+    let mut cursor = block.walk();
+    for child in block.named_children(&mut cursor) {
+        if child.start_position().row != point.row {
+            continue;
+        }
+        match child.kind() {
+            "asm_label" => {
+                out.label = child
+                    .child_by_field_name("name")
+                    .and_then(|n| AsmWord::from_node(n, source));
+            }
+            "asm_instruction" => {
+                out.mnemonic = child
+                    .child_by_field_name("mnemonic")
+                    .and_then(|n| AsmWord::from_node(n, source));
+                let mut cursor = child.walk();
+                out.operands = child
+                    .children_by_field_name("operand", &mut cursor)
+                    .filter_map(|n| AsmWord::from_node(n, source))
+                    .collect();
+            }
+            _ => {}
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::Position;
+
+    use super::*;
+    use crate::test_utils::parse;
+
+    fn get_texts(words: &[AsmWord]) -> Vec<&str> {
+        words.iter().map(|w| w.text.as_str()).collect()
+    }
+
+    #[test]
+    fn test_find_asm_blocks() {
+        let source = "void f() {\n#asm\n   movlw 0x10\n#endasm\n}\n#ASM ASIS\n   nop\n#ENDASM\n";
+
+        assert_eq!(
+            find_asm_blocks(&parse(source)),
+            vec![
+                AsmBlock {
+                    start_line: 1,
+                    end_line: 3
+                },
+                AsmBlock {
+                    start_line: 5,
+                    end_line: 7
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_find_asm_line() {
+        let source = "#asm\n   movlw b'0101' // low nibble\n\nloop: decfsz count, F ; spin, spin\n\
+                      #endasm\n";
+        let tree = parse(source);
+
+        let line = find_asm_line(&tree, source.as_bytes(), Point::new(3, 8)).unwrap();
+        assert_eq!(line.label.unwrap().text, "loop");
+        let mnemonic = line.mnemonic.unwrap();
+        assert_eq!(mnemonic.text, "decfsz");
+        assert_eq!(
+            mnemonic.range,
+            Range::new(Position::new(3, 6), Position::new(3, 12))
+        );
+        assert_eq!(get_texts(&line.operands), vec!["count", "F"]);
+        assert_eq!(
+            line.operands[1].range,
+            Range::new(Position::new(3, 20), Position::new(3, 21))
+        );
+
+        let line = find_asm_line(&tree, source.as_bytes(), Point::new(1, 4)).unwrap();
+        assert_eq!(line.label, None);
+        assert_eq!(line.mnemonic.unwrap().text, "movlw");
+        assert_eq!(get_texts(&line.operands), vec!["b'0101'"]);
+
+        let line = find_asm_line(&tree, source.as_bytes(), Point::new(2, 0)).unwrap();
+        assert_eq!(line, AsmLine::default());
+        assert_eq!(
+            find_asm_line(&tree, source.as_bytes(), Point::new(0, 2)),
+            None
+        );
+    }
+}
@@ -0,0 +1,5 @@
+pub use crate::asm::asm_block::*;
+pub use crate::asm::pic_instruction::*;
+
+pub mod asm_block;
+pub mod pic_instruction;
@@ -0,0 +1,77 @@
+use lazy_static::lazy_static;
+use serde::Deserialize;
+
+lazy_static! {
+    static ref PIC_INSTRUCTIONS: Vec<PicInstruction> =
+        serde_json::from_str(include_str!("pic_instructions.json")).unwrap();
+}
+
+/// An instruction of the PIC16/PIC18 instruction sets. The catalog lives in
+/// `pic_instructions.json`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct PicInstruction {
+    pub name: String,
+    /// e.g. `f, d`
+    pub operands: String,
+    pub description: String,
+    /// Instruction cycles, skips and branches in parentheses, e.g. `1 (2)`
+    pub cycles: String,
+    /// Affected status bits
+    pub status: String,
+    pub family: String,
+}
+
+impl PicInstruction {
+    pub fn all() -> &'static [PicInstruction] {
+        &PIC_INSTRUCTIONS
+    }
+
+    /// Mnemonics are case-insensitive
+    pub fn find(name: &str) -> Option<&'static PicInstruction> {
+        PIC_INSTRUCTIONS
+            .iter()
+            .find(|i| i.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn get_hover_text(&self) -> String {
+        let status = if self.status.is_empty() {
+            "none"
+        } else {
+            self.status.as_str()
+        };
+        let title = if self.operands.is_empty() {
+            format!("**{}**", self.name)
+        } else {
+            format!("**{}** `{}`", self.name, self.operands)
+        };
+        format!(
+            "{}\n\n{}\n\nCycles: {}, status bits: {} ({})",
+            title, self.description, self.cycles, status, self.family
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_catalog_is_valid() {
+        assert!(!PicInstruction::all().is_empty());
+        for instruction in PicInstruction::all() {
+            assert_eq!(
+                PicInstruction::find(&instruction.name),
+                Some(instruction),
+                "'{}' is listed twice",
+                instruction.name
+            );
+        }
+
+        let movlw = PicInstruction::find("MOVLW").unwrap();
+        assert_eq!(
+            movlw.get_hover_text(),
+            "**movlw** `k`\n\nMove literal to W\n\nCycles: 1, status bits: none (PIC16, PIC18)"
+        );
+        assert!(PicInstruction::find("nop").unwrap().get_hover_text().starts_with("**nop**\n"));
+    }
+}
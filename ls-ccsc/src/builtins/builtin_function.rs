@@ -0,0 +1,96 @@
+use lazy_static::lazy_static;
+use serde::Deserialize;
+
+use crate::symbols;
+
+lazy_static! {
+    static ref BUILTIN_FUNCTIONS: Vec<BuiltinFunction> =
+        serde_json::from_str(include_str!("builtin_functions.json")).unwrap();
+}
+
+/// A function of the CCS C standard library. The catalog lives in `builtin_functions.json`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct BuiltinFunction {
+    pub name: String,
+    pub returns: String,
+    pub parameters: Vec<BuiltinParameter>,
+    /// Markdown
+    pub documentation: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct BuiltinParameter {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub type_name: String,
+    /// Whether the parameter takes a pin, e.g. `PIN_C0`
+    #[serde(default)]
+    pub is_pin: bool,
+    /// Markdown
+    pub documentation: String,
+}
+
+impl BuiltinFunction {
+    pub fn all() -> &'static [BuiltinFunction] {
+        &BUILTIN_FUNCTIONS
+    }
+
+    /// Also matches `Output_Low` unless the project uses `#case`
+    pub fn find(name: &str, case_sensitive: bool) -> Option<&'static BuiltinFunction> {
+        BUILTIN_FUNCTIONS
+            .iter()
+            .find(|f| symbols::is_same_name(&f.name, name, case_sensitive))
+    }
+
+    /// Returns the signature as written in the CCS manual, e.g. `void output_low(int16 pin)`
+    pub fn get_signature(&self) -> String {
+        format!(
+            "{} {}({})",
+            self.returns,
+            self.name,
+            self.get_parameter_labels().join(", ")
+        )
+    }
+
+    pub fn get_parameter_labels(&self) -> Vec<String> {
+        self.parameters
+            .iter()
+            .map(|p| match p.type_name.as_str() {
+                "..." => "...".to_owned(),
+                t if t.ends_with('*') => format!("{}{}", t, p.name),
+                t => format!("{} {}", t, p.name),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_catalog_is_valid() {
+        assert!(!BuiltinFunction::all().is_empty());
+        for function in BuiltinFunction::all() {
+            assert_eq!(
+                BuiltinFunction::find(&function.name, true),
+                Some(function),
+                "'{}' is listed twice",
+                function.name
+            );
+        }
+
+        let output_low = BuiltinFunction::find("output_low", true).unwrap();
+        assert_eq!(output_low.get_signature(), "void output_low(int16 pin)");
+        let printf = BuiltinFunction::find("printf", true).unwrap();
+        assert_eq!(printf.get_signature(), "void printf(char *string, ...)");
+
+        let output_bit = BuiltinFunction::find("output_bit", true).unwrap();
+        let pins = output_bit
+            .parameters
+            .iter()
+            .map(|p| p.is_pin)
+            .collect::<Vec<_>>();
+        assert_eq!(pins, vec![true, false]);
+    }
+}
@@ -0,0 +1,3 @@
+pub use crate::builtins::builtin_function::*;
+
+pub mod builtin_function;
@@ -1,4 +1,4 @@
-use tower_lsp::lsp_types::{Diagnostic, Url};
+use tower_lsp::lsp_types::{Diagnostic, Range, Url};
 
 use crate::DiagnosticSeverity;
 
@@ -7,6 +7,10 @@ use crate::DiagnosticSeverity;
 pub struct CCSCResponse {
     pub logs: Option<Vec<String>>,
     pub uri_diagnostics: Option<(Url, Vec<Diagnostic>)>,
+    /// Diagnostics of other documents, which changed along with the document of the response
+    pub related_diagnostics: Vec<(Url, Vec<Diagnostic>)>,
+    /// Sent as `ccsc/inactiveRegions` notifications
+    pub inactive_regions: Vec<(Url, Vec<Range>)>,
 }
 
 impl CCSCResponse {
@@ -14,9 +18,24 @@ impl CCSCResponse {
         CCSCResponse {
             logs,
             uri_diagnostics,
+            related_diagnostics: vec![],
+            inactive_regions: vec![],
         }
     }
 
+    pub fn with_inactive_regions(mut self, inactive_regions: Vec<(Url, Vec<Range>)>) -> Self {
+        self.inactive_regions = inactive_regions;
+        self
+    }
+
+    pub fn with_related_diagnostics(
+        mut self,
+        related_diagnostics: Vec<(Url, Vec<Diagnostic>)>,
+    ) -> Self {
+        self.related_diagnostics = related_diagnostics;
+        self
+    }
+
     pub fn from_diagnostics(uri: Url, diagnostics: Vec<Diagnostic>) -> Self {
         CCSCResponse::new(None, Some((uri, diagnostics)))
     }
@@ -0,0 +1,246 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use tower_lsp::jsonrpc::Result;
+
+use crate::utils;
+
+/// A function in the call tree, e.g. `├─add  0/5  Ram=2` or `├─@cinit1  (Inline)  Ram=0`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallTreeNode {
+    pub name: String,
+    /// Zero-based line in the .tre file
+    pub line: u32,
+    /// Index of the calling node. `None` for functions called by the program itself, e.g.
+    /// `MAIN` or interrupt handlers
+    pub parent: Option<usize>,
+    /// Program memory page. `None` for inlined functions
+    pub page: Option<u32>,
+    /// Program words
+    pub rom: Option<u32>,
+    /// Bytes
+    pub ram: Option<u32>,
+}
+
+impl CallTreeNode {
+    /// Returns true, if the compiler inlined the function into its caller
+    pub fn is_inline(&self) -> bool {
+        self.page.is_none()
+    }
+
+    /// Returns the name without the prefix and suffix the compiler adds to the built-in functions
+    /// it generates, e.g. `delay_ms` for `@delay_ms1`
+    pub fn get_base_name(&self) -> &str {
+        self.name
+            .trim_start_matches('@')
+            .trim_end_matches(|c: char| c.is_ascii_digit())
+    }
+
+    /// Returns e.g. `ROM 5 words, RAM 2 bytes` or `Inline, RAM 0 bytes`
+    pub fn get_summary(&self) -> String {
+        let rom = match self.rom {
+            Some(rom) if !self.is_inline() => format!("ROM {} words", rom),
+            _ => "Inline".to_owned(),
+        };
+        match self.ram {
+            Some(ram) => format!("{}, RAM {} bytes", rom, ram),
+            None => rom,
+        }
+    }
+}
+
+/// Functions as the compiler emitted them and who calls them, parsed from the .tre file
+/// (e.g. `main.tre`)
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct CallTree {
+    pub path: PathBuf,
+    pub nodes: Vec<CallTreeNode>,
+}
+
+impl CallTree {
+    pub fn load(path: &Path) -> Result<Self> {
+        let raw = fs::read(path).map_err(|e| {
+            utils::create_server_error(11, format!("Failed to read '{}': {}", path.display(), e))
+        })?;
+        let mut out = CallTree::from_tre(&decode_tree_drawing(&raw));
+        out.path = path.to_owned();
+        Ok(out)
+    }
+
+    pub fn from_tre(raw: &str) -> Self {
+        fn parse_node(text: &str, line: u32, parent: Option<usize>) -> Option<CallTreeNode> {
+            let mut tokens = text.split_whitespace();
+            let name = tokens.next()?.to_owned();
+            let mut out = CallTreeNode {
+                name,
+                line,
+                parent,
+                page: None,
+                rom: None,
+                ram: None,
+            };
+            for token in tokens {
+                if let Some(ram) = token.strip_prefix("Ram=") {
+                    out.ram = ram.parse().ok();
+                } else if let Some((page, rom)) = token.split_once('/') {
+                    out.page = page.parse().ok();
+                    out.rom = rom.parse().ok();
+                }
+            }
+            Some(out)
+        }
+
+        let mut out = CallTree::default();
+        // Indentation and index of the nodes from the root to the previous line
+        let mut ancestors: Vec<(usize, Option<usize>)> = vec![];
+        for (line, text) in raw.lines().enumerate() {
+            let (indentation, branch) = match text.char_indices().find(|(_, c)| is_branch(*c)) {
+                Some(found) => found,
+                None => continue,
+            };
+            let text = text[indentation + branch.len_utf8()..].trim_start_matches('─');
+            while ancestors.last().is_some_and(|(i, _)| *i >= indentation) {
+                ancestors.pop();
+            }
+
+            // The first level only names the program, e.g. `└─main`
+            if ancestors.is_empty() {
+                ancestors.push((indentation, None));
+                continue;
+            }
+            let parent = ancestors.last().and_then(|(_, index)| *index);
+            match parse_node(text, line as u32, parent) {
+                Some(node) => {
+                    ancestors.push((indentation, Some(out.nodes.len())));
+                    out.nodes.push(node);
+                }
+                None => ancestors.push((indentation, parent)),
+            }
+        }
+        out
+    }
+
+    /// Returns every node of the function `name`. Names are compared case-insensitively, the
+    /// compiler writes `MAIN` for `main`
+    pub fn get_nodes<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a CallTreeNode> {
+        self.nodes
+            .iter()
+            .filter(move |n| n.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Returns the functions `name` calls
+    pub fn get_callees(&self, name: &str) -> Vec<&CallTreeNode> {
+        let mut out: Vec<&CallTreeNode> = vec![];
+        for node in &self.nodes {
+            let is_callee = node
+                .parent
+                .is_some_and(|p| self.nodes[p].name.eq_ignore_ascii_case(name));
+            let is_known = out.iter().any(|n| n.name.eq_ignore_ascii_case(&node.name));
+            if is_callee && !is_known {
+                out.push(node);
+            }
+        }
+        out
+    }
+
+    /// Returns the functions calling `name`
+    pub fn get_callers(&self, name: &str) -> Vec<&CallTreeNode> {
+        let mut out: Vec<&CallTreeNode> = vec![];
+        for parent in self.get_nodes(name).filter_map(|n| n.parent) {
+            let parent = &self.nodes[parent];
+            if !out
+                .iter()
+                .any(|n| n.name.eq_ignore_ascii_case(&parent.name))
+            {
+                out.push(parent);
+            }
+        }
+        out
+    }
+}
+
+fn is_branch(c: char) -> bool {
+    matches!(c, '├' | '└')
+}
+
+/// The compiler draws the tree with code page 437 box-drawing characters. Files which are valid
+/// UTF-8 are returned as they are
+pub fn decode_tree_drawing(raw: &[u8]) -> String {
+    if let Ok(out) = std::str::from_utf8(raw) {
+        return out.to_owned();
+    }
+    raw.iter()
+        .map(|b| match b {
+            0x00..=0x7F => *b as char,
+            0xB3 => '│',
+            0xB4 => '┤',
+            0xBF => '┐',
+            0xC0 => '└',
+            0xC1 => '┴',
+            0xC2 => '┬',
+            0xC3 => '├',
+            0xC4 => '─',
+            0xC5 => '┼',
+            0xD9 => '┘',
+            0xDA => '┌',
+            _ => char::REPLACEMENT_CHARACTER,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RAW: &[u8] = b"\xC0\xC4main\n   \xC0\xC4MAIN  0/50  Ram=2\n      \xC3\xC4@cinit1  (Inline)  Ram=0\n\
+                         \x20     \xC3\xC4add  0/5  Ram=2\n      \xB3  \xC0\xC4@MUL88  0/18  Ram=3\n\
+                         \x20     \xC0\xC4@delay_ms1  0/22  Ram=1\n";
+
+    #[test]
+    fn test_decode_tree_drawing() {
+        let decoded = decode_tree_drawing(RAW);
+
+        assert!(decoded.starts_with("└─main\n   └─MAIN"));
+        assert!(decoded.contains("      │  └─@MUL88"));
+        assert_eq!(decode_tree_drawing("├─add".as_bytes()), "├─add");
+    }
+
+    #[test]
+    fn test_call_tree() {
+        let tree = CallTree::from_tre(&decode_tree_drawing(RAW));
+
+        let nodes = tree
+            .nodes
+            .iter()
+            .map(|n| (n.name.as_str(), n.line, n.parent, n.rom, n.ram))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            nodes,
+            vec![
+                ("MAIN", 1, None, Some(50), Some(2)),
+                ("@cinit1", 2, Some(0), None, Some(0)),
+                ("add", 3, Some(0), Some(5), Some(2)),
+                ("@MUL88", 4, Some(2), Some(18), Some(3)),
+                ("@delay_ms1", 5, Some(0), Some(22), Some(1)),
+            ]
+        );
+        assert!(tree.nodes[1].is_inline());
+        assert_eq!(tree.nodes[4].get_base_name(), "delay_ms");
+    }
+
+    #[test]
+    fn test_callers_and_callees() {
+        let tree = CallTree::from_tre(&decode_tree_drawing(RAW));
+
+        let callees = tree
+            .get_callees("main")
+            .iter()
+            .map(|n| n.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(callees, vec!["@cinit1", "add", "@delay_ms1"]);
+        assert_eq!(tree.get_callers("@mul88")[0].name, "add");
+        assert!(tree.get_callers("main").is_empty());
+        assert_eq!(tree.nodes[2].get_summary(), "ROM 5 words, RAM 2 bytes");
+        assert_eq!(tree.nodes[1].get_summary(), "Inline, RAM 0 bytes");
+    }
+}
@@ -0,0 +1,504 @@
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use tower_lsp::jsonrpc::Result;
+
+use crate::compiler_output::{self, ProjectStatistics, SymbolMap};
+
+/// Name of the command line subcommand, e.g. `ls-ccsc hex main.hex`
+pub const HEX_SUBCOMMAND: &str = "hex";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryRegion {
+    Program,
+    IdLocations,
+    Config,
+    Eeprom,
+    /// E.g. the device ID or calibration words
+    Other,
+}
+
+/// Byte addresses the memory regions of a device family are written to in .hex files
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryLayout {
+    pub id_locations: Range<u32>,
+    pub config: Range<u32>,
+    pub eeprom: Range<u32>,
+    /// PIC10/12/16 address program memory in words, PIC18 in bytes
+    pub word_addressed: bool,
+}
+
+impl MemoryLayout {
+    /// PIC10/12/16, e.g. the PIC16F883. Config words at `0x2007`, EEPROM at `0x2100`
+    pub fn midrange() -> Self {
+        MemoryLayout {
+            id_locations: 0x4000..0x4008,
+            config: 0x400E..0x4020,
+            eeprom: 0x4200..0x4400,
+            word_addressed: true,
+        }
+    }
+
+    /// PIC12F1xxx/PIC16F1xxx. Config words at `0x8007`, EEPROM at `0xF000`
+    pub fn enhanced_midrange() -> Self {
+        MemoryLayout {
+            id_locations: 0x10000..0x10008,
+            config: 0x1000E..0x10020,
+            eeprom: 0x1E000..0x1E200,
+            word_addressed: true,
+        }
+    }
+
+    pub fn pic18() -> Self {
+        MemoryLayout {
+            id_locations: 0x200000..0x200008,
+            config: 0x300000..0x300010,
+            eeprom: 0xF00000..0xF01000,
+            word_addressed: false,
+        }
+    }
+
+    /// Guesses the family from the device the compiler wrote into the file (e.g. `;PIC16F883`),
+    /// falling back to the highest address used
+    fn detect(device: Option<&str>, max_address: Option<u32>) -> Self {
+        if let Some(device) = device {
+            let family = device.trim_start_matches("PIC");
+            if family.starts_with("18") {
+                return MemoryLayout::pic18();
+            }
+            // E.g. `16F1827` or `16LF18346`, but not `16F883`
+            let number = family
+                .get(2..)
+                .unwrap_or_default()
+                .trim_start_matches(|c: char| c.is_ascii_alphabetic());
+            let is_enhanced = (family.starts_with("12") || family.starts_with("16"))
+                && number.len() >= 4
+                && number.starts_with('1');
+            if is_enhanced {
+                return MemoryLayout::enhanced_midrange();
+            }
+            return MemoryLayout::midrange();
+        }
+        match max_address {
+            Some(address) if address >= 0x200000 => MemoryLayout::pic18(),
+            Some(address) if address >= 0x10000 => MemoryLayout::enhanced_midrange(),
+            _ => MemoryLayout::midrange(),
+        }
+    }
+
+    pub fn get_region(&self, address: u32) -> MemoryRegion {
+        if address < self.id_locations.start {
+            MemoryRegion::Program
+        } else if self.id_locations.contains(&address) {
+            MemoryRegion::IdLocations
+        } else if self.config.contains(&address) {
+            MemoryRegion::Config
+        } else if self.eeprom.contains(&address) {
+            MemoryRegion::Eeprom
+        } else {
+            MemoryRegion::Other
+        }
+    }
+
+    /// Returns the address the device uses for the byte at `address` of the .hex file
+    pub fn get_device_address(&self, address: u32) -> u32 {
+        if self.word_addressed {
+            address / 2
+        } else {
+            address
+        }
+    }
+}
+
+/// Memory images of an Intel HEX file (INHX8M or INHX32) the compiler wrote (e.g. `main.hex`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HexFile {
+    pub path: PathBuf,
+    /// Device written by the compiler, e.g. `;PIC16F883`
+    pub device: Option<String>,
+    /// Checksum written by the compiler, e.g. `;CRC=34E4`. CCS does not document how it is
+    /// computed, so it is only compared against the one in the .sym file
+    pub crc: Option<u16>,
+    pub layout: MemoryLayout,
+    /// Data bytes by their address in the file
+    pub memory: BTreeMap<u32, u8>,
+    /// Malformed records, bad checksums, ...
+    pub errors: Vec<String>,
+}
+
+impl HexFile {
+    pub fn load(path: &Path) -> Result<Self> {
+        let raw = compiler_output::read_output_file(path)?;
+        let mut out = HexFile::from_hex(&raw);
+        out.path = path.to_owned();
+        Ok(out)
+    }
+
+    pub fn from_hex(raw: &str) -> Self {
+        fn decode(text: &str) -> Option<Vec<u8>> {
+            if !text.len().is_multiple_of(2) || !text.is_ascii() {
+                return None;
+            }
+            (0..text.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok())
+                .collect()
+        }
+
+        let mut device = None;
+        let mut crc = None;
+        let mut memory = BTreeMap::new();
+        let mut errors = vec![];
+        let mut base = 0u32;
+        let mut has_end = false;
+        for (index, line) in raw.lines().enumerate() {
+            let line = line.trim();
+            let number = index + 1;
+            if line.is_empty() {
+                continue;
+            }
+            // `;PIC16F883` and `;CRC=34E4  CREATED="02-Jan-22 13:50"`
+            if let Some(comment) = line.strip_prefix(';') {
+                match comment.strip_prefix("CRC=") {
+                    Some(rest) => {
+                        let value = rest.split_whitespace().next().unwrap_or_default();
+                        crc = u16::from_str_radix(value, 16).ok();
+                    }
+                    None if device.is_none() => device = Some(comment.trim().to_owned()),
+                    None => {}
+                }
+                continue;
+            }
+
+            let bytes = match line.strip_prefix(':').and_then(decode) {
+                Some(bytes) if bytes.len() >= 5 => bytes,
+                _ => {
+                    errors.push(format!("Line {}: Not an Intel HEX record", number));
+                    continue;
+                }
+            };
+            if has_end {
+                errors.push(format!(
+                    "Line {}: Record after the end of file record",
+                    number
+                ));
+                continue;
+            }
+            let length = bytes[0] as usize;
+            if bytes.len() != length + 5 {
+                errors.push(format!(
+                    "Line {}: Record announces {} data bytes, but has {}",
+                    number,
+                    length,
+                    bytes.len().saturating_sub(5)
+                ));
+                continue;
+            }
+            let sum = bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+            if sum != 0 {
+                let checksum = bytes[bytes.len() - 1];
+                errors.push(format!(
+                    "Line {}: Checksum is {:02X}, expected {:02X}",
+                    number,
+                    checksum,
+                    checksum.wrapping_sub(sum)
+                ));
+                continue;
+            }
+
+            let offset = u32::from(bytes[1]) << 8 | u32::from(bytes[2]);
+            let data = &bytes[4..4 + length];
+            let get_upper = || u32::from(data[0]) << 8 | u32::from(data[1]);
+            match (bytes[3], length) {
+                (0x00, _) => {
+                    for (i, b) in data.iter().enumerate() {
+                        match base.checked_add(offset + i as u32) {
+                            Some(address) => {
+                                memory.insert(address, *b);
+                            }
+                            None => {
+                                errors.push(format!(
+                                    "Line {}: Data exceeds the 32-bit address space",
+                                    number
+                                ));
+                                break;
+                            }
+                        }
+                    }
+                }
+                (0x01, _) => has_end = true,
+                (0x02, 2) => base = get_upper() << 4,
+                (0x04, 2) => base = get_upper() << 16,
+                (0x03, _) | (0x05, _) => {}
+                (kind, _) => errors.push(format!(
+                    "Line {}: Invalid record of type {:02X}",
+                    number, kind
+                )),
+            }
+        }
+        if !has_end {
+            errors.push("Missing end of file record. The file is truncated".to_owned());
+        }
+
+        let layout = MemoryLayout::detect(device.as_deref(), memory.keys().last().copied());
+        HexFile {
+            path: PathBuf::new(),
+            device,
+            crc,
+            layout,
+            memory,
+            errors,
+        }
+    }
+
+    /// Returns the bytes of `region` by their address in the file
+    pub fn get_image(&self, region: MemoryRegion) -> BTreeMap<u32, u8> {
+        self.memory
+            .iter()
+            .filter(|(address, _)| self.layout.get_region(**address) == region)
+            .map(|(address, b)| (*address, *b))
+            .collect()
+    }
+
+    /// Returns the little-endian words of `region` by their device address, e.g. `0x2007` for
+    /// the config word of a PIC16F883
+    pub fn get_words(&self, region: MemoryRegion) -> BTreeMap<u32, u16> {
+        let image = self.get_image(region);
+        let mut out = BTreeMap::new();
+        for address in image.keys().map(|a| a & !1) {
+            let low = image.get(&address).copied().unwrap_or(0xFF);
+            let high = image.get(&(address + 1)).copied().unwrap_or(0xFF);
+            out.insert(
+                self.layout.get_device_address(address),
+                u16::from(high) << 8 | u16::from(low),
+            );
+        }
+        out
+    }
+
+    /// Returns the EEPROM contents by their offset. PIC10/12/16 store every byte in a word
+    pub fn get_eeprom(&self) -> BTreeMap<u32, u8> {
+        let start = self.layout.eeprom.start;
+        if self.layout.word_addressed {
+            self.get_words(MemoryRegion::Eeprom)
+                .into_iter()
+                .map(|(address, word)| (address - start / 2, word as u8))
+                .collect()
+        } else {
+            self.get_image(MemoryRegion::Eeprom)
+                .into_iter()
+                .map(|(address, b)| (address - start, b))
+                .collect()
+        }
+    }
+
+    /// Returns the number of program memory words, as in `ROM used:`
+    pub fn get_rom_used(&self) -> usize {
+        self.get_words(MemoryRegion::Program).len()
+    }
+
+    /// Returns the errors of the file itself and where it disagrees with the other files of the
+    /// same build. An outdated .hex file has another CRC than the .sym file
+    pub fn verify(
+        &self,
+        symbol_map: Option<&SymbolMap>,
+        statistics: Option<&ProjectStatistics>,
+    ) -> Vec<String> {
+        let mut out = self.errors.clone();
+        if let (Some(expected), Some(actual)) = (symbol_map.and_then(|m| m.hex_crc), self.crc) {
+            if expected != actual {
+                out.push(format!(
+                    "CRC is {:04X}, but the .sym file of the build reports {:04X}. The .hex file \
+                     is outdated",
+                    actual, expected
+                ));
+            }
+        }
+        if let Some(expected) = statistics.and_then(|s| s.rom) {
+            let actual = self.get_rom_used();
+            if actual != expected.used as usize {
+                out.push(format!(
+                    "Contains {} program words, but the .STA file of the build reports {}",
+                    actual, expected.used
+                ));
+            }
+        }
+        out
+    }
+
+    /// Returns the contents in a human readable form
+    pub fn get_summary(&self) -> String {
+        fn format_words(words: &BTreeMap<u32, u16>) -> String {
+            if words.is_empty() {
+                return "none".to_owned();
+            }
+            words
+                .iter()
+                .map(|(address, word)| format!("{:04X}={:04X}", address, word))
+                .collect::<Vec<_>>()
+                .join(" ")
+        }
+
+        let mut out = vec![];
+        let name = self
+            .path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let crc = self.crc.map(|crc| format!(", CRC {:04X}", crc));
+        out.push(format!(
+            "{}: {}{}",
+            name,
+            self.device.as_deref().unwrap_or("Unknown device"),
+            crc.unwrap_or_default()
+        ));
+
+        let program = self.get_words(MemoryRegion::Program);
+        match (program.keys().next(), program.keys().last()) {
+            (Some(first), Some(last)) => out.push(format!(
+                "Program: {} words ({:04X}-{:04X})",
+                self.get_rom_used(),
+                first,
+                last
+            )),
+            _ => out.push("Program: empty".to_owned()),
+        }
+        let id_locations = self.get_words(MemoryRegion::IdLocations);
+        if !id_locations.is_empty() {
+            out.push(format!("ID locations: {}", format_words(&id_locations)));
+        }
+        out.push(format!(
+            "Config: {}",
+            format_words(&self.get_words(MemoryRegion::Config))
+        ));
+        let eeprom = self.get_eeprom();
+        out.push(format!("EEPROM: {} bytes", eeprom.len()));
+        out.join("\n")
+    }
+}
+
+/// Runs `ls-ccsc hex <path>`. Prints the contents of the .hex file and checks it against the .sym
+/// and .STA files next to it. Returns the exit code
+pub fn run_hex_subcommand(arguments: &[String]) -> i32 {
+    let path = match arguments.first() {
+        Some(path) => Path::new(path),
+        None => {
+            eprintln!("Usage: ls-ccsc {} <file.hex>", HEX_SUBCOMMAND);
+            return 2;
+        }
+    };
+    let hex_file = match HexFile::load(path) {
+        Ok(hex_file) => hex_file,
+        Err(e) => {
+            eprintln!("{}", e.message);
+            return 2;
+        }
+    };
+
+    let directory = path.parent().unwrap_or_else(|| Path::new("."));
+    let find_output_file = |extension| compiler_output::find_output_file(directory, extension);
+    let symbol_map = find_output_file("sym").and_then(|p| SymbolMap::load(&p).ok());
+    let statistics = find_output_file("sta").and_then(|p| ProjectStatistics::load(&p).ok());
+
+    println!("{}", hex_file.get_summary());
+    let problems = hex_file.verify(symbol_map.as_ref(), statistics.as_ref());
+    for problem in &problems {
+        eprintln!("error: {}", problem);
+    }
+    if problems.is_empty() {
+        0
+    } else {
+        1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RAW: &str = ":1000000000308A001F28000023082207F8008A1108\n\
+                       :0200A0006300FB\n:02420000AB0011\n:04400E00E12FFF3F60\n:00000001FF\n\
+                       ;PIC16F883\n;CRC=34E4  CREATED=\"02-Jan-22 13:50\"\n";
+
+    #[test]
+    fn test_memory_images() {
+        let hex_file = HexFile::from_hex(RAW);
+
+        assert!(hex_file.errors.is_empty());
+        assert_eq!(hex_file.device.as_deref(), Some("PIC16F883"));
+        assert_eq!(hex_file.crc, Some(0x34E4));
+        assert_eq!(hex_file.get_rom_used(), 9);
+        assert_eq!(
+            hex_file.get_words(MemoryRegion::Program).get(&0x02),
+            Some(&0x281F)
+        );
+        let config = hex_file.get_words(MemoryRegion::Config);
+        assert_eq!(
+            config.into_iter().collect::<Vec<_>>(),
+            vec![(0x2007, 0x2FE1), (0x2008, 0x3FFF)]
+        );
+        assert_eq!(
+            hex_file.get_eeprom().into_iter().collect::<Vec<_>>(),
+            vec![(0, 0xAB)]
+        );
+    }
+
+    #[test]
+    fn test_invalid_records() {
+        let hex_file =
+            HexFile::from_hex(":1000000000308A001F28000023082207F8008A1109\n:0200A00063\n");
+
+        assert_eq!(
+            hex_file.errors,
+            vec![
+                "Line 1: Checksum is 09, expected 08".to_owned(),
+                "Line 2: Record announces 2 data bytes, but has 0".to_owned(),
+                "Missing end of file record. The file is truncated".to_owned(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extended_addresses() {
+        let hex_file = HexFile::from_hex(
+            ":020000021000EC\n:01000000AA55\n:02000004FFFFFC\n:02FFFF00BBCC79\n:00000001FF\n",
+        );
+
+        assert_eq!(
+            hex_file.errors,
+            vec!["Line 4: Data exceeds the 32-bit address space".to_owned()]
+        );
+        assert_eq!(
+            hex_file.memory.into_iter().collect::<Vec<_>>(),
+            vec![(0x10000, 0xAA), (0xFFFFFFFF, 0xBB)]
+        );
+    }
+
+    #[test]
+    fn test_verify() {
+        let hex_file = HexFile::from_hex(RAW);
+        let symbol_map = SymbolMap::from_sym("ROM Allocation:\n000004  add\n\nHex file CRC=1234\n");
+        let statistics = ProjectStatistics::from_sta("ROM used:   9/4096 (0%)\n");
+
+        assert!(hex_file.verify(None, Some(&statistics)).is_empty());
+        assert_eq!(
+            hex_file.verify(Some(&symbol_map), None),
+            vec![
+                "CRC is 34E4, but the .sym file of the build reports 1234. The .hex file is \
+                  outdated"
+                    .to_owned()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pic18_layout() {
+        let hex_file = HexFile::from_hex(":020000040030CA\n:0100000022DD\n:00000001FF\n");
+
+        assert!(hex_file.errors.is_empty());
+        assert_eq!(hex_file.layout, MemoryLayout::pic18());
+        assert_eq!(hex_file.layout.get_region(0x300000), MemoryRegion::Config);
+        assert_eq!(hex_file.get_rom_used(), 0);
+    }
+}
@@ -0,0 +1,329 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::request::Request;
+use tower_lsp::lsp_types::{Range, TextDocumentIdentifier};
+
+use crate::compiler_output;
+use crate::docs::text_document_type::TextDocumentTypeTrait;
+use crate::docs::TextDocumentType;
+use crate::symbols;
+
+type Docs = HashMap<PathBuf, TextDocumentType>;
+
+lazy_static! {
+    // `.................... 	return a + b;`
+    static ref SOURCE_LINE_MATCHER: Regex = Regex::new(r"^\.{20} ?(?P<text>.*)$").unwrap();
+    // `0009:  MOVLW  23` or `001E:  GOTO   04D (RETURN)`
+    static ref INSTRUCTION_MATCHER: Regex = Regex::new(
+        r"^(?P<address>[0-9A-Fa-f]{4,6}):\s+(?P<mnemonic>\S+)(?:\s+(?P<operands>.*?))?\s*$"
+    )
+    .unwrap();
+}
+
+/// Instructions shown on hover. Loops easily generate hundreds
+const MAX_HOVER_INSTRUCTIONS: usize = 24;
+
+/// An addressed instruction of the listing, e.g. `0009:  MOVLW  23`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Instruction {
+    pub address: u32,
+    pub mnemonic: String,
+    /// E.g. `23` or `04D (RETURN)`. Empty for instructions like `NOP`
+    pub operands: String,
+}
+
+impl Instruction {
+    /// Returns the instruction as written in the listing
+    pub fn get_text(&self) -> String {
+        format!(
+            "{:04X}:  {:<6} {}",
+            self.address, self.mnemonic, self.operands
+        )
+        .trim_end()
+        .to_owned()
+    }
+}
+
+/// A source line the compiler echoed into the listing, followed by the instructions it generated
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListingLine {
+    pub text: String,
+    pub instructions: Vec<Instruction>,
+}
+
+/// Instructions generated for a line of a source file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DisassembledLine {
+    /// Zero-based
+    pub line: u32,
+    pub source: String,
+    pub instructions: Vec<Instruction>,
+}
+
+/// Returns the instructions generated for a range of source lines
+pub enum DisassemblyRequest {}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DisassemblyParams {
+    pub text_document: TextDocumentIdentifier,
+    pub range: Range,
+}
+
+impl Request for DisassemblyRequest {
+    type Params = DisassemblyParams;
+    type Result = Vec<DisassembledLine>;
+    const METHOD: &'static str = "ccsc/disassembly";
+}
+
+/// The source lines interleaved with the instructions generated for them, parsed from the .lst
+/// file (e.g. `main.lst`)
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Listing {
+    pub path: PathBuf,
+    pub lines: Vec<ListingLine>,
+}
+
+impl Listing {
+    pub fn load(path: &Path) -> Result<Self> {
+        let raw = compiler_output::read_output_file(path)?;
+        let mut out = Listing::from_lst(&raw);
+        out.path = path.to_owned();
+        Ok(out)
+    }
+
+    /// Instructions in front of the first source line, e.g. the reset vector, are left out
+    pub fn from_lst(raw: &str) -> Self {
+        let mut out = Listing::default();
+        for line in raw.lines() {
+            if let Some(captures) = SOURCE_LINE_MATCHER.captures(line) {
+                out.lines.push(ListingLine {
+                    text: captures["text"].to_owned(),
+                    instructions: vec![],
+                });
+                continue;
+            }
+            let (captures, curr) = match (INSTRUCTION_MATCHER.captures(line), out.lines.last_mut())
+            {
+                (Some(captures), Some(curr)) => (captures, curr),
+                _ => continue,
+            };
+            if let Ok(address) = u32::from_str_radix(&captures["address"], 16) {
+                curr.instructions.push(Instruction {
+                    address,
+                    mnemonic: captures["mnemonic"].to_owned(),
+                    operands: captures
+                        .name("operands")
+                        .map(|o| o.as_str().to_owned())
+                        .unwrap_or_default(),
+                });
+            }
+        }
+        out
+    }
+
+    /// Returns the source file the listing was generated for, e.g. `main.c` for `main.lst`
+    pub fn get_main_path(&self) -> PathBuf {
+        self.path.with_extension("c")
+    }
+
+    /// Returns the instructions generated for each line of `path`. The listing only contains the
+    /// text of the source lines, so they are matched against the documents in the order the
+    /// compiler read them, descending into `#include`s. Lines which changed since the last build
+    /// are not matched
+    pub fn get_line_instructions(&self, docs: &Docs, path: &Path) -> BTreeMap<u32, &ListingLine> {
+        struct Frame<'a> {
+            path: &'a Path,
+            lines: Vec<&'a str>,
+            cursor: usize,
+        }
+        fn create_frame<'a>(docs: &'a Docs, path: &'a Path) -> Option<Frame<'a>> {
+            let doc = symbols::get_source_doc(docs, path)?;
+            Some(Frame {
+                path,
+                lines: doc.get_source().get_raw().lines().collect(),
+                cursor: 0,
+            })
+        }
+        fn find_line(frame: &Frame, text: &str) -> Option<usize> {
+            frame.lines[frame.cursor.min(frame.lines.len())..]
+                .iter()
+                .position(|l| l.trim_end() == text)
+                .map(|i| frame.cursor + i)
+        }
+
+        let main_path = self.get_main_path();
+        let mut out = BTreeMap::new();
+        let mut stack = match create_frame(docs, &main_path) {
+            Some(frame) => vec![frame],
+            None => return out,
+        };
+        for line in &self.lines {
+            let text = line.text.trim_end();
+            if text.trim_start().is_empty() {
+                continue;
+            }
+            let found = (0..stack.len())
+                .rev()
+                .find_map(|depth| find_line(&stack[depth], text).map(|row| (depth, row)));
+            let (depth, row) = match found {
+                Some(found) => found,
+                None => continue,
+            };
+            stack.truncate(depth + 1);
+            let frame = &mut stack[depth];
+            frame.cursor = row + 1;
+            if frame.path == path {
+                out.insert(row as u32, line);
+            }
+
+            let included = symbols::get_source_doc(docs, frame.path)
+                .and_then(|doc| {
+                    doc.get_includes()
+                        .iter()
+                        .find(|i| i.range.start.line as usize == row)
+                })
+                .and_then(|include| include.resolved_path.as_deref())
+                .and_then(|included| create_frame(docs, included));
+            stack.extend(included);
+        }
+        out
+    }
+
+    /// Returns the lines of `path` from `start` to `end` which generated instructions
+    pub fn get_disassembly(
+        &self,
+        docs: &Docs,
+        path: &Path,
+        start: u32,
+        end: u32,
+    ) -> Vec<DisassembledLine> {
+        self.get_line_instructions(docs, path)
+            .range(start..=end)
+            .filter(|(_, line)| !line.instructions.is_empty())
+            .map(|(row, line)| DisassembledLine {
+                line: *row,
+                source: line.text.clone(),
+                instructions: line.instructions.clone(),
+            })
+            .collect()
+    }
+
+    /// Returns the instructions of `lines` in Markdown
+    pub fn get_hover_text(&self, lines: &[DisassembledLine]) -> Option<String> {
+        let instructions = lines
+            .iter()
+            .flat_map(|l| l.instructions.iter())
+            .collect::<Vec<_>>();
+        if instructions.is_empty() {
+            return None;
+        }
+
+        let words = instructions.len();
+        let mut code = instructions
+            .iter()
+            .take(MAX_HOVER_INSTRUCTIONS)
+            .map(|i| i.get_text())
+            .collect::<Vec<_>>();
+        if words > MAX_HOVER_INSTRUCTIONS {
+            code.push(format!("; {} more", words - MAX_HOVER_INSTRUCTIONS));
+        }
+        let file_name = self
+            .path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Some(format!(
+            "**{} {}** generated\n\n```asm\n{}\n```\n\nFrom `{}`",
+            words,
+            if words == 1 { "word" } else { "words" },
+            code.join("\n"),
+            file_name
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::create_docs;
+
+    const RAW: &str =
+        "               ROM used:   81 words (2%)\n\n*\n0000:  MOVLW  00\n0003:  NOP\n\
+                       .................... #include \"add.c\"\n\
+                       .................... int add(int a, int b) {\n\
+                       .................... \treturn a + b;\n*\n0004:  MOVF   23,W\n\
+                       0005:  ADDWF  22,W\n0008:  GOTO   039 (RETURN)\n\
+                       .................... }\n.................... \n\
+                       .................... void main() {\n*\n001F:  MOVF   03,W\n\
+                       .................... \toutput_low(PIN_C0);\n003B:  BCF    20.0\n\
+                       .................... }\n0051:  SLEEP\n";
+
+    #[test]
+    fn test_listing() {
+        let listing = Listing::from_lst(RAW);
+
+        let lines = listing
+            .lines
+            .iter()
+            .map(|l| (l.text.as_str(), l.instructions.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            vec![
+                ("#include \"add.c\"", 0),
+                ("int add(int a, int b) {", 0),
+                ("\treturn a + b;", 3),
+                ("}", 0),
+                ("", 0),
+                ("void main() {", 1),
+                ("\toutput_low(PIN_C0);", 1),
+                ("}", 1),
+            ]
+        );
+        assert_eq!(
+            listing.lines[2].instructions[2].get_text(),
+            "0008:  GOTO   039 (RETURN)"
+        );
+        assert_eq!(
+            listing.lines[5].instructions[0].get_text(),
+            "001F:  MOVF   03,W"
+        );
+    }
+
+    #[test]
+    fn test_line_instructions() {
+        let docs = create_docs(&[
+            (
+                "/project/main.c",
+                "#include \"add.c\"\n\nvoid main() {\n\toutput_low(PIN_C0);\n}\n",
+            ),
+            (
+                "/project/add.c",
+                "int add(int a, int b) {\n\treturn a + b;\n}\n",
+            ),
+        ]);
+        let mut listing = Listing::from_lst(RAW);
+        listing.path = PathBuf::from("/project/main.lst");
+
+        let main = listing.get_line_instructions(&docs, Path::new("/project/main.c"));
+        let main = main
+            .iter()
+            .map(|(row, l)| (*row, l.instructions.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(main, vec![(0, 0), (2, 1), (3, 1), (4, 1)]);
+
+        let add = listing.get_disassembly(&docs, Path::new("/project/add.c"), 0, 2);
+        assert_eq!(add.len(), 1);
+        assert_eq!(add[0].line, 1);
+        assert!(listing
+            .get_hover_text(&add)
+            .unwrap()
+            .starts_with("**3 words** generated\n\n```asm\n0004:  MOVF   23,W\n"));
+    }
+}
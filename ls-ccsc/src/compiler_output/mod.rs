@@ -0,0 +1,13 @@
+pub use crate::compiler_output::call_tree::*;
+pub use crate::compiler_output::hex_file::*;
+pub use crate::compiler_output::listing::*;
+pub use crate::compiler_output::output_files::*;
+pub use crate::compiler_output::statistics::*;
+pub use crate::compiler_output::symbol_map::*;
+
+pub mod call_tree;
+pub mod hex_file;
+pub mod listing;
+pub mod output_files;
+pub mod statistics;
+pub mod symbol_map;
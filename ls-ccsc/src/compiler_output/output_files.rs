@@ -0,0 +1,34 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use tower_lsp::jsonrpc::Result;
+
+use crate::utils;
+
+/// Returns the most recently written file with `extension` (e.g. `sym`) inside `directory`.
+/// Extensions are compared case-insensitively, the compiler writes both `main.sym` and `main.STA`
+pub fn find_output_file(directory: &Path, extension: &str) -> Option<PathBuf> {
+    directory
+        .read_dir()
+        .ok()?
+        .filter_map(|f| f.ok())
+        .map(|f| f.path())
+        .filter(|f| f.is_file())
+        .filter(|f| is_output_file(f, extension))
+        .max_by_key(|f| f.metadata().and_then(|m| m.modified()).ok())
+}
+
+/// Returns true, if `path` has `extension`, ignoring case
+pub fn is_output_file(path: &Path, extension: &str) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case(extension))
+}
+
+/// Reads a file written by the compiler. They are not necessarily UTF-8
+pub fn read_output_file(path: &Path) -> Result<String> {
+    let raw = fs::read(path).map_err(|e| {
+        utils::create_server_error(11, format!("Failed to read '{}': {}", path.display(), e))
+    })?;
+    Ok(String::from_utf8_lossy(&raw).into_owned())
+}
@@ -0,0 +1,388 @@
+use std::path::{Path, PathBuf};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Serialize;
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::{CodeLens, Command};
+
+use crate::compiler_output;
+use crate::docs::text_document_type::TextDocumentTypeTrait;
+use crate::docs::TextDocument;
+use crate::symbols::SymbolDefinitionKind;
+
+lazy_static! {
+    // `81/4096 (2%)`, `9/256 (4%) worst case`
+    static ref USAGE_MATCHER: Regex =
+        Regex::new(r"(?P<used>\d+)/(?P<total>\d+)\s+\(\d+%\)\s*(?P<note>.*)$").unwrap();
+    static ref STACK_MATCHER: Regex =
+        Regex::new(r"^Stack (?P<kind>used|size):\s+(?P<value>\d+)").unwrap();
+    // `   17     5  83  main.c`
+    static ref FILE_MATCHER: Regex =
+        Regex::new(r"^\s*(?P<lines>\d+)\s+(?P<statements>\d+)\s+(?P<percent>\d+)\s+(?P<path>\S.*?)\s*$")
+            .unwrap();
+    // `    Cyclomatic Complexity   1`
+    static ref METRIC_MATCHER: Regex =
+        Regex::new(r"^\s+(?P<name>\S.*?)\s{2,}(?P<value>\S+)\s*$").unwrap();
+    // `00004-007FF    77  1967`
+    static ref SEGMENT_MATCHER: Regex = Regex::new(
+        r"^\s*(?P<start>[0-9A-Fa-f]+)-(?P<end>[0-9A-Fa-f]+)\s+(?P<used>\d+)\s+(?P<free>\d+)"
+    )
+    .unwrap();
+}
+
+/// Command returning the statistics of the last build as JSON
+pub const PROJECT_SUMMARY_COMMAND: &str = "ccsc.projectSummary";
+
+/// E.g. `ROM used: 81/4096 (2%)`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct MemoryUsage {
+    pub used: u32,
+    pub total: u32,
+}
+
+/// A row of the `Lines Stmts % Files` table
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileStatistics {
+    pub path: String,
+    pub lines: u32,
+    pub statements: u32,
+    /// Share of the statements of the project
+    pub percent: u32,
+}
+
+/// A row of the `Page ROM % RAM Vol Diff Functions:` table
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FunctionStatistics {
+    /// E.g. `MAIN` or `@delay_ms1`
+    pub name: String,
+    /// Program memory page. `None` for inlined functions
+    pub page: Option<u32>,
+    /// Program words
+    pub rom: Option<u32>,
+    pub rom_percent: Option<u32>,
+    /// Bytes
+    pub ram: u32,
+    /// Halstead volume
+    pub volume: Option<u32>,
+    /// Halstead difficulty
+    pub difficulty: Option<f64>,
+}
+
+impl FunctionStatistics {
+    /// Returns e.g. `ROM 5 words (6%) | RAM 2 bytes | Difficulty 3.0`
+    pub fn get_summary(&self) -> String {
+        let mut out = vec![];
+        match (self.rom, self.rom_percent) {
+            (Some(rom), Some(percent)) => out.push(format!("ROM {} words ({}%)", rom, percent)),
+            (Some(rom), None) => out.push(format!("ROM {} words", rom)),
+            _ if self.page.is_none() => out.push("Inline".to_owned()),
+            _ => {}
+        }
+        out.push(format!("RAM {} bytes", self.ram));
+        if let Some(difficulty) = self.difficulty {
+            out.push(format!("Difficulty {:.1}", difficulty));
+        }
+        out.join(" | ")
+    }
+}
+
+/// A line of `Program metrics:`, e.g. `Cyclomatic Complexity 1`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ProgramMetric {
+    pub name: String,
+    pub value: String,
+}
+
+/// A row of the `Segment Used Free` table
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct SegmentStatistics {
+    pub start: u32,
+    pub end: u32,
+    pub used: u32,
+    pub free: u32,
+}
+
+/// Memory usage and metrics of the last build, parsed from the .STA file (e.g. `main.STA`)
+#[derive(Debug, Clone, PartialEq, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectStatistics {
+    pub path: PathBuf,
+    pub rom: Option<MemoryUsage>,
+    /// RAM used at `main()` level
+    pub ram: Option<MemoryUsage>,
+    pub ram_worst_case: Option<MemoryUsage>,
+    pub stack_used: Option<u32>,
+    pub stack_size: Option<u32>,
+    pub files: Vec<FileStatistics>,
+    pub functions: Vec<FunctionStatistics>,
+    pub metrics: Vec<ProgramMetric>,
+    pub segments: Vec<SegmentStatistics>,
+}
+
+impl ProjectStatistics {
+    pub fn load(path: &Path) -> Result<Self> {
+        let raw = compiler_output::read_output_file(path)?;
+        let mut out = ProjectStatistics::from_sta(&raw);
+        out.path = path.to_owned();
+        Ok(out)
+    }
+
+    pub fn from_sta(raw: &str) -> Self {
+        #[derive(Clone, Copy, PartialEq, Eq)]
+        enum Section {
+            Rom,
+            Ram,
+            Files,
+            Functions,
+            Metrics,
+            Segments,
+        }
+        fn parse_function(line: &str) -> Option<FunctionStatistics> {
+            let mut tokens = line.split_whitespace().collect::<Vec<_>>();
+            let name = tokens.pop()?.to_owned();
+            // ` Inline         0             @cinit1`
+            if tokens.first() == Some(&"Inline") {
+                return Some(FunctionStatistics {
+                    name,
+                    page: None,
+                    rom: None,
+                    rom_percent: None,
+                    ram: tokens.get(1)?.parse().ok()?,
+                    volume: None,
+                    difficulty: None,
+                });
+            }
+            Some(FunctionStatistics {
+                name,
+                page: Some(tokens.first()?.parse().ok()?),
+                rom: Some(tokens.get(1)?.parse().ok()?),
+                rom_percent: tokens.get(2).and_then(|t| t.parse().ok()),
+                ram: tokens.get(3)?.parse().ok()?,
+                volume: tokens.get(4).and_then(|t| t.parse().ok()),
+                difficulty: tokens.get(5).and_then(|t| t.parse().ok()),
+            })
+        }
+
+        let mut out = ProjectStatistics::default();
+        let mut section = None;
+        for line in raw.lines() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() {
+                if section != Some(Section::Rom) && section != Some(Section::Ram) {
+                    section = None;
+                }
+                continue;
+            }
+            if trimmed.starts_with("---") {
+                continue;
+            }
+
+            if trimmed.starts_with("ROM used:") {
+                section = Some(Section::Rom);
+            } else if trimmed.starts_with("RAM used:") {
+                section = Some(Section::Ram);
+            } else if trimmed.starts_with("Lines Stmts") {
+                section = Some(Section::Files);
+                continue;
+            } else if trimmed.starts_with("Page ROM") {
+                section = Some(Section::Functions);
+                continue;
+            } else if trimmed.starts_with("Program metrics:") {
+                section = Some(Section::Metrics);
+                continue;
+            } else if trimmed.starts_with("Segment") {
+                section = Some(Section::Segments);
+                continue;
+            }
+
+            if let Some(captures) = STACK_MATCHER.captures(line) {
+                let value = captures["value"].parse().ok();
+                match &captures["kind"] {
+                    "used" => out.stack_used = value,
+                    _ => out.stack_size = value,
+                }
+                section = None;
+                continue;
+            }
+
+            match section {
+                Some(Section::Rom) | Some(Section::Ram) => {
+                    let captures = match USAGE_MATCHER.captures(line) {
+                        Some(captures) => captures,
+                        None => continue,
+                    };
+                    let usage = match (captures["used"].parse(), captures["total"].parse()) {
+                        (Ok(used), Ok(total)) => MemoryUsage { used, total },
+                        _ => continue,
+                    };
+                    if section == Some(Section::Rom) {
+                        out.rom.get_or_insert(usage);
+                    } else if captures["note"].contains("worst case") {
+                        out.ram_worst_case = Some(usage);
+                    } else {
+                        out.ram = Some(usage);
+                    }
+                }
+                Some(Section::Files) => {
+                    if let Some(captures) = FILE_MATCHER.captures(line) {
+                        out.files.push(FileStatistics {
+                            path: captures["path"].to_owned(),
+                            lines: captures["lines"].parse().unwrap_or_default(),
+                            statements: captures["statements"].parse().unwrap_or_default(),
+                            percent: captures["percent"].parse().unwrap_or_default(),
+                        });
+                    }
+                }
+                Some(Section::Functions) => out.functions.extend(parse_function(line)),
+                Some(Section::Metrics) => {
+                    if let Some(captures) = METRIC_MATCHER.captures(line) {
+                        out.metrics.push(ProgramMetric {
+                            name: captures["name"].to_owned(),
+                            value: captures["value"].to_owned(),
+                        });
+                    }
+                }
+                Some(Section::Segments) => {
+                    if let Some(captures) = SEGMENT_MATCHER.captures(line) {
+                        let parse_hex = |name| u32::from_str_radix(&captures[name], 16).ok();
+                        if let (Some(start), Some(end)) = (parse_hex("start"), parse_hex("end")) {
+                            out.segments.push(SegmentStatistics {
+                                start,
+                                end,
+                                used: captures["used"].parse().unwrap_or_default(),
+                                free: captures["free"].parse().unwrap_or_default(),
+                            });
+                        }
+                    }
+                }
+                None => {}
+            }
+        }
+        out
+    }
+
+    /// Function names are compared case-insensitively, the compiler writes `MAIN` for `main`
+    pub fn get_function(&self, name: &str) -> Option<&FunctionStatistics> {
+        self.functions
+            .iter()
+            .find(|f| f.name.eq_ignore_ascii_case(name))
+    }
+}
+
+/// Returns a code lens with the statistics of every function defined in `doc`
+pub fn get_code_lenses(doc: &TextDocument, statistics: &ProjectStatistics) -> Vec<CodeLens> {
+    doc.get_symbol_index()
+        .definitions
+        .iter()
+        .filter(|def| def.kind == SymbolDefinitionKind::Function)
+        .filter_map(|def| {
+            let function = statistics.get_function(&def.name)?;
+            Some(CodeLens {
+                range: def.selection_range,
+                command: Some(Command {
+                    title: function.get_summary(),
+                    // Only informational
+                    command: String::new(),
+                    arguments: None,
+                }),
+                data: None,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RAW: &str = "ROM used:   81/4096 (2%)\n            81/4096 (2%) including unused fragments\n\n\
+                       \x20           0.1 Average locations per line\n\n\
+                       RAM used:   8/256 (3%) at main() level\n            9/256 (4%) worst case\n\n\
+                       Stack used: 0 worst case (0 in main + 0 for interrupts)\nStack size: 8\n\n\
+                       Lines Stmts  %   Files\n----- ----- ---  -----\n   17     5  83  main.c\n\
+                       \x20   6     1  17  sth\\add.c\n----- -----\n  818     6 Total\n\n\
+                       Page ROM  %  RAM    Vol Diff  Functions:\n---- --- --- ---    --- ----  ----------\n\
+                       0     22  27    1             @delay_ms1\n0      5   6    2     43  3.0  add\n\
+                       \x20Inline         0             @cinit1\n\n\
+                       Program metrics:\n    Functions               2\n    Volume (V)              202\n\
+                       \x20   Cyclomatic Complexity   1\n\n\
+                       \x20Segment     Used  Free\n-----------  ----  ----\n00000-00003     4  0  \n\
+                       00004-007FF    77  1967  \n";
+
+    #[test]
+    fn test_memory_usage() {
+        let statistics = ProjectStatistics::from_sta(RAW);
+
+        assert_eq!(
+            statistics.rom,
+            Some(MemoryUsage {
+                used: 81,
+                total: 4096
+            })
+        );
+        assert_eq!(
+            statistics.ram,
+            Some(MemoryUsage {
+                used: 8,
+                total: 256
+            })
+        );
+        assert_eq!(
+            statistics.ram_worst_case,
+            Some(MemoryUsage {
+                used: 9,
+                total: 256
+            })
+        );
+        assert_eq!(statistics.stack_used, Some(0));
+        assert_eq!(statistics.stack_size, Some(8));
+        assert_eq!(statistics.segments.len(), 2);
+        assert_eq!(statistics.segments[1].end, 0x7FF);
+    }
+
+    #[test]
+    fn test_tables() {
+        let statistics = ProjectStatistics::from_sta(RAW);
+
+        let files = statistics
+            .files
+            .iter()
+            .map(|f| (f.path.as_str(), f.statements))
+            .collect::<Vec<_>>();
+        assert_eq!(files, vec![("main.c", 5), ("sth\\add.c", 1)]);
+
+        let summaries = statistics
+            .functions
+            .iter()
+            .map(|f| (f.name.as_str(), f.get_summary()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summaries,
+            vec![
+                ("@delay_ms1", "ROM 22 words (27%) | RAM 1 bytes".to_owned()),
+                (
+                    "add",
+                    "ROM 5 words (6%) | RAM 2 bytes | Difficulty 3.0".to_owned()
+                ),
+                ("@cinit1", "Inline | RAM 0 bytes".to_owned()),
+            ]
+        );
+
+        let metrics = statistics
+            .metrics
+            .iter()
+            .map(|m| (m.name.as_str(), m.value.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            metrics,
+            vec![
+                ("Functions", "2"),
+                ("Volume (V)", "202"),
+                ("Cyclomatic Complexity", "1")
+            ]
+        );
+    }
+}
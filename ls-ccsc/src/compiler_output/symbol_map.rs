@@ -0,0 +1,269 @@
+use std::path::{Path, PathBuf};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use tower_lsp::jsonrpc::Result;
+
+use crate::compiler_output;
+
+lazy_static! {
+    // `022     add.a`, `107.6   C1OUT` or `004-005 @WRITE_PROGRAM_MEMORY8.P1`
+    static ref RAM_MATCHER: Regex = Regex::new(
+        r"^(?P<address>[0-9A-Fa-f]+)(?:\.(?P<bit>[0-7])|-(?P<end>[0-9A-Fa-f]+))?\s+(?P<name>\S+)\s*$"
+    )
+    .unwrap();
+    // `000004  add`
+    static ref ROM_MATCHER: Regex =
+        Regex::new(r"^(?P<address>[0-9A-Fa-f]+)\s+(?P<name>\S+)\s*$").unwrap();
+}
+
+/// Initialization code the compiler places inside of `main`, e.g. `@cinit1`
+const INIT_CODE_PREFIX: &str = "@cinit";
+
+/// A RAM location, e.g. `022 add.a`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RamSymbol {
+    /// The function the variable is local to, e.g. `add` of `add.a`
+    pub scope: Option<String>,
+    pub name: String,
+    pub address: u32,
+    /// Number of bytes, more than one for ranges like `004-005`
+    pub size: u32,
+    /// Set for bits of registers, e.g. `107.6 C1OUT`
+    pub bit: Option<u8>,
+}
+
+impl RamSymbol {
+    /// Returns the name as written in the .sym file, e.g. `add.a`
+    pub fn get_full_name(&self) -> String {
+        match &self.scope {
+            Some(scope) => format!("{}.{}", scope, self.name),
+            None => self.name.clone(),
+        }
+    }
+}
+
+/// An entry of the `ROM Allocation:` table, e.g. `000004 add`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RomSymbol {
+    pub name: String,
+    pub address: u32,
+    /// Number of program words up to the next function. Unknown for the last one
+    pub size: Option<u32>,
+}
+
+/// RAM and ROM addresses the compiler assigned, parsed from the .sym file (e.g. `main.sym`)
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SymbolMap {
+    pub path: PathBuf,
+    pub ram: Vec<RamSymbol>,
+    pub rom: Vec<RomSymbol>,
+    /// Checksum of the .hex file the build wrote, e.g. `Hex file CRC=34E4`
+    pub hex_crc: Option<u16>,
+}
+
+impl SymbolMap {
+    pub fn load(path: &Path) -> Result<Self> {
+        let raw = compiler_output::read_output_file(path)?;
+        let mut out = SymbolMap::from_sym(&raw);
+        out.path = path.to_owned();
+        Ok(out)
+    }
+
+    pub fn from_sym(raw: &str) -> Self {
+        fn parse_address(text: &str) -> Option<u32> {
+            u32::from_str_radix(text, 16).ok()
+        }
+
+        let mut out = SymbolMap {
+            hex_crc: raw.lines().find_map(|line| {
+                let crc = line.trim().strip_prefix("Hex file CRC=")?;
+                u16::from_str_radix(crc, 16).ok()
+            }),
+            ..Default::default()
+        };
+        let mut lines = raw.lines();
+        for line in lines.by_ref() {
+            if line.trim() == "ROM Allocation:" {
+                break;
+            }
+            let captures = match RAM_MATCHER.captures(line) {
+                Some(captures) => captures,
+                None => continue,
+            };
+            let address = match parse_address(&captures["address"]) {
+                Some(address) => address,
+                None => continue,
+            };
+            let end = captures.name("end").and_then(|e| parse_address(e.as_str()));
+            let (scope, name) = match captures["name"].split_once('.') {
+                Some((scope, name)) => (Some(scope.to_owned()), name.to_owned()),
+                None => (None, captures["name"].to_owned()),
+            };
+            out.ram.push(RamSymbol {
+                scope,
+                name,
+                address,
+                size: end.map_or(1, |end| end.saturating_sub(address) + 1),
+                bit: captures.name("bit").and_then(|b| b.as_str().parse().ok()),
+            });
+        }
+
+        for line in lines {
+            // The table ends with an empty line
+            if line.trim().is_empty() && !out.rom.is_empty() {
+                break;
+            }
+            if let Some(captures) = ROM_MATCHER.captures(line) {
+                if let Some(address) = parse_address(&captures["address"]) {
+                    out.rom.push(RomSymbol {
+                        name: captures["name"].to_owned(),
+                        address,
+                        size: None,
+                    });
+                }
+            }
+        }
+
+        let boundaries = out
+            .rom
+            .iter()
+            .filter(|s| !s.name.starts_with(INIT_CODE_PREFIX))
+            .map(|s| s.address)
+            .collect::<Vec<_>>();
+        for symbol in out
+            .rom
+            .iter_mut()
+            .filter(|s| !s.name.starts_with(INIT_CODE_PREFIX))
+        {
+            symbol.size = boundaries
+                .iter()
+                .filter(|a| **a > symbol.address)
+                .min()
+                .map(|next| next - symbol.address);
+        }
+        out
+    }
+
+    /// Returns the variable `name`, local to the function `scope` if given. Names are compared
+    /// case-insensitively, the compiler writes `MAIN.c` for `c` of `main`
+    pub fn get_ram_symbol(&self, scope: Option<&str>, name: &str) -> Option<&RamSymbol> {
+        self.ram.iter().find(|s| {
+            s.name.eq_ignore_ascii_case(name)
+                && match (&s.scope, scope) {
+                    (Some(a), Some(b)) => a.eq_ignore_ascii_case(b),
+                    (a, b) => a.is_none() && b.is_none(),
+                }
+        })
+    }
+
+    pub fn get_rom_symbol(&self, name: &str) -> Option<&RomSymbol> {
+        self.rom.iter().find(|s| s.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Returns the address of the variable or function `name` in Markdown. Variables local to
+    /// the function `scope` are preferred over global ones
+    pub fn get_hover_text(&self, scope: Option<&str>, name: &str) -> Option<String> {
+        let details = if let Some(symbol) = scope
+            .and_then(|scope| self.get_ram_symbol(Some(scope), name))
+            .or_else(|| self.get_ram_symbol(None, name))
+        {
+            let location = match symbol.bit {
+                Some(bit) => format!("RAM `0x{:03X}`, bit {}", symbol.address, bit),
+                None if symbol.size == 1 => format!("RAM `0x{:03X}`, 1 byte", symbol.address),
+                None => format!("RAM `0x{:03X}`, {} bytes", symbol.address, symbol.size),
+            };
+            (symbol.get_full_name(), location)
+        } else if let Some(symbol) = self.get_rom_symbol(name) {
+            let location = match symbol.size {
+                Some(size) => format!("ROM `0x{:04X}`, {} words", symbol.address, size),
+                None => format!("ROM `0x{:04X}`", symbol.address),
+            };
+            (symbol.name.clone(), location)
+        } else {
+            return None;
+        };
+
+        let file_name = self
+            .path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Some(format!(
+            "**{}**\n\n{}\n\nFrom `{}`",
+            details.0, details.1, file_name
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RAW: &str = "004-005 @WRITE_PROGRAM_MEMORY8.P1\n015     CCP_1\n021     MAIN.c\n\
+                       022     add.a\n022     MAIN.@SCRATCH1\n023     add.b\n077     @SCRATCH\n\
+                       107.6   C1OUT\n\nROM Allocation:\n000004  add\n000009  @delay_ms1\n\
+                       00001F  MAIN\n00001F  @cinit1\n000033  @cinit2\n\nProject Directory:\n\
+                       \x20   C:\\project\\\n\nHex file CRC=34E4\n";
+
+    #[test]
+    fn test_ram_symbols() {
+        let map = SymbolMap::from_sym(RAW);
+
+        assert_eq!(map.ram.len(), 8);
+        assert_eq!(
+            map.ram[0],
+            RamSymbol {
+                scope: Some("@WRITE_PROGRAM_MEMORY8".to_owned()),
+                name: "P1".to_owned(),
+                address: 0x004,
+                size: 2,
+                bit: None,
+            }
+        );
+        assert_eq!(
+            map.get_ram_symbol(Some("main"), "c").unwrap().address,
+            0x021
+        );
+        assert_eq!(map.get_ram_symbol(None, "c1out").unwrap().bit, Some(6));
+        assert!(map.get_ram_symbol(None, "c").is_none());
+        assert_eq!(map.hex_crc, Some(0x34E4));
+    }
+
+    #[test]
+    fn test_rom_symbols() {
+        let map = SymbolMap::from_sym(RAW);
+
+        let sizes = map
+            .rom
+            .iter()
+            .map(|s| (s.name.as_str(), s.address, s.size))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sizes,
+            vec![
+                ("add", 0x04, Some(5)),
+                ("@delay_ms1", 0x09, Some(22)),
+                ("MAIN", 0x1F, None),
+                ("@cinit1", 0x1F, None),
+                ("@cinit2", 0x33, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hover_text() {
+        let mut map = SymbolMap::from_sym(RAW);
+        map.path = PathBuf::from("/project/main.sym");
+
+        assert_eq!(
+            map.get_hover_text(Some("add"), "a").unwrap(),
+            "**add.a**\n\nRAM `0x022`, 1 byte\n\nFrom `main.sym`"
+        );
+        assert_eq!(
+            map.get_hover_text(Some("main"), "add").unwrap(),
+            "**add**\n\nROM `0x0004`, 5 words\n\nFrom `main.sym`"
+        );
+        assert!(map.get_hover_text(Some("main"), "b").is_none());
+    }
+}
@@ -0,0 +1,401 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::{
+    CompletionItem, CompletionItemKind, Documentation, MarkupContent, MarkupKind,
+};
+use tree_sitter::Point;
+
+use crate::asm::{self, PicInstruction};
+use crate::builtins::BuiltinFunction;
+use crate::completion;
+use crate::devices::{self, DeviceDatabase};
+use crate::directives::{self, DirectiveKind};
+use crate::docs::text_document_type::TextDocumentTypeTrait;
+use crate::docs::{TextDocument, TextDocumentType};
+use crate::symbols::{self, SymbolDefinition, SymbolDefinitionKind};
+use crate::utils;
+
+pub const C_KEYWORDS: &[&str] = &[
+    "auto",
+    "break",
+    "case",
+    "const",
+    "continue",
+    "default",
+    "do",
+    "else",
+    "enum",
+    "extern",
+    "for",
+    "goto",
+    "if",
+    "inline",
+    "register",
+    "return",
+    "sizeof",
+    "static",
+    "struct",
+    "switch",
+    "typedef",
+    "union",
+    "volatile",
+    "while",
+    "rom",
+    "_readonly",
+];
+
+pub const CCS_PRIMITIVE_TYPES: &[&str] = &[
+    "void", "char", "short", "int", "long", "float", "double", "signed", "unsigned", "int1",
+    "int8", "int16", "int32", "int48", "int64", "float32", "float48", "float64", "boolean",
+];
+
+/// Kinds of nodes inside which no completion is offered
+const NON_CODE_NODE_KINDS: &[&str] = &[
+    "comment",
+    "string_literal",
+    "char_literal",
+    "system_lib_string",
+];
+
+/// Returns local symbols visible at `point`, global symbols of the project, built-in functions,
+/// primitive types and keywords, in this order. Mnemonics are offered inside `#asm` blocks,
+/// fuses of the device inside `#fuses` and pins of the device inside pin arguments instead
+pub fn get_completion_items(
+    docs: &HashMap<PathBuf, TextDocumentType>,
+    path: &Path,
+    point: Point,
+    db: Option<&DeviceDatabase>,
+) -> Result<Vec<CompletionItem>> {
+    fn get_item_kind(kind: SymbolDefinitionKind) -> CompletionItemKind {
+        match kind {
+            SymbolDefinitionKind::Function | SymbolDefinitionKind::FunctionPrototype => {
+                CompletionItemKind::Function
+            }
+            SymbolDefinitionKind::Variable | SymbolDefinitionKind::Parameter => {
+                CompletionItemKind::Variable
+            }
+            SymbolDefinitionKind::Macro | SymbolDefinitionKind::FunctionMacro => {
+                CompletionItemKind::Constant
+            }
+            SymbolDefinitionKind::Type => CompletionItemKind::Struct,
+            SymbolDefinitionKind::EnumConstant => CompletionItemKind::EnumMember,
+        }
+    }
+    /// Returns the line declaring the symbol, e.g. `int add(int a, int b)`
+    fn get_definition_detail(doc: &TextDocument, def: &SymbolDefinition) -> Option<String> {
+        let line = doc
+            .get_source()
+            .get_raw()
+            .lines()
+            .nth(def.selection_range.start.line as usize)?;
+        Some(line.trim().trim_end_matches('{').trim_end().to_owned())
+    }
+    /// Returns the fuses which neither are set nor conflict with a fuse set by the `#fuses`
+    /// directive at `point`
+    fn get_fuses(
+        doc: &TextDocument,
+        point: Point,
+        db: &DeviceDatabase,
+    ) -> Result<Option<Vec<String>>> {
+        let source = doc.get_source().get_raw().as_bytes();
+        let position = utils::get_position(point);
+        let directive = directives::find_directive(doc.get_syntax_tree()?, source, point)
+            .filter(|d| d.kind == DirectiveKind::Fuses && d.directive.range.end < position);
+        let directive = match directive {
+            Some(directive) => directive,
+            None => return Ok(None),
+        };
+
+        let groups = directive
+            .arguments
+            .iter()
+            .filter(|a| !(a.key.range.start <= position && position <= a.key.range.end))
+            .map(|a| devices::get_fuse_group(&a.key.text))
+            .collect::<HashSet<_>>();
+        let out = db
+            .fuses
+            .iter()
+            .filter(|f| !groups.contains(&devices::get_fuse_group(f)))
+            .cloned()
+            .collect();
+        Ok(Some(out))
+    }
+    /// Returns true, if `byte` is inside the pin argument of a built-in function, e.g.
+    /// `output_low(|)`
+    fn takes_pin_at(doc: &TextDocument, byte: usize, case_sensitive: bool) -> Result<bool> {
+        let context = match completion::get_call_context(doc, byte)? {
+            Some(context) => context,
+            None => return Ok(false),
+        };
+        let out = BuiltinFunction::find(&context.function_name, case_sensitive)
+            .and_then(|f| f.parameters.get(context.active_parameter as usize))
+            .is_some_and(|p| p.is_pin);
+        Ok(out)
+    }
+    fn create_item(
+        label: &str,
+        kind: CompletionItemKind,
+        detail: Option<String>,
+        documentation: Option<String>,
+        sort_group: u8,
+    ) -> CompletionItem {
+        CompletionItem {
+            label: label.to_owned(),
+            kind: Some(kind),
+            detail,
+            documentation: documentation.map(|value| {
+                Documentation::MarkupContent(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value,
+                })
+            }),
+            sort_text: Some(format!("{}_{}", sort_group, label)),
+            ..Default::default()
+        }
+    }
+
+    let doc = match symbols::get_source_doc(docs, path) {
+        Some(doc) => doc,
+        None => return Ok(vec![]),
+    };
+    if is_inside_non_code(doc, point)? {
+        return Ok(vec![]);
+    }
+    if let Some(db) = db {
+        if let Some(fuses) = get_fuses(doc, point, db)? {
+            let detail = format!("Fuse of {}", db.device);
+            let out = fuses
+                .iter()
+                .map(|f| {
+                    create_item(
+                        f,
+                        CompletionItemKind::EnumMember,
+                        Some(detail.clone()),
+                        None,
+                        0,
+                    )
+                })
+                .collect();
+            return Ok(out);
+        }
+    }
+    let source = doc.get_source().get_raw().as_bytes();
+    if let Some(line) = asm::find_asm_line(doc.get_syntax_tree()?, source, point) {
+        let at_mnemonic = line
+            .mnemonic
+            .is_none_or(|m| point.column <= m.range.end.character as usize);
+        if at_mnemonic {
+            let out = PicInstruction::all()
+                .iter()
+                .map(|i| {
+                    create_item(
+                        &i.name,
+                        CompletionItemKind::Keyword,
+                        Some(i.description.clone()),
+                        Some(i.get_hover_text()),
+                        0,
+                    )
+                })
+                .collect();
+            return Ok(out);
+        }
+    }
+    let byte = doc.get_source().get_offset_for_point(&point)?;
+
+    // Symbols which only differ in case are the same symbol unless the project uses `#case`
+    let case_sensitive = doc.get_symbol_index().case_sensitive;
+    if let Some(db) = db {
+        if takes_pin_at(doc, byte, case_sensitive)? {
+            let detail = format!("Pin of {}", db.device);
+            let out = db
+                .pins
+                .iter()
+                .map(|p| {
+                    create_item(
+                        &p.name,
+                        CompletionItemKind::Constant,
+                        Some(detail.clone()),
+                        db.get_hover_text(&p.name),
+                        0,
+                    )
+                })
+                .collect();
+            return Ok(out);
+        }
+    }
+    let mut seen = HashSet::new();
+    let mut out = vec![];
+
+    let mut locals = doc
+        .get_symbol_index()
+        .definitions
+        .iter()
+        .filter(|def| !def.is_global() && def.is_visible_at(byte))
+        .collect::<Vec<_>>();
+    // Innermost scopes first, so shadowed symbols are dropped
+    locals.sort_by_key(|def| def.scope.as_ref().map(|s| s.len()));
+    for def in locals {
+        if seen.insert(symbols::get_symbol_key(&def.name, case_sensitive)) {
+            let detail = get_definition_detail(doc, def);
+            out.push(create_item(
+                &def.name,
+                get_item_kind(def.kind),
+                detail,
+                None,
+                0,
+            ));
+        }
+    }
+
+    let paths = symbols::get_include_closure(docs, path)
+        .into_iter()
+        .chain(docs.keys().map(|p| p.as_path()));
+    for (doc, def) in paths
+        .filter_map(|p| symbols::get_source_doc(docs, p))
+        .flat_map(|doc| {
+            doc.get_symbol_index()
+                .definitions
+                .iter()
+                .filter(|def| def.is_global())
+                .map(move |def| (doc, def))
+        })
+    {
+        if seen.insert(symbols::get_symbol_key(&def.name, case_sensitive)) {
+            let detail = get_definition_detail(doc, def);
+            out.push(create_item(
+                &def.name,
+                get_item_kind(def.kind),
+                detail,
+                None,
+                1,
+            ));
+        }
+    }
+
+    for function in BuiltinFunction::all() {
+        if seen.insert(symbols::get_symbol_key(&function.name, case_sensitive)) {
+            out.push(create_item(
+                &function.name,
+                CompletionItemKind::Function,
+                Some(function.get_signature()),
+                Some(function.documentation.clone()),
+                2,
+            ));
+        }
+    }
+
+    let types = CCS_PRIMITIVE_TYPES
+        .iter()
+        .map(|t| create_item(t, CompletionItemKind::TypeParameter, None, None, 3));
+    let keywords = C_KEYWORDS
+        .iter()
+        .map(|k| create_item(k, CompletionItemKind::Keyword, None, None, 4));
+    out.extend(types.chain(keywords));
+
+    Ok(out)
+}
+
+fn is_inside_non_code(doc: &TextDocument, point: Point) -> Result<bool> {
+    let tree = doc.get_syntax_tree()?;
+    let before = Point::new(point.row, point.column.saturating_sub(1));
+    let out = tree
+        .root_node()
+        .descendant_for_point_range(before, before)
+        .map(|node| {
+            let mut curr = Some(node);
+            while let Some(node) = curr {
+                if NON_CODE_NODE_KINDS.contains(&node.kind()) {
+                    // The cursor directly behind a closing quote is not inside the literal
+                    return node.end_position() != point || node.kind() == "comment";
+                }
+                curr = node.parent();
+            }
+            false
+        })
+        .unwrap_or(false);
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::create_docs;
+
+    fn get_labels(raw: &str, point: Point) -> Vec<String> {
+        get_labels_for_device(raw, point, None)
+    }
+
+    fn get_labels_for_device(raw: &str, point: Point, db: Option<&DeviceDatabase>) -> Vec<String> {
+        let docs = create_docs(&[("/project/main.c", raw)]);
+
+        get_completion_items(&docs, Path::new("/project/main.c"), point, db)
+            .unwrap()
+            .into_iter()
+            .map(|item| item.label)
+            .collect()
+    }
+
+    #[test]
+    fn test_symbols_in_scope() {
+        let raw = "int16 ticks;\n\nvoid f(int a) {\n\tint b;\n\t\n}\n\nvoid main() {\n\tint c;\n}";
+        let labels = get_labels(raw, Point::new(4, 1));
+
+        assert_eq!(&labels[..4], &["a", "b", "ticks", "f"]);
+        assert!(!labels.contains(&"c".to_owned()));
+        assert!(labels.contains(&"output_low".to_owned()));
+        assert!(labels.contains(&"int16".to_owned()));
+        assert!(labels.contains(&"while".to_owned()));
+    }
+
+    #[test]
+    fn test_no_completion_in_comments() {
+        let labels = get_labels("// output\nvoid main() {\n}", Point::new(0, 6));
+
+        assert!(labels.is_empty());
+    }
+
+    #[test]
+    fn test_mnemonics_in_asm_blocks() {
+        let raw = "void main() {\n#asm\n   mov\n   movwf \n#endasm\n}";
+
+        let labels = get_labels(raw, Point::new(2, 6));
+        assert!(labels.contains(&"movlw".to_owned()));
+        assert!(!labels.contains(&"main".to_owned()));
+
+        let labels = get_labels(raw, Point::new(3, 9));
+        assert!(labels.contains(&"main".to_owned()));
+    }
+
+    #[test]
+    fn test_fuses_of_device() {
+        let db = DeviceDatabase::from_header("//////// Fuses: LP,XT,HS,NOWDT,WDT,NOPUT,PUT\n");
+        let raw = "#fuses XT, NO\n#fuses HS, \nvoid main() {\n}";
+
+        let labels = get_labels_for_device(raw, Point::new(0, 13), Some(&db));
+        assert_eq!(labels, vec!["NOWDT", "WDT", "NOPUT", "PUT"]);
+
+        let labels = get_labels_for_device(raw, Point::new(1, 11), Some(&db));
+        assert_eq!(labels, vec!["NOWDT", "WDT", "NOPUT", "PUT"]);
+
+        let labels = get_labels_for_device(raw, Point::new(3, 0), Some(&db));
+        assert!(labels.contains(&"main".to_owned()));
+    }
+
+    #[test]
+    fn test_pins_of_device() {
+        let db = DeviceDatabase::from_header("#define PIN_C0 56\n#define PIN_C1 57\n");
+        let raw = "void main() {\n\toutput_low();\n\toutput_bit(PIN_C0, );\n}";
+
+        let labels = get_labels_for_device(raw, Point::new(1, 12), Some(&db));
+        assert_eq!(labels, vec!["PIN_C0", "PIN_C1"]);
+
+        let labels = get_labels_for_device(raw, Point::new(2, 20), Some(&db));
+        assert!(labels.contains(&"main".to_owned()));
+
+        let labels = get_labels_for_device(raw, Point::new(1, 12), None);
+        assert!(labels.contains(&"main".to_owned()));
+    }
+}
@@ -0,0 +1,5 @@
+pub use crate::completion::completion_items::*;
+pub use crate::completion::signature_help::*;
+
+pub mod completion_items;
+pub mod signature_help;
@@ -0,0 +1,351 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::{
+    Documentation, MarkupContent, MarkupKind, ParameterInformation, ParameterLabel, SignatureHelp,
+    SignatureInformation,
+};
+use tree_sitter::{Node, Point};
+
+use crate::builtins::BuiltinFunction;
+use crate::docs::text_document_type::TextDocumentTypeTrait;
+use crate::docs::{TextDocument, TextDocumentType};
+use crate::symbols::{self, SymbolDefinition, SymbolDefinitionKind};
+
+/// The call surrounding the cursor
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallContext {
+    pub function_name: String,
+    pub active_parameter: u32,
+}
+
+/// Returns the signatures of the function called at `point`. Project functions and function-like
+/// macros take precedence over built-in functions
+pub fn get_signature_help(
+    docs: &HashMap<PathBuf, TextDocumentType>,
+    path: &Path,
+    point: Point,
+) -> Result<Option<SignatureHelp>> {
+    fn create_documentation(value: String) -> Documentation {
+        Documentation::MarkupContent(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        })
+    }
+    fn get_builtin_signature(function: &BuiltinFunction) -> SignatureInformation {
+        let parameters = function
+            .get_parameter_labels()
+            .into_iter()
+            .zip(function.parameters.iter())
+            .map(|(label, p)| ParameterInformation {
+                label: ParameterLabel::Simple(label),
+                documentation: Some(create_documentation(p.documentation.clone())),
+            })
+            .collect();
+
+        SignatureInformation {
+            label: function.get_signature(),
+            documentation: Some(create_documentation(function.documentation.clone())),
+            parameters: Some(parameters),
+            active_parameter: None,
+        }
+    }
+
+    let doc = match symbols::get_source_doc(docs, path) {
+        Some(doc) => doc,
+        None => return Ok(None),
+    };
+    let byte = doc.get_source().get_offset_for_point(&point)?;
+    let context = match get_call_context(doc, byte)? {
+        Some(context) => context,
+        None => return Ok(None),
+    };
+
+    let case_sensitive = doc.get_symbol_index().case_sensitive;
+    let paths = symbols::get_include_closure(docs, path)
+        .into_iter()
+        .chain(docs.keys().map(|p| p.as_path()));
+    let mut signatures = vec![];
+    let mut seen = vec![];
+    for (doc, def) in paths
+        .filter_map(|p| symbols::get_source_doc(docs, p))
+        .flat_map(|doc| {
+            doc.get_symbol_index()
+                .definitions
+                .iter()
+                .map(move |def| (doc, def))
+        })
+        .filter(|(_, def)| def.is_global())
+        .filter(|(_, def)| symbols::is_same_name(&def.name, &context.function_name, case_sensitive))
+    {
+        if let Some(signature) = get_user_signature(doc, def)? {
+            // Prototypes repeat the signature of their definition
+            if !seen.contains(&signature.label) {
+                seen.push(signature.label.clone());
+                signatures.push(signature);
+            }
+        }
+    }
+    if signatures.is_empty() {
+        let builtin = BuiltinFunction::find(&context.function_name, case_sensitive);
+        signatures.extend(builtin.map(get_builtin_signature));
+    }
+    if signatures.is_empty() {
+        return Ok(None);
+    }
+
+    let active_signature = signatures
+        .iter()
+        .position(|s| {
+            s.parameters.as_ref().map_or(0, |p| p.len()) > context.active_parameter as usize
+        })
+        .unwrap_or(0);
+    Ok(Some(SignatureHelp {
+        signatures,
+        active_signature: Some(active_signature as u32),
+        active_parameter: Some(context.active_parameter),
+    }))
+}
+
+/// Finds the `call_expression` whose `argument_list` contains `byte`. Incomplete calls, which
+/// tree-sitter parses as `ERROR` nodes, are found by scanning the source instead
+pub fn get_call_context(doc: &TextDocument, byte: usize) -> Result<Option<CallContext>> {
+    fn is_inside_argument_list(argument_list: Node, byte: usize) -> bool {
+        let opening = argument_list.child(0).filter(|c| c.kind() == "(");
+        let closing = argument_list
+            .child(argument_list.child_count().saturating_sub(1))
+            .filter(|c| c.kind() == ")" && !c.is_missing());
+
+        opening.is_some_and(|o| o.end_byte() <= byte)
+            && closing.is_none_or(|c| byte <= c.start_byte())
+    }
+
+    let tree = doc.get_syntax_tree()?;
+    let source = doc.get_source().get_raw();
+    let node = tree
+        .root_node()
+        .descendant_for_byte_range(byte.saturating_sub(1), byte);
+
+    let mut curr = node;
+    while let Some(node) = curr {
+        if node.kind() == "argument_list" && is_inside_argument_list(node, byte) {
+            let function = node
+                .parent()
+                .filter(|p| p.kind() == "call_expression")
+                .and_then(|p| p.child_by_field_name("function"))
+                .filter(|f| f.kind() == "identifier");
+            if let Some(function) = function {
+                // A trailing comma, e.g. `output_bit(PIN_C0, )`, is parsed as an `ERROR` node
+                let mut cursor = node.walk();
+                let active_parameter = node
+                    .children(&mut cursor)
+                    .flat_map(|c| match c.kind() {
+                        "ERROR" => {
+                            let mut cursor = c.walk();
+                            c.children(&mut cursor).collect::<Vec<_>>()
+                        }
+                        _ => vec![c],
+                    })
+                    .filter(|c| c.kind() == "," && c.start_byte() < byte)
+                    .count();
+
+                return Ok(Some(CallContext {
+                    function_name: function
+                        .utf8_text(source.as_bytes())
+                        .unwrap_or_default()
+                        .to_owned(),
+                    active_parameter: active_parameter as u32,
+                }));
+            }
+        }
+        curr = node.parent();
+    }
+
+    if tree.root_node().has_error() {
+        return Ok(scan_call_context(&source[..byte.min(source.len())]));
+    }
+    Ok(None)
+}
+
+/// Searches backwards for the unmatched `(` of an incomplete call, e.g. `setup_timer_1(T1_`
+fn scan_call_context(text: &str) -> Option<CallContext> {
+    let mut depth = 0;
+    let mut active_parameter = 0;
+    let mut opening = None;
+
+    for (idx, c) in text.char_indices().rev() {
+        match c {
+            ')' | ']' | '}' => depth += 1,
+            '(' | '[' if depth > 0 => depth -= 1,
+            '(' => {
+                opening = Some(idx);
+                break;
+            }
+            ',' if depth == 0 => active_parameter += 1,
+            ';' | '{' => return None,
+            _ => {}
+        }
+    }
+
+    let before = text[..opening?].trim_end();
+    let start = before
+        .rfind(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .map_or(0, |idx| idx + 1);
+    let function_name = &before[start..];
+    if function_name.is_empty() || function_name.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+
+    Some(CallContext {
+        function_name: function_name.to_owned(),
+        active_parameter,
+    })
+}
+
+/// Builds the signature of a project function or function-like macro from its declarator
+fn get_user_signature(
+    doc: &TextDocument,
+    def: &SymbolDefinition,
+) -> Result<Option<SignatureInformation>> {
+    fn get_text(node: Node, source: &[u8]) -> String {
+        node.utf8_text(source).unwrap_or_default().trim().to_owned()
+    }
+
+    let parameter_list_kind = match def.kind {
+        SymbolDefinitionKind::Function | SymbolDefinitionKind::FunctionPrototype => {
+            "function_declarator"
+        }
+        SymbolDefinitionKind::FunctionMacro => "preproc_function_def",
+        _ => return Ok(None),
+    };
+    let tree = doc.get_syntax_tree()?;
+    let source = doc.get_source().get_raw().as_bytes();
+
+    let mut curr = tree
+        .root_node()
+        .descendant_for_byte_range(def.name_bytes.start, def.name_bytes.end);
+    while let Some(node) = curr {
+        if node.kind() == parameter_list_kind {
+            break;
+        }
+        curr = node.parent();
+    }
+    let owner = match curr {
+        Some(owner) => owner,
+        None => return Ok(None),
+    };
+    let parameters = match owner.child_by_field_name("parameters") {
+        Some(parameters) => parameters,
+        None => return Ok(None),
+    };
+
+    let mut cursor = parameters.walk();
+    let parameter_labels = parameters
+        .named_children(&mut cursor)
+        .filter(|p| p.kind() != "comment")
+        .map(|p| get_text(p, source))
+        .collect::<Vec<_>>();
+    let label = if def.kind == SymbolDefinitionKind::FunctionMacro {
+        format!("#define {}({})", def.name, parameter_labels.join(", "))
+    } else {
+        let mut definition = owner.parent();
+        while let Some(node) = definition {
+            if node.child_by_field_name("type").is_some() {
+                break;
+            }
+            definition = node.parent();
+        }
+        let return_type = definition
+            .and_then(|d| d.child_by_field_name("type"))
+            .map(|t| format!("{} ", get_text(t, source)))
+            .unwrap_or_default();
+        format!(
+            "{}{}({})",
+            return_type,
+            def.name,
+            parameter_labels.join(", ")
+        )
+    };
+
+    Ok(Some(SignatureInformation {
+        label,
+        documentation: None,
+        parameters: Some(
+            parameter_labels
+                .into_iter()
+                .map(|p| ParameterInformation {
+                    label: ParameterLabel::Simple(p),
+                    documentation: None,
+                })
+                .collect(),
+        ),
+        active_parameter: None,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::create_docs;
+
+    fn get_help(raw: &str, point: Point) -> Option<SignatureHelp> {
+        let docs = create_docs(&[("/project/main.c", raw)]);
+
+        get_signature_help(&docs, Path::new("/project/main.c"), point).unwrap()
+    }
+
+    #[test]
+    fn test_project_function() {
+        let raw = "int add(int a, int b) {\n\treturn a + b;\n}\n\nvoid main() {\n\tadd(1, 2);\n}";
+        let help = get_help(raw, Point::new(5, 8)).unwrap();
+
+        assert_eq!(help.signatures[0].label, "int add(int a, int b)");
+        assert_eq!(help.active_parameter, Some(1));
+    }
+
+    #[test]
+    fn test_reference_and_default_parameters() {
+        let raw =
+            "void lcd_gotoxy(int8 &x, int8 y = 1) {\n}\n\nvoid main() {\n\tlcd_gotoxy(a, 2);\n}";
+        let help = get_help(raw, Point::new(4, 15)).unwrap();
+
+        let signature = &help.signatures[0];
+        assert_eq!(signature.label, "void lcd_gotoxy(int8 &x, int8 y = 1)");
+        let parameters = signature
+            .parameters
+            .as_ref()
+            .unwrap()
+            .iter()
+            .map(|p| p.label.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            parameters,
+            vec![
+                ParameterLabel::Simple("int8 &x".to_owned()),
+                ParameterLabel::Simple("int8 y = 1".to_owned())
+            ]
+        );
+        assert_eq!(help.active_parameter, Some(1));
+    }
+
+    #[test]
+    fn test_incomplete_builtin_call() {
+        let raw = "void main() {\n\tsetup_timer_2(T2_DIV_BY_4, add(1, 2), \n}";
+        let help = get_help(raw, Point::new(1, 39)).unwrap();
+
+        assert_eq!(
+            help.signatures[0].label,
+            "void setup_timer_2(int8 mode, int8 period, int8 postscale)"
+        );
+        assert_eq!(help.active_parameter, Some(2));
+    }
+
+    #[test]
+    fn test_outside_of_call() {
+        let raw = "void main() {\n\tdelay_ms(500);\n}";
+
+        assert!(get_help(raw, Point::new(1, 15)).is_none());
+        assert!(get_help(raw, Point::new(1, 10)).is_some());
+    }
+}
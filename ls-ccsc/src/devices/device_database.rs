@@ -0,0 +1,358 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use tower_lsp::jsonrpc::Result;
+
+use crate::utils;
+
+lazy_static! {
+    static ref DEVICE_MATCHER: Regex = Regex::new(r"^\s*#device\s+(?P<device>\w+)").unwrap();
+    static ref FUSES_MATCHER: Regex = Regex::new(r"^/+\s*Fuses:(?P<fuses>.*)$").unwrap();
+    static ref SECTION_MATCHER: Regex = Regex::new(r"^/{10,}").unwrap();
+    static ref CONSTANTS_MATCHER: Regex =
+        Regex::new(r"^//\s*Constants\s+(?:used|returned).*\sare:").unwrap();
+    static ref FUNCTION_MATCHER: Regex = Regex::new(r"(\w+)\(\)").unwrap();
+    static ref DEFINE_MATCHER: Regex = Regex::new(
+        r"^\s*#define\s+(?P<name>\w+)(?:\s+(?P<value>.*?))?\s*(?://.*)?$"
+    )
+    .unwrap();
+    static ref REGISTER_MATCHER: Regex = Regex::new(
+        r"(?i)^\s*#(?P<kind>byte|word|bit)\s+(?P<name>\w+)\s*=\s*(?P<address>\w+)(?:\.(?P<bit>\d+))?"
+    )
+    .unwrap();
+    static ref PIN_MATCHER: Regex = Regex::new(r"^PIN_(?P<port>[A-Z])(?P<bit>\d+)$").unwrap();
+}
+
+/// Directories PICC installs its device headers to by default
+const DEFAULT_HEADER_DIRECTORIES: [&str; 2] = [
+    r"C:\Program Files\PICC\Devices",
+    r"C:\Program Files (x86)\PICC\Devices",
+];
+
+/// A pin constant like `#define PIN_C0 56`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DevicePin {
+    pub name: String,
+    /// Port letter, e.g. `C`
+    pub port: char,
+    /// The address of the port register multiplied by 8 plus the bit
+    pub number: u32,
+}
+
+impl DevicePin {
+    pub fn get_address(&self) -> u32 {
+        self.number / 8
+    }
+
+    pub fn get_bit(&self) -> u32 {
+        self.number % 8
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegisterKind {
+    Byte,
+    Word,
+    Bit,
+}
+
+/// A special function register declared with `#byte`, `#word` or `#bit`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpecialFunctionRegister {
+    pub name: String,
+    pub kind: RegisterKind,
+    pub address: u32,
+    /// Only set for `#bit`
+    pub bit: Option<u32>,
+}
+
+/// An interrupt constant like `#define INT_TIMER1 0x008C01`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceInterrupt {
+    pub name: String,
+    pub value: u32,
+}
+
+impl DeviceInterrupt {
+    /// Returns the name used by `#int_xxx` directives, e.g. `TIMER1`
+    pub fn get_directive_name(&self) -> &str {
+        &self.name[4..]
+    }
+}
+
+/// Any other constant defined by the header
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceConstant {
+    pub name: String,
+    pub value: String,
+    /// The built-in functions the header lists the constant for, e.g. `SETUP_TIMER_0`
+    pub functions: Vec<String>,
+}
+
+/// Pins, registers, fuses, ... of a device, parsed from its PICC header (e.g. `16F883.h`)
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DeviceDatabase {
+    /// E.g. `PIC16F883`
+    pub device: String,
+    pub path: PathBuf,
+    pub pins: Vec<DevicePin>,
+    pub registers: Vec<SpecialFunctionRegister>,
+    pub fuses: Vec<String>,
+    pub interrupts: Vec<DeviceInterrupt>,
+    pub constants: Vec<DeviceConstant>,
+}
+
+impl DeviceDatabase {
+    /// Loads the header of `device` (e.g. `PIC16F883` or `16F883`) from the first directory
+    /// containing it
+    pub fn load(directories: &[PathBuf], device: &str) -> Result<Self> {
+        let path = find_header(directories, device).ok_or_else(|| {
+            utils::create_server_error(
+                10,
+                format!("No device header found for '{}'", get_header_name(device)),
+            )
+        })?;
+        let raw = fs::read(&path).map_err(|e| {
+            utils::create_server_error(10, format!("Failed to read '{}': {}", path.display(), e))
+        })?;
+
+        let mut out = DeviceDatabase::from_header(&String::from_utf8_lossy(&raw));
+        if out.device.is_empty() {
+            out.device = device.to_owned();
+        }
+        out.path = path;
+        Ok(out)
+    }
+
+    pub fn from_header(raw: &str) -> Self {
+        fn parse_number(text: &str) -> Option<u32> {
+            match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                None => text.parse().ok(),
+            }
+        }
+
+        let mut out = DeviceDatabase::default();
+        let mut functions = vec![];
+        for line in raw.lines() {
+            if let Some(captures) = FUSES_MATCHER.captures(line) {
+                out.fuses.extend(
+                    captures["fuses"]
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|f| !f.is_empty())
+                        .map(String::from),
+                );
+            } else if SECTION_MATCHER.is_match(line) {
+                functions.clear();
+            } else if CONSTANTS_MATCHER.is_match(line) {
+                functions = FUNCTION_MATCHER
+                    .captures_iter(line)
+                    .map(|c| c[1].to_uppercase())
+                    .collect();
+            } else if let Some(captures) = DEVICE_MATCHER.captures(line) {
+                out.device = captures["device"].to_uppercase();
+            } else if let Some(captures) = REGISTER_MATCHER.captures(line) {
+                let address = &captures["address"];
+                let address = match parse_number(address) {
+                    Some(address) => address,
+                    // `#bit Z = STATUS.2`
+                    None => match out.get_register(address) {
+                        Some(register) => register.address,
+                        None => continue,
+                    },
+                };
+                let kind = match captures["kind"].to_lowercase().as_str() {
+                    "byte" => RegisterKind::Byte,
+                    "word" => RegisterKind::Word,
+                    _ => RegisterKind::Bit,
+                };
+                out.registers.push(SpecialFunctionRegister {
+                    name: captures["name"].to_owned(),
+                    kind,
+                    address,
+                    bit: captures.name("bit").and_then(|b| b.as_str().parse().ok()),
+                });
+            } else if let Some(captures) = DEFINE_MATCHER.captures(line) {
+                let name = captures["name"].to_owned();
+                let value = captures.name("value").map_or("", |v| v.as_str());
+                let number = parse_number(value);
+                match (PIN_MATCHER.captures(&name), number) {
+                    (Some(pin), Some(number)) => out.pins.push(DevicePin {
+                        port: pin["port"].chars().next().unwrap_or_default(),
+                        name,
+                        number,
+                    }),
+                    (None, Some(value)) if name.starts_with("INT_") => {
+                        out.interrupts.push(DeviceInterrupt { name, value })
+                    }
+                    _ => out.constants.push(DeviceConstant {
+                        name,
+                        value: value.to_owned(),
+                        functions: functions.clone(),
+                    }),
+                }
+            }
+        }
+        out
+    }
+
+    pub fn get_pin(&self, name: &str) -> Option<&DevicePin> {
+        self.pins.iter().find(|p| p.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn get_register(&self, name: &str) -> Option<&SpecialFunctionRegister> {
+        self.registers
+            .iter()
+            .find(|r| r.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn get_fuse(&self, name: &str) -> Option<&str> {
+        self.fuses
+            .iter()
+            .find(|f| f.eq_ignore_ascii_case(name))
+            .map(String::as_str)
+    }
+
+    pub fn get_interrupt(&self, name: &str) -> Option<&DeviceInterrupt> {
+        self.interrupts
+            .iter()
+            .find(|i| i.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn get_constant(&self, name: &str) -> Option<&DeviceConstant> {
+        self.constants
+            .iter()
+            .find(|c| c.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Markdown describing the pin, register, ... named `name`
+    pub fn get_hover_text(&self, name: &str) -> Option<String> {
+        let (title, details) = if let Some(pin) = self.get_pin(name) {
+            let details = format!(
+                "Pin {}{} (register `0x{:02X}`, bit {})",
+                pin.port,
+                pin.get_bit(),
+                pin.get_address(),
+                pin.get_bit()
+            );
+            (&pin.name, details)
+        } else if let Some(register) = self.get_register(name) {
+            let details = match register.bit {
+                Some(bit) => format!("Bit {} of register `0x{:02X}`", bit, register.address),
+                None => format!("Register at `0x{:02X}`", register.address),
+            };
+            (&register.name, details)
+        } else if let Some(interrupt) = self.get_interrupt(name) {
+            let details = format!(
+                "Interrupt `0x{:X}`, handled by `#int_{}`",
+                interrupt.value,
+                interrupt.get_directive_name().to_lowercase()
+            );
+            (&interrupt.name, details)
+        } else if let Some(fuse) = self.get_fuse(name) {
+            return Some(format!("**{}**\n\nFuse of `{}`", fuse, self.device));
+        } else if let Some(constant) = self.get_constant(name) {
+            let mut details = format!("`{}`", constant.value);
+            if !constant.functions.is_empty() {
+                details.push_str(&format!(" (used by `{}`)", constant.functions.join("`, `")));
+            }
+            (&constant.name, details)
+        } else {
+            return None;
+        };
+
+        Some(format!(
+            "**{}**\n\n{}\n\nDefined by `{}`",
+            title, details, self.device
+        ))
+    }
+}
+
+/// Returns the file name of the header of `device`, e.g. `16F883.h` for `PIC16F883`
+pub fn get_header_name(device: &str) -> String {
+    let device = device.trim().to_uppercase();
+    let device = device.strip_prefix("PIC").unwrap_or(&device);
+    format!("{}.h", device)
+}
+
+/// Returns the directories to look for device headers in: the configured one, if any, followed
+/// by the default installation directories of PICC
+pub fn get_header_directories(configured: Option<&Path>) -> Vec<PathBuf> {
+    configured
+        .map(Path::to_path_buf)
+        .into_iter()
+        .chain(DEFAULT_HEADER_DIRECTORIES.iter().map(PathBuf::from))
+        .collect()
+}
+
+/// Returns the path to the header of `device`. File names are compared case-insensitively
+pub fn find_header(directories: &[PathBuf], device: &str) -> Option<PathBuf> {
+    let name = get_header_name(device);
+    directories.iter().find_map(|directory| {
+        let path = directory.join(&name);
+        if path.is_file() {
+            return Some(path);
+        }
+        fs::read_dir(directory)
+            .ok()?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .find(|p| {
+                p.file_name()
+                    .and_then(|n| n.to_str())
+                    .is_some_and(|n| n.eq_ignore_ascii_case(&name))
+            })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_fixture_directory() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/devices")
+    }
+
+    #[test]
+    fn test_load_fixture() {
+        let db = DeviceDatabase::load(&[get_fixture_directory()], "PIC16f883").unwrap();
+
+        assert_eq!(db.device, "PIC16F883");
+        assert_eq!(db.pins.len(), 25);
+        let pin = db.get_pin("pin_c0").unwrap();
+        assert_eq!((pin.port, pin.get_address(), pin.get_bit()), ('C', 7, 0));
+
+        assert_eq!(db.fuses.len(), 36);
+        assert_eq!(db.get_fuse("nowdt"), Some("NOWDT"));
+        assert_eq!(db.get_fuse("WDT_2304MS"), None);
+
+        let register = db.get_register("Z").unwrap();
+        assert_eq!(
+            (register.kind, register.address, register.bit),
+            (RegisterKind::Bit, 3, Some(2))
+        );
+        assert_eq!(db.get_register("TMR1").unwrap().kind, RegisterKind::Word);
+
+        let interrupt = db.get_interrupt("INT_TIMER1").unwrap();
+        assert_eq!(
+            (interrupt.value, interrupt.get_directive_name()),
+            (0x8C01, "TIMER1")
+        );
+        assert!(db.get_interrupt("GLOBAL").is_none());
+
+        let constant = db.get_constant("T0_DIV_256").unwrap();
+        assert_eq!(constant.value, "7");
+        assert_eq!(constant.functions, vec!["SETUP_TIMER_0"]);
+        assert_eq!(db.get_constant("BYTE").unwrap().value, "int8");
+        assert!(db.get_constant("BYTE").unwrap().functions.is_empty());
+    }
+
+    #[test]
+    fn test_missing_header() {
+        assert!(DeviceDatabase::load(&[get_fixture_directory()], "PIC18F4520").is_err());
+        assert_eq!(get_header_name(" pic18f4520 "), "18F4520.h");
+    }
+}
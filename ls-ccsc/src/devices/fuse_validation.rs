@@ -0,0 +1,157 @@
+use std::collections::{HashMap, HashSet};
+
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity};
+use tree_sitter::{Node, Tree};
+
+use crate::devices::DeviceDatabase;
+use crate::directives::{self, CcsDirective, DirectiveKind, DirectiveWord};
+use crate::preprocessor::{self, InactiveRegion};
+
+/// Fuses selecting the oscillator. Only one of them may be set
+const OSCILLATOR_FUSES: &[&str] = &[
+    "LP", "XT", "HS", "H4", "HSPLL", "HSM", "HSH", "EC", "EC_IO", "ECH", "ECM", "ECL", "ECH_IO",
+    "ECM_IO", "ECL_IO", "RC", "RC_IO", "INTRC", "INTRC_IO", "INTRC_HP", "INTRC_LP", "PRIMARY",
+    "FRC", "FRC_PLL", "FRC_PS", "LPRC",
+];
+
+/// Returns the group of mutually exclusive fuses `fuse` belongs to. Oscillator fuses form a
+/// group, otherwise a fuse conflicts with its negation and its variants (`WDT`, `NOWDT`,
+/// `WDT_SW`)
+pub fn get_fuse_group(fuse: &str) -> String {
+    let fuse = fuse.to_uppercase();
+    if OSCILLATOR_FUSES.contains(&fuse.as_str()) {
+        return "oscillator".to_owned();
+    }
+    let base = fuse.strip_prefix("NO").unwrap_or(&fuse);
+    base.split('_').next().unwrap_or(base).to_owned()
+}
+
+/// Returns the fuses of every `#fuses` directive which is compiled
+pub fn find_fuses(
+    tree: &Tree,
+    source: &[u8],
+    inactive_regions: &[InactiveRegion],
+) -> Vec<DirectiveWord> {
+    fn collect_fuses(node: Node, source: &[u8], out: &mut Vec<DirectiveWord>) {
+        if directives::DIRECTIVE_KINDS.contains(&node.kind()) {
+            if let Some(directive) = CcsDirective::from_node(node, source) {
+                if directive.kind == DirectiveKind::Fuses {
+                    out.extend(directive.arguments.into_iter().map(|a| a.key));
+                }
+                return;
+            }
+        }
+        let mut cursor = node.walk();
+        for child in node.named_children(&mut cursor) {
+            collect_fuses(child, source, out);
+        }
+    }
+
+    let mut out = vec![];
+    collect_fuses(tree.root_node(), source, &mut out);
+    out.retain(|f| !preprocessor::is_inactive_line(inactive_regions, f.range.start.line));
+    out
+}
+
+/// Flags unknown, duplicate and mutually exclusive fuses
+pub fn validate_fuses(
+    tree: &Tree,
+    source: &[u8],
+    db: &DeviceDatabase,
+    inactive_regions: &[InactiveRegion],
+) -> Vec<Diagnostic> {
+    fn create_diagnostic(
+        fuse: &DirectiveWord,
+        severity: DiagnosticSeverity,
+        message: String,
+    ) -> Diagnostic {
+        Diagnostic {
+            range: fuse.range,
+            severity: Some(severity),
+            message,
+            source: Some("ls-ccsc".to_owned()),
+            ..Default::default()
+        }
+    }
+
+    let mut out = vec![];
+    let mut seen = HashSet::new();
+    let mut groups: HashMap<String, &str> = HashMap::new();
+    let fuses = find_fuses(tree, source, inactive_regions);
+    for fuse in fuses.iter() {
+        let name = match db.get_fuse(&fuse.text) {
+            Some(name) => name,
+            None => {
+                let message = format!("Unknown fuse '{}' for {}", fuse.text, db.device);
+                out.push(create_diagnostic(fuse, DiagnosticSeverity::Error, message));
+                continue;
+            }
+        };
+        if !seen.insert(name) {
+            let message = format!("Duplicate fuse '{}'", fuse.text);
+            out.push(create_diagnostic(
+                fuse,
+                DiagnosticSeverity::Warning,
+                message,
+            ));
+            continue;
+        }
+        match groups.get(&get_fuse_group(name)) {
+            Some(other) => {
+                let message = format!("Fuse '{}' conflicts with '{}'", fuse.text, other);
+                out.push(create_diagnostic(fuse, DiagnosticSeverity::Error, message));
+            }
+            None => {
+                groups.insert(get_fuse_group(name), name);
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::parse;
+
+    fn get_messages(raw: &str) -> Vec<(u32, String)> {
+        let tree = parse(raw);
+        let db = DeviceDatabase::from_header(
+            "#device PIC16F883\n//////// Fuses: LP,XT,HS,NOWDT,WDT,NOPUT,PUT\n\
+             //////// Fuses: NOBROWNOUT,BROWNOUT,BROWNOUT_SW,NOLVP,LVP\n",
+        );
+
+        validate_fuses(&tree, raw.as_bytes(), &db, &[])
+            .into_iter()
+            .map(|d| (d.range.start.character, d.message))
+            .collect()
+    }
+
+    #[test]
+    fn test_valid_fuses() {
+        assert!(get_messages("#fuses XT, NOWDT, PUT\n#fuses brownout NOLVP\n").is_empty());
+    }
+
+    #[test]
+    fn test_invalid_fuses() {
+        let messages = get_messages("#fuses XT, NOWTD, WDT, HS, NOWDT, xt\n");
+
+        assert_eq!(
+            messages,
+            vec![
+                (11, "Unknown fuse 'NOWTD' for PIC16F883".to_owned()),
+                (23, "Fuse 'HS' conflicts with 'XT'".to_owned()),
+                (27, "Fuse 'NOWDT' conflicts with 'WDT'".to_owned()),
+                (34, "Duplicate fuse 'xt'".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fuse_groups() {
+        assert_eq!(get_fuse_group("NOWDT"), get_fuse_group("wdt"));
+        assert_eq!(get_fuse_group("BROWNOUT_SW"), get_fuse_group("NOBROWNOUT"));
+        assert_eq!(get_fuse_group("INTRC_IO"), get_fuse_group("XT"));
+        assert_ne!(get_fuse_group("PUT"), get_fuse_group("PROTECT"));
+    }
+}
@@ -0,0 +1,7 @@
+pub use crate::devices::device_database::*;
+pub use crate::devices::fuse_validation::*;
+pub use crate::devices::pin_validation::*;
+
+pub mod device_database;
+pub mod fuse_validation;
+pub mod pin_validation;
@@ -0,0 +1,295 @@
+use std::collections::HashSet;
+
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, Range};
+use tree_sitter::{Node, Tree};
+
+use crate::builtins::BuiltinFunction;
+use crate::devices::DeviceDatabase;
+use crate::directives::{self, CcsDirective, DirectiveKind, DirectiveWord};
+use crate::preprocessor::{self, InactiveRegion};
+use crate::symbols;
+use crate::utils;
+
+/// Returns true, if `name` is meant to be a pin, e.g. `PIN_C0`
+pub fn is_pin_name(name: &str) -> bool {
+    name.get(..4)
+        .is_some_and(|prefix| prefix.eq_ignore_ascii_case("PIN_"))
+}
+
+/// Returns the candidate closest to `name`, if it is close enough to be a typo
+pub fn find_nearest_name<'a>(
+    name: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> Option<&'a str> {
+    fn get_edit_distance(a: &[u8], b: &[u8]) -> usize {
+        let mut previous = (0..=b.len()).collect::<Vec<_>>();
+        for (i, ca) in a.iter().enumerate() {
+            let mut current = vec![i + 1];
+            for (j, cb) in b.iter().enumerate() {
+                let substitution = previous[j] + usize::from(!ca.eq_ignore_ascii_case(cb));
+                current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+            }
+            previous = current;
+        }
+        previous[b.len()]
+    }
+
+    let max_distance = (name.len() / 3).max(1);
+    candidates
+        .into_iter()
+        .map(|c| (get_edit_distance(name.as_bytes(), c.as_bytes()), c))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, c)| c)
+}
+
+/// Flags pins and registers the device does not have. Checks pin arguments of built-in
+/// functions, pin options of `#use` (`xmit=PIN_C6`), macros defined as a pin
+/// (`#define LED PIN_C0`) and registers of `#bit` (`#bit RB3 = PORTB.3`)
+pub fn validate_pins(
+    tree: &Tree,
+    source: &[u8],
+    db: &DeviceDatabase,
+    case_sensitive: bool,
+    inactive_regions: &[InactiveRegion],
+) -> Vec<Diagnostic> {
+    struct Validation<'a> {
+        source: &'a [u8],
+        case_sensitive: bool,
+        pins: Vec<DirectiveWord>,
+        registers: Vec<DirectiveWord>,
+        /// Registers declared by the document itself
+        declared: HashSet<String>,
+    }
+
+    impl<'a> Validation<'a> {
+        fn visit(&mut self, node: Node) {
+            match node.kind() {
+                "call_expression" => self.visit_call(node),
+                "preproc_def" => {
+                    let value = node.child_by_field_name("value");
+                    if let Some(word) = value.and_then(|v| self.create_trimmed_word(v)) {
+                        if is_pin_name(&word.text) && !word.text.contains(char::is_whitespace) {
+                            self.pins.push(word);
+                        }
+                    }
+                    return;
+                }
+                kind if directives::DIRECTIVE_KINDS.contains(&kind) => {
+                    if let Some(directive) = CcsDirective::from_node(node, self.source) {
+                        self.visit_directive(directive);
+                        return;
+                    }
+                }
+                _ => {}
+            }
+
+            let mut cursor = node.walk();
+            for child in node.named_children(&mut cursor) {
+                self.visit(child);
+            }
+        }
+
+        fn visit_call(&mut self, node: Node) {
+            let function = node
+                .child_by_field_name("function")
+                .filter(|f| f.kind() == "identifier")
+                .and_then(|f| f.utf8_text(self.source).ok())
+                .and_then(|f| BuiltinFunction::find(f, self.case_sensitive));
+            let arguments = node.child_by_field_name("arguments");
+            if let (Some(function), Some(arguments)) = (function, arguments) {
+                let mut cursor = arguments.walk();
+                let arguments = arguments
+                    .named_children(&mut cursor)
+                    .filter(|a| a.kind() != "comment");
+                for (parameter, argument) in function.parameters.iter().zip(arguments) {
+                    let text = argument.utf8_text(self.source).unwrap_or_default();
+                    if parameter.is_pin && argument.kind() == "identifier" && is_pin_name(text) {
+                        self.pins.push(DirectiveWord {
+                            text: text.to_owned(),
+                            range: utils::get_range(&argument),
+                        });
+                    }
+                }
+            }
+
+            let mut cursor = node.walk();
+            for child in node.named_children(&mut cursor) {
+                self.visit(child);
+            }
+        }
+
+        fn visit_directive(&mut self, directive: CcsDirective) {
+            match directive.kind {
+                DirectiveKind::Use => self.pins.extend(
+                    directive
+                        .arguments
+                        .into_iter()
+                        .filter_map(|a| a.value)
+                        .filter(|v| is_pin_name(&v.text)),
+                ),
+                DirectiveKind::Bit => {
+                    let register = directive
+                        .arguments
+                        .into_iter()
+                        .next()
+                        .map(|a| a.key)
+                        .filter(|k| k.text.starts_with(|c: char| c.is_alphabetic() || c == '_'));
+                    self.registers.extend(register);
+                }
+                _ => {}
+            }
+            if let (DirectiveKind::Byte | DirectiveKind::Word | DirectiveKind::Bit, Some(target)) =
+                (directive.kind, directive.target)
+            {
+                self.declared
+                    .insert(symbols::get_symbol_key(&target.text, self.case_sensitive));
+            }
+        }
+
+        /// Returns the text of `node` without surrounding whitespace
+        fn create_trimmed_word(&self, node: Node) -> Option<DirectiveWord> {
+            let text = node.utf8_text(self.source).ok()?;
+            let leading = &text[..text.len() - text.trim_start().len()];
+            let trimmed = text.trim();
+            let start = utils::get_point_after(node.start_position(), leading);
+            let end = utils::get_point_after(start, trimmed);
+            Some(DirectiveWord {
+                text: trimmed.to_owned(),
+                range: Range::new(utils::get_position(start), utils::get_position(end)),
+            })
+        }
+
+        fn is_known(&self, name: &str, known: Option<&str>) -> bool {
+            known.is_some_and(|k| symbols::is_same_name(k, name, self.case_sensitive))
+        }
+    }
+
+    fn create_diagnostic(word: &DirectiveWord, message: String) -> Diagnostic {
+        Diagnostic {
+            range: word.range,
+            severity: Some(DiagnosticSeverity::Error),
+            message,
+            source: Some("ls-ccsc".to_owned()),
+            ..Default::default()
+        }
+    }
+    fn get_suggestion(nearest: Option<&str>) -> String {
+        nearest
+            .map(|n| format!(". Did you mean '{}'?", n))
+            .unwrap_or_default()
+    }
+
+    let mut validation = Validation {
+        source,
+        case_sensitive,
+        pins: vec![],
+        registers: vec![],
+        declared: HashSet::new(),
+    };
+    validation.visit(tree.root_node());
+
+    let is_active = |word: &DirectiveWord| {
+        !preprocessor::is_inactive_line(inactive_regions, word.range.start.line)
+    };
+    let mut out = vec![];
+    for pin in validation.pins.iter().filter(|w| is_active(w)) {
+        let known = db.get_pin(&pin.text).map(|p| p.name.as_str());
+        if !validation.is_known(&pin.text, known) {
+            let nearest = find_nearest_name(&pin.text, db.pins.iter().map(|p| p.name.as_str()));
+            let message = format!(
+                "Unknown pin '{}' for {}{}",
+                pin.text,
+                db.device,
+                get_suggestion(nearest)
+            );
+            out.push(create_diagnostic(pin, message));
+        }
+    }
+    for register in validation.registers.iter().filter(|w| is_active(w)) {
+        let key = symbols::get_symbol_key(&register.text, case_sensitive);
+        let known = db.get_register(&register.text).map(|r| r.name.as_str());
+        if !validation.is_known(&register.text, known) && !validation.declared.contains(&key) {
+            let nearest =
+                find_nearest_name(&register.text, db.registers.iter().map(|r| r.name.as_str()));
+            let message = format!(
+                "Unknown register '{}' for {}{}",
+                register.text,
+                db.device,
+                get_suggestion(nearest)
+            );
+            out.push(create_diagnostic(register, message));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::parse;
+
+    fn get_messages(raw: &str, case_sensitive: bool) -> Vec<(u32, u32, String)> {
+        let tree = parse(raw);
+        let db = DeviceDatabase::from_header(
+            "#device PIC16F883\n#define PIN_C0 56\n#define PIN_C1 57\n#define PIN_C6 62\n\
+             #define PIN_C7 63\n#byte PORTB = 0x06\n",
+        );
+
+        validate_pins(&tree, raw.as_bytes(), &db, case_sensitive, &[])
+            .into_iter()
+            .map(|d| (d.range.start.line, d.range.start.character, d.message))
+            .collect()
+    }
+
+    #[test]
+    fn test_unknown_pins() {
+        let raw = "#use rs232(baud=9600, xmit=PIN_C6, rcv=PIN_D7)\n#define LED  PIN_C9\n\
+                   #bit RB3 = PORTB.3\n#bit OOPS = PORTX.1\n\n\
+                   void main() {\n\toutput_low(PIN_C1);\n\toutput_bit(pin_c0, PIN_Q5);\n\
+                   \tinput(PIN_A0);\n}";
+
+        assert_eq!(
+            get_messages(raw, false),
+            vec![
+                (
+                    0,
+                    39,
+                    "Unknown pin 'PIN_D7' for PIC16F883. Did you mean 'PIN_C7'?".to_owned()
+                ),
+                (
+                    1,
+                    13,
+                    "Unknown pin 'PIN_C9' for PIC16F883. Did you mean 'PIN_C0'?".to_owned()
+                ),
+                (
+                    8,
+                    7,
+                    "Unknown pin 'PIN_A0' for PIC16F883. Did you mean 'PIN_C0'?".to_owned()
+                ),
+                (
+                    3,
+                    12,
+                    "Unknown register 'PORTX' for PIC16F883. Did you mean 'PORTB'?".to_owned()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pins_are_case_sensitive_with_case_directive() {
+        let raw = "void main() {\n\toutput_low(pin_c0);\n}";
+
+        assert!(get_messages(raw, false).is_empty());
+        assert_eq!(get_messages(raw, true).len(), 1);
+    }
+
+    #[test]
+    fn test_find_nearest_name() {
+        let names = ["PIN_B0", "PIN_C0", "PIN_C7"];
+
+        assert_eq!(find_nearest_name("PIN_C8", names), Some("PIN_C0"));
+        assert_eq!(find_nearest_name("pin_c7", names), Some("PIN_C7"));
+        assert_eq!(find_nearest_name("LED", names), None);
+    }
+}
@@ -0,0 +1,443 @@
+use tower_lsp::lsp_types::Range;
+use tree_sitter::{Node, Point, Tree};
+
+use crate::utils;
+
+/// Node kinds of the dedicated directive rules of tree-sitter-ccsc
+pub const DIRECTIVE_KINDS: &[&str] = &[
+    "preproc_fuses",
+    "preproc_use",
+    "preproc_interrupt",
+    "preproc_device",
+    "preproc_org",
+    "preproc_byte",
+    "preproc_bit",
+    "preproc_locate",
+    "preproc_reserve",
+    "preproc_rom",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DirectiveKind {
+    Fuses,
+    Use,
+    Interrupt,
+    Device,
+    Org,
+    Byte,
+    Word,
+    Bit,
+    Locate,
+    Reserve,
+    Rom,
+}
+
+impl DirectiveKind {
+    fn from_name(name: &str) -> Option<DirectiveKind> {
+        let out = match name {
+            "#fuses" => DirectiveKind::Fuses,
+            "#use" => DirectiveKind::Use,
+            "#device" => DirectiveKind::Device,
+            "#org" => DirectiveKind::Org,
+            "#byte" => DirectiveKind::Byte,
+            "#word" => DirectiveKind::Word,
+            "#bit" => DirectiveKind::Bit,
+            "#locate" => DirectiveKind::Locate,
+            "#reserve" => DirectiveKind::Reserve,
+            "#rom" => DirectiveKind::Rom,
+            n if n.starts_with("#int_") => DirectiveKind::Interrupt,
+            _ => return None,
+        };
+        Some(out)
+    }
+
+    pub fn get_description(&self) -> &'static str {
+        match self {
+            DirectiveKind::Fuses => "Sets the configuration fuses of the device",
+            DirectiveKind::Use => "Configures a built-in library",
+            DirectiveKind::Interrupt => "Marks the following function as interrupt handler",
+            DirectiveKind::Device => "Selects the target device and compiler options",
+            DirectiveKind::Org => "Places the following function or constant at a fixed address",
+            DirectiveKind::Byte => "Maps a variable to an 8 bit register",
+            DirectiveKind::Word => "Maps a variable to a 16 bit register",
+            DirectiveKind::Bit => "Maps a variable to a single bit of a register",
+            DirectiveKind::Locate => "Places a variable at a fixed RAM address",
+            DirectiveKind::Reserve => "Keeps the compiler from allocating the given RAM",
+            DirectiveKind::Rom => "Stores data in program memory or EEPROM",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirectiveWord {
+    pub text: String,
+    pub range: Range,
+}
+
+/// Meaning depends on the directive: fuse (`WDT`), option (`clock=4Mhz`), address range
+/// (`0x20:0x2F`), register and bit position (`0x0C.0`) or address and data (`0x2100={1, 2}`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirectiveArgument {
+    pub key: DirectiveWord,
+    pub value: Option<DirectiveWord>,
+}
+
+/// A CCS specific directive like `#fuses XT, NOWDT` or `#bit TMR1IF = 0x0C.0`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CcsDirective {
+    pub kind: DirectiveKind,
+    /// `#fuses`, `#int_timer1`, ...
+    pub directive: DirectiveWord,
+    /// Library of `#use`, chip of `#device`, type of `#rom` or name declared by `#byte`,
+    /// `#word`, `#bit` and `#locate`
+    pub target: Option<DirectiveWord>,
+    pub arguments: Vec<DirectiveArgument>,
+    /// Everything behind the directive
+    pub argument_text: String,
+    pub range: Range,
+}
+
+fn create_word(node: Node, source: &[u8]) -> Option<DirectiveWord> {
+    Some(DirectiveWord {
+        text: node.utf8_text(source).ok()?.to_owned(),
+        range: utils::get_range(&node),
+    })
+}
+
+/// Reads `key[=value]` pairs like `fuse`, `directive_option` and `device_option` nodes
+fn create_argument(node: Node, source: &[u8], key: &str, value: &str) -> Option<DirectiveArgument> {
+    let mut key = create_word(node.child_by_field_name(key)?, source)?;
+    // `*=16` of `#device` is a single token
+    if key.text == "*=" {
+        key.text.truncate(1);
+        key.range.end.character = key.range.start.character + 1;
+    }
+    Some(DirectiveArgument {
+        key,
+        value: node
+            .child_by_field_name(value)
+            .and_then(|n| create_word(n, source)),
+    })
+}
+
+impl CcsDirective {
+    /// Reads the fields of the directive nodes. Returns `None` for other nodes
+    pub fn from_node(node: Node, source: &[u8]) -> Option<CcsDirective> {
+        if !DIRECTIVE_KINDS.contains(&node.kind()) {
+            return None;
+        }
+
+        let directive = node
+            .child_by_field_name("directive")
+            .or_else(|| node.child(0))?;
+        let name = directive
+            .utf8_text(source)
+            .ok()?
+            .replace(|c: char| c.is_whitespace(), "");
+        let kind = DirectiveKind::from_name(&name.to_lowercase())?;
+
+        let argument_start = directive.end_byte();
+        let argument_end = node.end_byte().max(argument_start);
+        let text = std::str::from_utf8(&source[argument_start..argument_end]).ok()?;
+
+        let word = |field: &str| {
+            node.child_by_field_name(field)
+                .and_then(|n| create_word(n, source))
+        };
+        let mut cursor = node.walk();
+        let target = match kind {
+            DirectiveKind::Use => word("library"),
+            DirectiveKind::Device => word("chip"),
+            DirectiveKind::Rom => word("type"),
+            DirectiveKind::Byte
+            | DirectiveKind::Word
+            | DirectiveKind::Bit
+            | DirectiveKind::Locate => word("name"),
+            _ => None,
+        };
+        let arguments = match kind {
+            DirectiveKind::Fuses => match node.child_by_field_name("fuses") {
+                Some(list) => list
+                    .named_children(&mut cursor)
+                    .filter_map(|n| create_argument(n, source, "name", "value"))
+                    .collect(),
+                None => vec![],
+            },
+            DirectiveKind::Use => match node.child_by_field_name("options") {
+                Some(list) => list
+                    .named_children(&mut cursor)
+                    .filter_map(|n| create_argument(n, source, "key", "value"))
+                    .collect(),
+                None => vec![],
+            },
+            DirectiveKind::Interrupt => node
+                .children_by_field_name("option", &mut cursor)
+                .filter_map(|n| create_word(n, source))
+                .map(|key| DirectiveArgument { key, value: None })
+                .collect(),
+            DirectiveKind::Device => node
+                .children_by_field_name("option", &mut cursor)
+                .filter_map(|n| create_argument(n, source, "key", "value"))
+                .collect(),
+            DirectiveKind::Org => create_argument(node, source, "start", "end")
+                .into_iter()
+                .collect(),
+            DirectiveKind::Reserve => node
+                .children_by_field_name("range", &mut cursor)
+                .filter_map(|n| match n.kind() {
+                    "address_range" => create_argument(n, source, "start", "end"),
+                    _ => create_word(n, source).map(|key| DirectiveArgument { key, value: None }),
+                })
+                .collect(),
+            DirectiveKind::Byte | DirectiveKind::Word | DirectiveKind::Locate => word("address")
+                .map(|key| DirectiveArgument { key, value: None })
+                .into_iter()
+                .collect(),
+            DirectiveKind::Bit => create_argument(node, source, "address", "bit")
+                .into_iter()
+                .collect(),
+            DirectiveKind::Rom => create_argument(node, source, "address", "value")
+                .into_iter()
+                .collect(),
+        };
+
+        Some(CcsDirective {
+            kind,
+            directive: DirectiveWord {
+                text: name,
+                range: utils::get_range(&directive),
+            },
+            target,
+            arguments,
+            argument_text: text.trim().to_owned(),
+            range: utils::get_range(&node),
+        })
+    }
+
+    /// Returns the interrupt of `#int_xxx` directives, e.g. `timer1`
+    pub fn get_interrupt_name(&self) -> Option<&str> {
+        match self.kind {
+            DirectiveKind::Interrupt => self.directive.text.get(5..),
+            _ => None,
+        }
+    }
+
+    /// Markdown describing the directive and its parsed arguments
+    pub fn get_hover_text(&self) -> String {
+        fn code(word: &DirectiveWord) -> String {
+            format!("`{}`", word.text)
+        }
+        fn code_or_unknown(word: Option<&DirectiveWord>) -> String {
+            word.map(code).unwrap_or_else(|| "?".to_owned())
+        }
+
+        let title = match &self.target {
+            Some(target) => format!("{} {}", self.directive.text, target.text),
+            None => self.directive.text.clone(),
+        };
+        let mut lines = vec![
+            format!("**{}**", title),
+            self.kind.get_description().to_owned(),
+        ];
+
+        let first = self.arguments.first();
+        match self.kind {
+            DirectiveKind::Interrupt => {
+                lines.push(format!(
+                    "Interrupt: `{}`",
+                    self.get_interrupt_name().unwrap_or_default()
+                ));
+            }
+            DirectiveKind::Byte | DirectiveKind::Word | DirectiveKind::Locate => {
+                lines.push(format!(
+                    "Address: {}",
+                    code_or_unknown(first.map(|a| &a.key))
+                ));
+            }
+            DirectiveKind::Bit => {
+                lines.push(format!(
+                    "Register: {}, bit: {}",
+                    code_or_unknown(first.map(|a| &a.key)),
+                    code_or_unknown(first.and_then(|a| a.value.as_ref()))
+                ));
+            }
+            DirectiveKind::Rom => {
+                lines.push(format!(
+                    "Address: {}, data: {}",
+                    code_or_unknown(first.map(|a| &a.key)),
+                    code_or_unknown(first.and_then(|a| a.value.as_ref()))
+                ));
+            }
+            _ => {}
+        }
+
+        let listed: &[DirectiveArgument] = match self.kind {
+            DirectiveKind::Fuses
+            | DirectiveKind::Use
+            | DirectiveKind::Device
+            | DirectiveKind::Org
+            | DirectiveKind::Reserve => &self.arguments,
+            _ => &[],
+        };
+        let separator = match self.kind {
+            DirectiveKind::Org | DirectiveKind::Reserve => " - ",
+            _ => " = ",
+        };
+        let items = listed
+            .iter()
+            .map(|a| match &a.value {
+                Some(value) => format!("- {}{}{}", code(&a.key), separator, code(value)),
+                None => format!("- {}", code(&a.key)),
+            })
+            .collect::<Vec<_>>();
+        if !items.is_empty() {
+            lines.push(items.join("\n"));
+        }
+
+        lines.join("\n\n")
+    }
+}
+
+/// Returns the CCS directive at `point`
+pub fn find_directive(tree: &Tree, source: &[u8], point: Point) -> Option<CcsDirective> {
+    let mut node = tree.root_node().descendant_for_point_range(point, point);
+    while let Some(n) = node {
+        if DIRECTIVE_KINDS.contains(&n.kind()) {
+            return CcsDirective::from_node(n, source);
+        }
+        node = n.parent();
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::Position;
+
+    use super::*;
+    use crate::test_utils::parse;
+
+    fn parse_directives(raw: &str) -> Vec<CcsDirective> {
+        let tree = parse(raw);
+        let root = tree.root_node();
+        let mut cursor = root.walk();
+        let out = root
+            .named_children(&mut cursor)
+            .filter_map(|n| CcsDirective::from_node(n, raw.as_bytes()))
+            .collect();
+        out
+    }
+
+    fn get_pairs(directive: &CcsDirective) -> Vec<(String, Option<String>)> {
+        directive
+            .arguments
+            .iter()
+            .map(|a| (a.key.text.clone(), a.value.as_ref().map(|v| v.text.clone())))
+            .collect()
+    }
+
+    fn pair(key: &str, value: Option<&str>) -> (String, Option<String>) {
+        (key.to_owned(), value.map(String::from))
+    }
+
+    #[test]
+    fn test_directives() {
+        let directives = parse_directives(
+            "#include <16F883.h>\n#FUSES XT, NOWDT NOLVP\n\
+             #use rs232(baud=9600, xmit=PIN_C6, rcv=PIN_C7)\n#device PIC16F883 ADC=10 *=16\n\
+             #int_timer1 HIGH\n#org 0x1E00, 0x1FFF\n#byte PORTB = 0x06\n#bit TMR1IF = 0x0C.0\n\
+             #word TMR1 = getenv(\"SFR:TMR1L\")\n#reserve 0x20:0x2F, 0x70\n\
+             #rom int8 0x2100 = {1, 2, 3}\n#pragma once\n",
+        );
+
+        let kinds = directives.iter().map(|d| d.kind).collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                DirectiveKind::Fuses,
+                DirectiveKind::Use,
+                DirectiveKind::Device,
+                DirectiveKind::Interrupt,
+                DirectiveKind::Org,
+                DirectiveKind::Byte,
+                DirectiveKind::Bit,
+                DirectiveKind::Word,
+                DirectiveKind::Reserve,
+                DirectiveKind::Rom,
+            ]
+        );
+
+        let fuses = &directives[0];
+        assert_eq!(
+            get_pairs(fuses),
+            vec![pair("XT", None), pair("NOWDT", None), pair("NOLVP", None)]
+        );
+        assert_eq!(
+            fuses.arguments[1].key.range,
+            Range::new(Position::new(1, 11), Position::new(1, 16))
+        );
+
+        let rs232 = &directives[1];
+        assert_eq!(rs232.target.as_ref().unwrap().text, "rs232");
+        assert_eq!(
+            get_pairs(rs232),
+            vec![
+                pair("baud", Some("9600")),
+                pair("xmit", Some("PIN_C6")),
+                pair("rcv", Some("PIN_C7"))
+            ]
+        );
+
+        let device = &directives[2];
+        assert_eq!(device.target.as_ref().unwrap().text, "PIC16F883");
+        assert_eq!(
+            get_pairs(device),
+            vec![pair("ADC", Some("10")), pair("*", Some("16"))]
+        );
+
+        assert_eq!(directives[3].get_interrupt_name(), Some("timer1"));
+        assert_eq!(get_pairs(&directives[3]), vec![pair("HIGH", None)]);
+        assert_eq!(
+            get_pairs(&directives[4]),
+            vec![pair("0x1E00", Some("0x1FFF"))]
+        );
+        assert_eq!(directives[5].target.as_ref().unwrap().text, "PORTB");
+        assert_eq!(get_pairs(&directives[5]), vec![pair("0x06", None)]);
+
+        let bit = &directives[6];
+        assert_eq!(get_pairs(bit), vec![pair("0x0C", Some("0"))]);
+        assert_eq!(
+            bit.arguments[0].value.as_ref().unwrap().range,
+            Range::new(Position::new(7, 19), Position::new(7, 20))
+        );
+
+        assert_eq!(
+            get_pairs(&directives[7]),
+            vec![pair("getenv(\"SFR:TMR1L\")", None)]
+        );
+        assert_eq!(
+            get_pairs(&directives[8]),
+            vec![pair("0x20", Some("0x2F")), pair("0x70", None)]
+        );
+        assert_eq!(directives[9].target.as_ref().unwrap().text, "int8");
+        assert_eq!(
+            get_pairs(&directives[9]),
+            vec![pair("0x2100", Some("{1, 2, 3}"))]
+        );
+    }
+
+    #[test]
+    fn test_hover_text() {
+        let directives = parse_directives("#use delay(clock=4Mhz)\n#bit TMR1IF = 0x0C.0\n");
+
+        assert_eq!(
+            directives[0].get_hover_text(),
+            "**#use delay**\n\nConfigures a built-in library\n\n- `clock` = `4Mhz`"
+        );
+        assert_eq!(
+            directives[1].get_hover_text(),
+            "**#bit TMR1IF**\n\nMaps a variable to a single bit of a register\n\n\
+             Register: `0x0C`, bit: `0`"
+        );
+    }
+}
@@ -0,0 +1,3 @@
+pub use crate::directives::ccs_directive::*;
+
+pub mod ccs_directive;
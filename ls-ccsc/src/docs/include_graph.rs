@@ -0,0 +1,403 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Component, Path, PathBuf};
+
+use lazy_static::lazy_static;
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, Range};
+use tree_sitter::{Query, QueryCursor, Tree};
+
+use crate::docs::text_document_type::TextDocumentTypeTrait;
+use crate::docs::TextDocumentType;
+use crate::preprocessor;
+use crate::utils;
+
+type Docs = HashMap<PathBuf, TextDocumentType>;
+
+lazy_static! {
+    static ref PREPROC_INCLUDE_QUERY: Query = Query::new(
+        tree_sitter_ccsc::language(),
+        "(preproc_include path: (_) @path) @include",
+    )
+    .unwrap();
+    static ref PIQ_INCLUDE_IDX: u32 = PREPROC_INCLUDE_QUERY
+        .capture_index_for_name("include")
+        .unwrap();
+    static ref PIQ_PATH_IDX: u32 = PREPROC_INCLUDE_QUERY
+        .capture_index_for_name("path")
+        .unwrap();
+}
+
+/// Default installation directories of the PICC drivers, e.g. `rs232.c`. Other platforms rely
+/// on the include directories of the settings
+#[cfg(windows)]
+const DEFAULT_DRIVER_DIRECTORIES: [&str; 2] = [
+    r"C:\Program Files\PICC\Drivers",
+    r"C:\Program Files (x86)\PICC\Drivers",
+];
+#[cfg(not(windows))]
+const DEFAULT_DRIVER_DIRECTORIES: [&str; 0] = [];
+
+/// An `#include "sth\add.h"` or `#include <16F883.h>` directive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IncludeDirective {
+    /// Path as written, without quotes or angle brackets
+    pub path: String,
+    /// True for `<...>`
+    pub is_system: bool,
+    /// Range of the entire `#include` line
+    pub range: Range,
+    /// The file `path` refers to. `None`, if it was not found
+    pub resolved_path: Option<PathBuf>,
+}
+
+/// Directories `#include`s are searched in
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IncludePaths {
+    pub project_root: Option<PathBuf>,
+    /// Searched for system includes and for quoted includes which are not found relative to the
+    /// including file or the project root
+    pub directories: Vec<PathBuf>,
+    /// The device header and driver directories among `directories`. Files inside them belong
+    /// to PICC rather than the project
+    pub library_directories: Vec<PathBuf>,
+}
+
+impl IncludePaths {
+    /// Relative directories (e.g. `dir_inc` of the .mcp) are relative to `project_root`. The
+    /// `library_directories` are searched after the `directories` of the project
+    pub fn new(
+        project_root: PathBuf,
+        directories: Vec<PathBuf>,
+        library_directories: Vec<PathBuf>,
+    ) -> Self {
+        let library_directories = library_directories
+            .into_iter()
+            .map(|d| project_root.join(d))
+            .chain(DEFAULT_DRIVER_DIRECTORIES.iter().map(PathBuf::from))
+            .collect::<Vec<_>>();
+        let directories = directories
+            .into_iter()
+            .map(|d| project_root.join(d))
+            .chain(library_directories.iter().cloned())
+            .collect();
+        Self {
+            project_root: Some(project_root),
+            directories,
+            library_directories,
+        }
+    }
+
+    /// Returns true, if `path` lies inside the project root and outside of the device header
+    /// and driver directories. Every file is part of the project if there is no root
+    pub fn is_project_file(&self, path: &Path) -> bool {
+        let path = clean_path(path);
+        let is_inside_root = self
+            .project_root
+            .as_ref()
+            .is_none_or(|root| path.starts_with(root));
+        is_inside_root && !self.library_directories.iter().any(|d| path.starts_with(d))
+    }
+
+    /// Returns the paths `include` may refer to, in the order the compiler searches them
+    pub fn get_candidates(&self, including: &Path, include: &IncludeDirective) -> Vec<PathBuf> {
+        let relative = utils::normalize_path_separators(&include.path);
+        let local = including
+            .parent()
+            .into_iter()
+            .chain(self.project_root.as_deref());
+        let directories = self.directories.iter().map(PathBuf::as_path);
+        let mut out: Vec<PathBuf> = if include.is_system {
+            directories
+                .chain(local)
+                .map(|d| clean_path(&d.join(&relative)))
+                .collect()
+        } else {
+            local
+                .chain(directories)
+                .map(|d| clean_path(&d.join(&relative)))
+                .collect()
+        };
+        out.dedup();
+        out
+    }
+
+    /// Returns the first candidate for which `exists` holds
+    pub fn resolve(
+        &self,
+        including: &Path,
+        include: &IncludeDirective,
+        exists: impl Fn(&Path) -> bool,
+    ) -> Option<PathBuf> {
+        self.get_candidates(including, include)
+            .into_iter()
+            .find(|p| exists(p))
+    }
+}
+
+/// Removes `.` and `..` from `path`, so that `sth/../config.h` and `config.h` are the same document
+fn clean_path(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if out.file_name().is_some() => {
+                out.pop();
+            }
+            component => out.push(component),
+        }
+    }
+    out
+}
+
+/// Returns the `#include` directives of `tree`. Their paths are resolved relative to `path`
+/// until the project is known, see `update_included_files`
+pub fn find_includes(tree: &Tree, source: &[u8], path: &Path) -> Vec<IncludeDirective> {
+    let mut query_cursor = QueryCursor::new();
+    let mut out = vec![];
+    for m in query_cursor.matches(&PREPROC_INCLUDE_QUERY, tree.root_node(), source) {
+        let include = m.nodes_for_capture_index(*PIQ_INCLUDE_IDX).next();
+        let path_node = m.nodes_for_capture_index(*PIQ_PATH_IDX).next();
+        let (include, path_node) = match (include, path_node) {
+            (Some(include), Some(path_node)) if !include.has_error() => (include, path_node),
+            _ => continue,
+        };
+        let text = path_node.utf8_text(source).unwrap_or_default();
+        if text.len() <= 2 {
+            continue;
+        }
+
+        let mut include = IncludeDirective {
+            path: text[1..text.len() - 1].to_owned(),
+            is_system: text.starts_with('<'),
+            range: utils::get_range(&include),
+            resolved_path: None,
+        };
+        include.resolved_path = IncludePaths::default()
+            .get_candidates(path, &include)
+            .into_iter()
+            .next();
+        out.push(include);
+    }
+    out
+}
+
+/// Resolves the `#include`s of every document against the documents of the project and the
+/// files on disk
+pub fn update_included_files(docs: &mut Docs, include_paths: &IncludePaths) {
+    let known = docs.keys().cloned().collect::<HashSet<_>>();
+    for (path, doc_type) in docs.iter_mut() {
+        if let TextDocumentType::Source(doc) = doc_type {
+            let includes = resolve_includes(doc.get_includes(), path, include_paths, &known);
+            doc.set_includes(includes);
+        }
+    }
+}
+
+/// Resolves `includes` of the document at `path`. `known` contains the documents of the project
+pub fn resolve_includes(
+    includes: &[IncludeDirective],
+    path: &Path,
+    include_paths: &IncludePaths,
+    known: &HashSet<PathBuf>,
+) -> Vec<IncludeDirective> {
+    includes
+        .iter()
+        .map(|include| IncludeDirective {
+            resolved_path: include_paths
+                .resolve(path, include, |p| known.contains(p) || p.is_file()),
+            ..include.clone()
+        })
+        .collect()
+}
+
+/// Returns the shortest chain of includes leading from `from` to `to`, both included
+fn find_include_chain(docs: &Docs, from: &Path, to: &Path) -> Option<Vec<PathBuf>> {
+    let mut parents: HashMap<PathBuf, PathBuf> = HashMap::new();
+    let mut visited = HashSet::from([from.to_owned()]);
+    let mut pending = VecDeque::from([from.to_owned()]);
+
+    while let Some(curr) = pending.pop_front() {
+        if curr == to {
+            let mut out = vec![curr];
+            while let Some(parent) = parents.get(out.last()?) {
+                out.push(parent.clone());
+            }
+            out.reverse();
+            return Some(out);
+        }
+        if let Some(TextDocumentType::Source(doc)) = docs.get(&curr) {
+            for included in doc.get_included_files() {
+                if visited.insert(included.clone()) {
+                    parents.insert(included.clone(), curr.clone());
+                    pending.push_back(included.clone());
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Flags `#include`s of `path` which were not found or which include `path` again
+pub fn get_include_diagnostics(docs: &Docs, path: &Path) -> Vec<Diagnostic> {
+    fn create_diagnostic(
+        include: &IncludeDirective,
+        severity: DiagnosticSeverity,
+        message: String,
+    ) -> Diagnostic {
+        Diagnostic {
+            range: include.range,
+            severity: Some(severity),
+            message,
+            source: Some("ls-ccsc".to_owned()),
+            ..Default::default()
+        }
+    }
+    fn get_file_name(path: &Path) -> String {
+        path.file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.display().to_string())
+    }
+
+    let doc = match docs.get(path) {
+        Some(TextDocumentType::Source(doc)) => doc,
+        _ => return vec![],
+    };
+
+    let mut out = vec![];
+    for include in doc
+        .get_includes()
+        .iter()
+        .filter(|i| !preprocessor::is_inactive_line(doc.get_inactive_regions(), i.range.start.line))
+    {
+        let included = match &include.resolved_path {
+            Some(included) => included,
+            // Without PICC installed, device headers and drivers cannot be found
+            None if include.is_system => {
+                let message = format!(
+                    "Cannot find include file <{}> in the include directories",
+                    include.path
+                );
+                out.push(create_diagnostic(
+                    include,
+                    DiagnosticSeverity::Warning,
+                    message,
+                ));
+                continue;
+            }
+            None => {
+                let message = format!("Cannot open include file \"{}\"", include.path);
+                out.push(create_diagnostic(
+                    include,
+                    DiagnosticSeverity::Error,
+                    message,
+                ));
+                continue;
+            }
+        };
+
+        if let Some(chain) = find_include_chain(docs, included, path) {
+            let names = std::iter::once(path)
+                .chain(chain.iter().map(PathBuf::as_path))
+                .map(get_file_name)
+                .collect::<Vec<_>>();
+            let message = format!("Cyclic include: {}", names.join(" -> "));
+            out.push(create_diagnostic(
+                include,
+                DiagnosticSeverity::Warning,
+                message,
+            ));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::create_docs;
+
+    fn get_messages(docs: &Docs, path: &str) -> Vec<(u32, String)> {
+        get_include_diagnostics(docs, Path::new(path))
+            .into_iter()
+            .map(|d| (d.range.start.line, d.message))
+            .collect()
+    }
+
+    #[test]
+    fn test_resolve_includes() {
+        let mut docs = create_docs(&[
+            (
+                "/project/main.c",
+                "#include <16F883.h>\n#include \"sth\\add.h\"\n#include \"config.h\"\n",
+            ),
+            ("/project/sth/add.h", "#include \"../config.h\"\n"),
+            ("/project/config.h", ""),
+            ("/picc/16F883.h", ""),
+        ]);
+        let include_paths = IncludePaths {
+            project_root: Some(PathBuf::from("/project")),
+            directories: vec![PathBuf::from("/picc")],
+            library_directories: vec![PathBuf::from("/picc")],
+        };
+        update_included_files(&mut docs, &include_paths);
+
+        let resolved = match &docs[Path::new("/project/main.c")] {
+            TextDocumentType::Source(doc) => doc
+                .get_includes()
+                .iter()
+                .map(|i| i.resolved_path.clone().unwrap())
+                .collect::<Vec<_>>(),
+            TextDocumentType::Ignored => unreachable!(),
+        };
+        assert_eq!(
+            resolved,
+            vec![
+                PathBuf::from("/picc/16F883.h"),
+                PathBuf::from("/project/sth/add.h"),
+                PathBuf::from("/project/config.h"),
+            ]
+        );
+        assert!(get_messages(&docs, "/project/main.c").is_empty());
+        assert!(get_messages(&docs, "/project/sth/add.h").is_empty());
+    }
+
+    #[test]
+    fn test_missing_includes() {
+        let mut docs = create_docs(&[(
+            "/project/main.c",
+            "#include <18F4550.h>\n#if 0\n#include \"old.h\"\n#endif\n#include \"missing.h\"\n",
+        )]);
+        update_included_files(&mut docs, &IncludePaths::default());
+        preprocessor::update_inactive_regions(&mut docs, None);
+
+        assert_eq!(
+            get_messages(&docs, "/project/main.c"),
+            vec![
+                (
+                    0,
+                    "Cannot find include file <18F4550.h> in the include directories".to_owned()
+                ),
+                (4, "Cannot open include file \"missing.h\"".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cyclic_includes() {
+        let mut docs = create_docs(&[
+            ("/project/main.c", "#include \"a.h\"\n"),
+            ("/project/a.h", "#include \"b.h\"\n"),
+            ("/project/b.h", "#include \"a.h\"\n"),
+        ]);
+        update_included_files(&mut docs, &IncludePaths::default());
+
+        assert!(get_messages(&docs, "/project/main.c").is_empty());
+        assert_eq!(
+            get_messages(&docs, "/project/a.h"),
+            vec![(0, "Cyclic include: a.h -> b.h -> a.h".to_owned())]
+        );
+        assert_eq!(
+            get_messages(&docs, "/project/b.h"),
+            vec![(0, "Cyclic include: b.h -> a.h -> b.h".to_owned())]
+        );
+    }
+}
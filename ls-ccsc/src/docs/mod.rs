@@ -1,7 +1,9 @@
+pub use crate::docs::include_graph::*;
 pub use crate::docs::text_document::TextDocument;
 pub use crate::docs::text_document_source::TextDocumentSource;
 pub use crate::docs::text_document_type::TextDocumentType;
 
+pub mod include_graph;
 pub mod text_document;
 pub mod text_document_source;
 pub mod text_document_type;
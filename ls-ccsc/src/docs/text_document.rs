@@ -7,7 +7,9 @@ use tower_lsp::lsp_types::Diagnostic;
 use tree_sitter::{Parser, Tree};
 
 use crate::docs::text_document_type::TextDocumentTypeTrait;
-use crate::docs::TextDocumentSource;
+use crate::docs::{IncludeDirective, TextDocumentSource};
+use crate::preprocessor::InactiveRegion;
+use crate::symbols::DocumentSymbolIndex;
 
 #[derive(Clone)]
 pub struct TextDocument {
@@ -15,9 +17,12 @@ pub struct TextDocument {
     pub source: TextDocumentSource,
     pub syntax_tree: Option<Tree>,
     pub parser: Arc<Mutex<Parser>>,
+    pub includes: Vec<IncludeDirective>,
+    /// Resolved paths of `includes`
     pub included_files: HashSet<PathBuf>,
-    // TODO: Detect cyclic includes
     pub compiler_diagnostics: Vec<Diagnostic>,
+    pub symbol_index: DocumentSymbolIndex,
+    pub inactive_regions: Vec<InactiveRegion>,
 }
 
 impl TextDocumentTypeTrait for TextDocument {
@@ -29,6 +34,22 @@ impl TextDocumentTypeTrait for TextDocument {
         self.syntax_tree = syntax_tree;
     }
 
+    fn set_symbol_index(&mut self, symbol_index: DocumentSymbolIndex) {
+        self.symbol_index = symbol_index;
+    }
+
+    fn set_inactive_regions(&mut self, inactive_regions: Vec<InactiveRegion>) {
+        self.inactive_regions = inactive_regions;
+    }
+
+    fn set_includes(&mut self, includes: Vec<IncludeDirective>) {
+        self.included_files = includes
+            .iter()
+            .filter_map(|i| i.resolved_path.clone())
+            .collect();
+        self.includes = includes;
+    }
+
     fn get_source(&self) -> &TextDocumentSource {
         &self.source
     }
@@ -43,6 +64,10 @@ impl TextDocumentTypeTrait for TextDocument {
         &self.absolute_path
     }
 
+    fn get_includes(&self) -> &Vec<IncludeDirective> {
+        &self.includes
+    }
+
     fn get_included_files(&self) -> &HashSet<PathBuf> {
         &self.included_files
     }
@@ -51,6 +76,14 @@ impl TextDocumentTypeTrait for TextDocument {
         &self.compiler_diagnostics
     }
 
+    fn get_symbol_index(&self) -> &DocumentSymbolIndex {
+        &self.symbol_index
+    }
+
+    fn get_inactive_regions(&self) -> &Vec<InactiveRegion> {
+        &self.inactive_regions
+    }
+
     fn get_parser(&self) -> Arc<Mutex<Parser>> {
         self.parser.clone()
     }
@@ -66,15 +99,21 @@ impl TextDocumentTypeTrait for TextDocument {
     }
 
     fn new(absolute_path: PathBuf, raw: String, parser: Arc<Mutex<Parser>>) -> Self {
-        let (absolute_path, source, syntax_tree, parser, included_files, compiler_diagnostics) =
+        let (absolute_path, source, syntax_tree, parser, includes, compiler_diagnostics) =
             Self::from_string(absolute_path, raw, parser);
-        Self {
+        let mut out = Self {
             absolute_path,
             source,
             syntax_tree,
             parser,
-            included_files,
+            includes: vec![],
+            included_files: HashSet::new(),
             compiler_diagnostics,
-        }
+            symbol_index: Default::default(),
+            inactive_regions: vec![],
+        };
+        out.set_includes(includes);
+        out.update_symbol_index();
+        out
     }
 }
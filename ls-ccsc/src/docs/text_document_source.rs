@@ -52,7 +52,7 @@ impl TextDocumentSource {
     }
 
     pub fn get_point_from_byte_idx(&self, byte: usize) -> Result<Point> {
-        fn get_character_position(positions: &Vec<usize>, byte: usize) -> Result<usize> {
+        fn get_character_position(positions: &[usize], byte: usize) -> Result<usize> {
             let out =
                 positions
                     .iter()
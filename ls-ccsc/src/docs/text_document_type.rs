@@ -1,53 +1,53 @@
 use std::collections::{HashMap, HashSet};
-use std::io::Read;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 
-use lazy_static::lazy_static;
 use tower_lsp::jsonrpc::{self, Error, Result};
 use tower_lsp::lsp_types::{Diagnostic, Position, Range, TextDocumentContentChangeEvent};
-use tree_sitter::{
-    InputEdit, Node, Parser, Point, Query, QueryCursor, QueryMatch, Tree, TreeCursor,
-};
+use tree_sitter::{InputEdit, Parser, Point, Tree, TreeCursor};
 
-use crate::{MPLABProjectConfig, TextDocument, utils};
-use crate::docs::TextDocumentSource;
+use crate::docs::{self, IncludeDirective, IncludePaths, TextDocumentSource};
 use crate::mplab_project_config::MPLABFile;
+use crate::preprocessor::{self, InactiveRegion};
+use crate::symbols::DocumentSymbolIndex;
+use crate::{asm, utils, MPLABProjectConfig, TextDocument};
 
 // Replace with Trait?
 #[derive(Clone)]
 pub enum TextDocumentType {
     Ignored,
-    Source(TextDocument),
+    Source(Box<TextDocument>),
     //#[allow(dead_code)]
     //MCP(TextDocument), // TODO: MCP is not implemented yet
 }
 
-lazy_static! {
-    static ref PREPROC_INCLUDE_QUERY: Query = Query::new(
-        tree_sitter_ccsc::language(),
-        "(preproc_include path: (_) @path) @include",
-    )
-    .unwrap();
-    static ref PIQ_INCLUDE_IDX: u32 = PREPROC_INCLUDE_QUERY
-        .capture_index_for_name("include")
-        .unwrap();
-    static ref PIQ_PATH_IDX: u32 = PREPROC_INCLUDE_QUERY
-        .capture_index_for_name("path")
-        .unwrap();
-}
-
-type TDCCE = TextDocumentContentChangeEvent;
+type ContentChange = TextDocumentContentChangeEvent;
+/// Path, source, syntax tree, parser, include directives and compiler diagnostics of a new
+/// document
+type DocumentParts = (
+    PathBuf,
+    TextDocumentSource,
+    Option<Tree>,
+    Arc<Mutex<Parser>>,
+    Vec<IncludeDirective>,
+    Vec<Diagnostic>,
+);
 
 pub trait TextDocumentTypeTrait {
     fn set_source(&mut self, source: TextDocumentSource);
     fn set_syntax_tree(&mut self, syntax_tree: Option<Tree>);
+    fn set_symbol_index(&mut self, symbol_index: DocumentSymbolIndex);
+    fn set_inactive_regions(&mut self, inactive_regions: Vec<InactiveRegion>);
+    fn set_includes(&mut self, includes: Vec<IncludeDirective>);
 
     fn get_source(&self) -> &TextDocumentSource;
     fn get_syntax_tree(&self) -> Result<&Tree>;
     fn get_absolute_path(&self) -> &PathBuf;
+    fn get_includes(&self) -> &Vec<IncludeDirective>;
     fn get_included_files(&self) -> &HashSet<PathBuf>;
     fn get_compiler_diagnostics(&self) -> &Vec<Diagnostic>;
+    fn get_symbol_index(&self) -> &DocumentSymbolIndex;
+    fn get_inactive_regions(&self) -> &Vec<InactiveRegion>;
 
     fn get_parser(&self) -> Arc<Mutex<Parser>>;
     fn get_mut_syntax_tree(&mut self) -> Result<&mut Tree>;
@@ -59,46 +59,15 @@ pub trait TextDocumentTypeTrait {
         absolute_path: PathBuf,
         raw: String,
         parser: Arc<Mutex<Parser>>,
-    ) -> (
-        PathBuf,
-        TextDocumentSource,
-        Option<Tree>,
-        Arc<Mutex<Parser>>,
-        HashSet<PathBuf>,
-        Vec<Diagnostic>,
-    ) {
-        fn get_included_files(node: Node, source: &[u8], path: &PathBuf) -> HashSet<PathBuf> {
-            fn include_has_no_errors(m: &QueryMatch) -> bool {
-                !m.nodes_for_capture_index(*PIQ_INCLUDE_IDX)
-                    .any(|c| c.has_error())
-            }
-            fn get_node_with_path<'cursor: 'tree, 'tree>(
-                m: QueryMatch<'cursor, 'tree>,
-            ) -> Option<Node<'cursor>> {
-                m.nodes_for_capture_index(*PIQ_PATH_IDX).next()
-            }
-            let mut query_cursor = QueryCursor::new();
-
-            let out = query_cursor
-                .matches(&PREPROC_INCLUDE_QUERY, node, source)
-                .filter(include_has_no_errors)
-                .filter_map(get_node_with_path)
-                .filter_map(|c| c.utf8_text(source).ok())
-                .filter(|path_str| path_str.len() > 2)
-                .map(|path_str| path.join(&path_str[1..path_str.len() - 1]))
-                .collect::<HashSet<_>>();
-
-            out
-        }
-
+    ) -> DocumentParts {
         let source = TextDocumentSource::from(raw);
 
         let mut parser_lock = parser.lock().unwrap();
         let syntax_tree = parser_lock.parse(source.get_raw(), None);
         std::mem::drop(parser_lock);
 
-        let included_files = get_included_files(
-            syntax_tree.as_ref().unwrap().root_node(),
+        let includes = docs::find_includes(
+            syntax_tree.as_ref().unwrap(),
             source.get_raw().as_bytes(),
             &absolute_path,
         );
@@ -110,7 +79,7 @@ pub trait TextDocumentTypeTrait {
             source,
             syntax_tree,
             parser,
-            included_files,
+            includes,
             compiler_diagnostics,
         )
     }
@@ -125,25 +94,25 @@ pub trait TextDocumentTypeTrait {
         )
     }
 
-    fn reparse_with_lsp(&mut self, params: Vec<TDCCE>) -> Result<String> {
+    fn reparse_with_lsp(&mut self, params: Vec<ContentChange>) -> Result<String> {
         type In1 = (usize, usize, usize, usize, String);
         type In2 = (Point, Point, String);
         type In3 = (TextDocumentSource, InputEdit);
         type In4 = (TextDocumentSource, Option<Tree>);
-        fn deconstruct_input(tdcce: TextDocumentContentChangeEvent) -> In1 {
-            let TextDocumentContentChangeEvent { range, text, .. } = tdcce;
+        fn deconstruct_input(change: ContentChange) -> In1 {
+            let ContentChange { range, text, .. } = change;
 
             let Range {
                 start:
-                Position {
-                    line: start_line,
-                    character: start_character,
-                },
+                    Position {
+                        line: start_line,
+                        character: start_character,
+                    },
                 end:
-                Position {
-                    line: end_line,
-                    character: end_character,
-                },
+                    Position {
+                        line: end_line,
+                        character: end_character,
+                    },
             } = range.unwrap_or(Range {
                 start: Default::default(),
                 end: Position {
@@ -162,8 +131,8 @@ pub trait TextDocumentTypeTrait {
         }
         fn construct_points_and_change(input: In1) -> In2 {
             let (start_line, end_line, start_character, end_character, changed) = input;
-            let start_position = Point::new(start_line as usize, start_character as usize);
-            let old_end_position = Point::new(end_line as usize, end_character as usize);
+            let start_position = Point::new(start_line, start_character);
+            let old_end_position = Point::new(end_line, end_character);
             (start_position, old_end_position, changed)
         }
         fn preprocess_for_reparsing(input: In2, source: TextDocumentSource) -> Result<In3> {
@@ -205,8 +174,8 @@ pub trait TextDocumentTypeTrait {
         let mut log = String::with_capacity(self.get_source().get_raw().len());
         for param in params
             .into_iter()
-            .map(|param| deconstruct_input(param))
-            .map(|input| construct_points_and_change(input))
+            .map(deconstruct_input)
+            .map(construct_points_and_change)
         {
             let param = preprocess_for_reparsing(param, self.get_source().clone())?;
             let (source, tree) =
@@ -219,10 +188,58 @@ pub trait TextDocumentTypeTrait {
             log.push_str(self.get_syntax_tree()?.root_node().to_sexp().as_str());
             log.push_str("\n\n---\n\n");
         }
+        // Resolved against the project again by `update_included_files`
+        let includes = docs::find_includes(
+            self.get_syntax_tree()?,
+            self.get_source().get_raw().as_bytes(),
+            self.get_absolute_path(),
+        );
+        self.set_includes(includes);
+        self.update_symbol_index();
 
         Ok(log)
     }
 
+    /// Rebuilds the symbol index of this document only. Other documents keep their index
+    fn update_symbol_index(&mut self) {
+        let case_sensitive = self.get_symbol_index().case_sensitive;
+        self.rebuild_symbol_index(case_sensitive);
+    }
+
+    /// Rebuilds the symbol index, if names were resolved in the other case mode
+    fn set_case_sensitive(&mut self, case_sensitive: bool) {
+        if self.get_symbol_index().case_sensitive != case_sensitive {
+            self.rebuild_symbol_index(case_sensitive);
+        }
+    }
+
+    /// Rebuilds the symbol index, if the inactive regions changed. Returns true in that case
+    fn update_inactive_regions(&mut self, inactive_regions: Vec<InactiveRegion>) -> bool {
+        if *self.get_inactive_regions() == inactive_regions {
+            return false;
+        }
+
+        self.set_inactive_regions(inactive_regions);
+        self.update_symbol_index();
+        true
+    }
+
+    fn rebuild_symbol_index(&mut self, case_sensitive: bool) {
+        let symbol_index = match self.get_syntax_tree() {
+            Ok(tree) => DocumentSymbolIndex::from_tree(
+                tree,
+                self.get_source().get_raw().as_bytes(),
+                case_sensitive,
+                self.get_inactive_regions(),
+            ),
+            Err(_) => DocumentSymbolIndex {
+                case_sensitive,
+                ..Default::default()
+            },
+        };
+        self.set_symbol_index(symbol_index);
+    }
+
     fn get_diagnostics(&self) -> Result<Vec<Diagnostic>> {
         fn populate_syntax_errors(mut cursor: TreeCursor, diags: &mut Vec<Diagnostic>, raw: &[u8]) {
             let node = cursor.node();
@@ -261,6 +278,17 @@ pub trait TextDocumentTypeTrait {
             &mut diagnostics,
             self.get_source().get_raw().as_bytes(),
         );
+        // PIC assembly is not C, errors reported inside `#asm` blocks are meaningless
+        let asm_blocks = asm::find_asm_blocks(self.get_syntax_tree()?);
+        diagnostics.retain(|d| {
+            !asm_blocks
+                .iter()
+                .any(|b| b.contains_line(d.range.start.line as usize))
+        });
+        // Branches which are not compiled may contain anything
+        diagnostics.retain(|d| {
+            !preprocessor::is_inactive_line(self.get_inactive_regions(), d.range.start.line)
+        });
         diagnostics.extend(self.get_compiler_diagnostics().clone());
 
         Ok(diagnostics)
@@ -270,44 +298,24 @@ pub trait TextDocumentTypeTrait {
 impl TextDocumentType {
     pub fn index_from_mcp(
         mcp: &MPLABProjectConfig,
-        root_path: &PathBuf,
+        root_path: &Path,
+        include_paths: &IncludePaths,
         parser: Arc<Mutex<Parser>>,
     ) -> jsonrpc::Result<HashMap<PathBuf, TextDocumentType>> {
-        fn read_string(path: &PathBuf) -> jsonrpc::Result<String> {
-            let mut file = std::fs::File::open(path).map_err(|e| {
-                utils::create_server_error(
-                    6,
-                    format!(
-                        "Could not open file '{}' ('{}')",
-                        path.display(),
-                        e.to_string()
-                    ),
-                )
-            })?;
-            let mut contents = String::new();
-            file.read_to_string(&mut contents).map_err(|e| {
-                utils::create_server_error(
-                    6,
-                    format!(
-                        "Could not read file '{}' ('{}')",
-                        path.display(),
-                        e.to_string()
-                    ),
-                )
-            })?;
-            Ok(contents)
-        }
-        fn deconstruct_path(f: &MPLABFile, root_path: &PathBuf) -> (PathBuf, bool) {
+        fn deconstruct_path(f: &MPLABFile, root_path: &Path) -> (PathBuf, bool) {
             let MPLABFile {
                 path,
                 is_generated,
                 is_other,
                 ..
             } = f;
-            (root_path.join(path), *is_generated || *is_other)
+            (
+                root_path.join(utils::normalize_path_separators(path)),
+                *is_generated || *is_other,
+            )
         }
         fn insert_raw_string(tup: (PathBuf, bool)) -> Option<(PathBuf, String, bool)> {
-            read_string(&tup.0).map(|s| (tup.0, s, tup.1)).ok()
+            utils::read_string(&tup.0).map(|s| (tup.0, s, tup.1)).ok()
         }
         fn create_text_document_type(
             tup: (PathBuf, String, bool),
@@ -315,7 +323,11 @@ impl TextDocumentType {
         ) -> (PathBuf, TextDocumentType) {
             let (p, raw, to_be_ignored) = tup;
             let td = if !to_be_ignored && utils::is_source_file(&p) {
-                TextDocumentType::Source(TextDocument::new(p.clone(), raw, parser.clone()))
+                TextDocumentType::Source(Box::new(TextDocument::new(
+                    p.clone(),
+                    raw,
+                    parser.clone(),
+                )))
             } else {
                 TextDocumentType::Ignored
             };
@@ -323,14 +335,58 @@ impl TextDocumentType {
             (p, td)
         }
 
-        let out = mcp
+        let mut out = mcp
             .files
             .values()
             .map(|f| deconstruct_path(f, root_path))
-            .filter_map(|tup| insert_raw_string(tup))
+            .filter_map(insert_raw_string)
             .map(|tup| create_text_document_type(tup, parser.clone()))
             .collect::<HashMap<_, _>>();
+        docs::update_included_files(&mut out, include_paths);
+        let included = TextDocumentType::index_from_includes(&out, include_paths, parser);
+        out.extend(included);
 
         Ok(out)
     }
+
+    /// Loads every source file that is reachable through `#include`s of `docs`, but is not part
+    /// of `docs` yet (e.g. files which are not listed in the .mcp). The `#include`s of the loaded
+    /// files are resolved against `include_paths`
+    pub fn index_from_includes(
+        docs: &HashMap<PathBuf, TextDocumentType>,
+        include_paths: &IncludePaths,
+        parser: Arc<Mutex<Parser>>,
+    ) -> HashMap<PathBuf, TextDocumentType> {
+        fn get_included_files(doc_type: &TextDocumentType) -> Vec<PathBuf> {
+            match doc_type {
+                TextDocumentType::Ignored => vec![],
+                TextDocumentType::Source(doc) => doc.get_included_files().iter().cloned().collect(),
+            }
+        }
+
+        let known = docs.keys().cloned().collect::<HashSet<_>>();
+        let mut out = HashMap::new();
+        let mut pending = docs
+            .values()
+            .flat_map(get_included_files)
+            .collect::<Vec<_>>();
+        while let Some(path) = pending.pop() {
+            if docs.contains_key(&path) || out.contains_key(&path) || !utils::is_source_file(&path)
+            {
+                continue;
+            }
+
+            if let Ok(raw) = utils::read_string(&path) {
+                let mut doc = TextDocument::new(path.clone(), raw, parser.clone());
+                let includes =
+                    docs::resolve_includes(doc.get_includes(), &path, include_paths, &known);
+                doc.set_includes(includes);
+                let doc = TextDocumentType::Source(Box::new(doc));
+                pending.extend(get_included_files(&doc));
+                out.insert(path, doc);
+            }
+        }
+
+        out
+    }
 }
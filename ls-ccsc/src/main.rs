@@ -1,21 +1,40 @@
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 
+use futures::channel::mpsc;
+use futures::stream;
 use ini::Ini;
+use serde_json::Value;
 use tower_lsp::{LanguageServer, LspService, Server};
 use tower_lsp::jsonrpc::{Error, ErrorCode, Result};
 use tower_lsp::lsp_types::*;
-use tree_sitter::Point;
+use tree_sitter::{Node, Point, Tree};
 
 use crate::ccsc_response::CCSCResponse;
+use crate::compiler_output::{Listing, SymbolMap};
+use crate::devices::DeviceDatabase;
 use crate::docs::{TextDocument, TextDocumentType};
 use crate::docs::text_document_type::TextDocumentTypeTrait;
 use crate::mplab_project_config::MPLABProjectConfig;
-use crate::server::Backend;
+use crate::server::{Backend, BackendInner, ServerSettings};
+use crate::symbols::SymbolOccurrenceRole;
 
+mod asm;
+mod builtins;
 mod ccsc_response;
+mod compiler_output;
+mod completion;
+mod devices;
+mod directives;
 mod docs;
 mod mplab_project_config;
+mod preprocessor;
+mod semantic_tokens;
 mod server;
+mod symbols;
+#[cfg(test)]
+mod test_utils;
 mod utils;
 
 #[tower_lsp::async_trait]
@@ -24,9 +43,9 @@ impl LanguageServer for server::Backend {
         fn get_path_from_option(uri: Option<Url>) -> Result<PathBuf> {
             let uri = uri.ok_or_else(|| Error::new(ErrorCode::InvalidParams))?;
 
-            Ok(utils::get_path(&uri)?)
+            utils::get_path(&uri)
         }
-        fn get_mcp_ini(path: &PathBuf) -> Result<Ini> {
+        fn get_mcp_ini(path: &Path) -> Result<Ini> {
             let ini =
                 Ini::load_from_file_noescape(utils::find_path_to_mcp(path)?).map_err(|_| {
                     utils::create_server_error(1, "Failed to load MPLAB Project Config".to_owned())
@@ -35,18 +54,26 @@ impl LanguageServer for server::Backend {
             Ok(ini)
         }
 
+        let settings = ServerSettings::from_initialization_options(init.initialization_options);
         let root_path = get_path_from_option(init.root_uri)?;
         let ini = get_mcp_ini(&root_path)?;
         let config = MPLABProjectConfig::from_ini_to_lsp_result(&ini)?;
 
-        let docs = TextDocumentType::index_from_mcp(&config, &root_path, self.get_parser())?;
         let err_paths = utils::find_paths_to_errs(&root_path)?;
 
         let diagnostics = {
             let mut data = self.get_inner();
             data.set_root_path(root_path);
             data.set_mcp(config);
+            data.set_settings(settings);
+            let docs = TextDocumentType::index_from_mcp(
+                data.get_mcp()?,
+                data.get_root_path()?,
+                &data.get_include_paths(),
+                self.get_parser(),
+            )?;
             data.insert_docs(docs);
+            data.update_compiler_output();
             data.insert_compiler_diagnostics(err_paths)
         };
 
@@ -61,6 +88,42 @@ impl LanguageServer for server::Backend {
                     TextDocumentSyncKind::Incremental,
                 )),
                 hover_provider: Some(HoverProviderCapability::Simple(true)),
+                completion_provider: Some(CompletionOptions::default()),
+                signature_help_provider: Some(SignatureHelpOptions {
+                    trigger_characters: Some(vec!["(".to_owned(), ",".to_owned()]),
+                    retrigger_characters: None,
+                    work_done_progress_options: Default::default(),
+                }),
+                definition_provider: Some(OneOf::Left(true)),
+                references_provider: Some(OneOf::Left(true)),
+                document_highlight_provider: Some(OneOf::Left(true)),
+                document_symbol_provider: Some(OneOf::Left(true)),
+                rename_provider: Some(OneOf::Right(RenameOptions {
+                    prepare_provider: Some(true),
+                    work_done_progress_options: Default::default(),
+                })),
+                workspace_symbol_provider: Some(OneOf::Left(true)),
+                call_hierarchy_provider: Some(CallHierarchyServerCapability::Simple(true)),
+                code_lens_provider: Some(CodeLensOptions {
+                    resolve_provider: Some(false),
+                }),
+                execute_command_provider: Some(ExecuteCommandOptions {
+                    commands: vec![
+                        preprocessor::EXPAND_MACRO_COMMAND.to_owned(),
+                        compiler_output::PROJECT_SUMMARY_COMMAND.to_owned(),
+                    ],
+                    work_done_progress_options: Default::default(),
+                }),
+                semantic_tokens_provider: Some(
+                    SemanticTokensServerCapabilities::SemanticTokensOptions(
+                        SemanticTokensOptions {
+                            work_done_progress_options: Default::default(),
+                            legend: semantic_tokens::get_legend(),
+                            range: Some(true),
+                            full: Some(SemanticTokensFullOptions::Delta { delta: Some(true) }),
+                        },
+                    ),
+                ),
                 ..Default::default()
             },
             server_info: Some(ServerInfo {
@@ -73,7 +136,7 @@ impl LanguageServer for server::Backend {
     async fn initialized(&self, _: InitializedParams) {
         let watch = DidChangeWatchedFilesRegistrationOptions {
             watchers: vec![FileSystemWatcher {
-                glob_pattern: "**/*.err".to_string(),
+                glob_pattern: "**/*.{err,sym,sta,STA,tre,lst}".to_string(),
                 kind: None,
             }],
         };
@@ -103,11 +166,16 @@ impl LanguageServer for server::Backend {
                 .collect()
         }
 
-        let mut err_paths = deconstruct_to_paths(params);
+        let (mut err_paths, output_paths): (Vec<_>, Vec<_>) = deconstruct_to_paths(params)
+            .into_iter()
+            .partition(|p| compiler_output::is_output_file(p, "err"));
         err_paths.sort();
         err_paths.dedup();
         let diagnostics = {
             let mut inner = self.get_inner();
+            if !output_paths.is_empty() {
+                inner.update_compiler_output();
+            }
             inner.insert_compiler_diagnostics(err_paths)
         };
 
@@ -118,19 +186,22 @@ impl LanguageServer for server::Backend {
     }
 
     async fn did_open(&self, params: DidOpenTextDocumentParams) {
-        type DOTDP = DidOpenTextDocumentParams;
-        fn did_open_with_result(this: &Backend, params: DOTDP) -> Result<CCSCResponse> {
-            let DOTDP {
+        type OpenParams = DidOpenTextDocumentParams;
+        fn did_open_with_result(this: &Backend, params: OpenParams) -> Result<CCSCResponse> {
+            let OpenParams {
                 text_document: TextDocumentItem { uri, .. },
             } = params;
 
             let path = utils::get_path(&uri)?;
             let mut data = this.get_inner();
-            let doc_type = data.get_doc_or_ignored(path);
+            let doc_type = data.get_doc_or_ignored(path.clone());
 
             let out = match doc_type {
                 TextDocumentType::Ignored => CCSCResponse::ignore_file(uri),
-                TextDocumentType::Source(doc) => generate_response(uri, doc.get_diagnostics()?),
+                TextDocumentType::Source(_) => {
+                    generate_response(uri, data.publish_diagnostics(&path)?)
+                        .with_inactive_regions(vec![data.get_inactive_regions(&path)?])
+                }
                 //TextDocumentType::MCP(doc) => generate_response(uri, doc.get_syntax_errors()?),
             };
 
@@ -148,11 +219,11 @@ impl LanguageServer for server::Backend {
     }
 
     async fn did_change(&self, params: DidChangeTextDocumentParams) {
-        type DCTDP = DidChangeTextDocumentParams;
-        type TDCCE = TextDocumentContentChangeEvent;
-        fn did_change_with_result(this: &Backend, params: DCTDP) -> Result<CCSCResponse> {
-            fn deconstruct_input(params: DidChangeTextDocumentParams) -> (Url, Vec<TDCCE>) {
-                let DCTDP {
+        type ChangeParams = DidChangeTextDocumentParams;
+        type ContentChange = TextDocumentContentChangeEvent;
+        fn did_change_with_result(this: &Backend, params: ChangeParams) -> Result<CCSCResponse> {
+            fn deconstruct_input(params: DidChangeTextDocumentParams) -> (Url, Vec<ContentChange>) {
+                let ChangeParams {
                     text_document: VersionedTextDocumentIdentifier { uri, .. },
                     content_changes,
                 } = params;
@@ -160,31 +231,38 @@ impl LanguageServer for server::Backend {
             }
             fn reparse_doc(
                 doc: &mut TextDocument,
-                changes: Vec<TDCCE>,
-                result: Url,
-            ) -> Result<CCSCResponse> {
+                changes: Vec<ContentChange>,
+            ) -> Result<Vec<String>> {
                 let log = doc.reparse_with_lsp(changes)?;
                 let logs = vec![format!(
                     "Document '{}' changed:\n{}\n",
                     doc.get_absolute_path().display(),
                     log
                 )];
-                let diagnostics = doc.get_diagnostics()?;
-                let out = CCSCResponse::new(Some(logs), Some((result, diagnostics)));
-                Ok(out)
+                Ok(logs)
             }
 
             let (uri, changes) = deconstruct_input(params);
             let path = utils::get_path(&uri)?;
 
             let mut data = this.get_inner();
-            let doc = data.get_doc_or_ignored(path);
-            let out = match doc {
-                TextDocumentType::Ignored => CCSCResponse::ignore_file(uri),
-                TextDocumentType::Source(doc) => reparse_doc(doc, changes, uri)?,
-                //TextDocumentType::MCP(doc) => reparse_doc(doc, changes, uri)?,
+            let doc = data.get_doc_or_ignored(path.clone());
+            let logs = match doc {
+                TextDocumentType::Ignored => return Ok(CCSCResponse::ignore_file(uri)),
+                TextDocumentType::Source(doc) => reparse_doc(doc, changes)?,
+                //TextDocumentType::MCP(doc) => reparse_doc(doc, changes)?,
             };
-            Ok(out)
+            let inactive_regions = data
+                .insert_included_docs(this.get_parser())
+                .iter()
+                .map(|p| data.get_inactive_regions(p))
+                .collect::<Result<Vec<_>>>()?;
+            let diagnostics = data.publish_diagnostics(&path)?;
+            let related_diagnostics = data.get_changed_diagnostics()?;
+
+            Ok(CCSCResponse::new(Some(logs), Some((uri, diagnostics)))
+                .with_related_diagnostics(related_diagnostics)
+                .with_inactive_regions(inactive_regions))
         }
 
         self.handle_response(did_change_with_result(self, params))
@@ -207,52 +285,643 @@ impl LanguageServer for server::Backend {
             let out = match doc_type {
                 TextDocumentType::Source(doc) => {
                     let tree = doc.get_syntax_tree()?;
-                    let mut cursor = tree.walk();
-                    let mut hover_out = String::new();
+                    let raw = doc.get_source().get_raw();
+                    let mnemonic = asm::find_asm_line(tree, raw.as_bytes(), pos)
+                        .and_then(|line| line.mnemonic)
+                        .filter(|m| m.range.start.character as usize <= pos.column)
+                        .filter(|m| pos.column <= m.range.end.character as usize);
+                    let instruction = mnemonic
+                        .as_ref()
+                        .and_then(|m| asm::PicInstruction::find(&m.text));
+                    if let (Some(mnemonic), Some(instruction)) = (mnemonic.as_ref(), instruction) {
+                        return Ok(Some(Hover {
+                            contents: HoverContents::Markup(MarkupContent {
+                                kind: MarkupKind::Markdown,
+                                value: instruction.get_hover_text(),
+                            }),
+                            range: Some(mnemonic.range),
+                        }));
+                    }
 
-                    hover_out.push_str(cursor.node().kind());
-                    while cursor.goto_first_child_for_point(pos).is_some() {
-                        hover_out.push_str(" > ");
-                        hover_out.push_str(cursor.node().kind());
+                    let source = raw.as_bytes();
+                    if let Some(directive) = directives::find_directive(tree, source, pos) {
+                        return Ok(Some(Hover {
+                            contents: HoverContents::Markup(MarkupContent {
+                                kind: MarkupKind::Markdown,
+                                value: directive.get_hover_text(),
+                            }),
+                            range: Some(directive.range),
+                        }));
                     }
 
-                    Some(Hover {
-                        contents: HoverContents::Array(vec![
-                            MarkedString::String(hover_out),
-                            MarkedString::String(
-                                doc.get_included_files()
-                                    .iter()
-                                    .filter_map(|s| s.to_str().map(|s2| String::from(s2)))
-                                    .reduce(|acc, x| format!("{}\n{}", acc, x))
-                                    .unwrap_or("".to_string()),
-                            ),
-                        ]),
-                        range: Some(utils::get_range(&cursor.node())),
-                    })
+                    None
                 }
                 _ => None,
             };
             Ok(out)
         }
 
+        fn get_device_hover(
+            pos: Point,
+            doc_type: &TextDocumentType,
+            db: &DeviceDatabase,
+        ) -> Result<Option<Hover>> {
+            let doc = match doc_type {
+                TextDocumentType::Source(doc) => doc,
+                TextDocumentType::Ignored => return Ok(None),
+            };
+            let source = doc.get_source().get_raw().as_bytes();
+            let out = symbols::get_identifier_at(doc.get_syntax_tree()?, pos).and_then(|node| {
+                let value = db.get_hover_text(node.utf8_text(source).ok()?)?;
+                Some(Hover {
+                    contents: HoverContents::Markup(MarkupContent {
+                        kind: MarkupKind::Markdown,
+                        value,
+                    }),
+                    range: Some(utils::get_range(&node)),
+                })
+            });
+            Ok(out)
+        }
+        fn get_symbol_map_hover(
+            pos: Point,
+            doc_type: &TextDocumentType,
+            symbol_map: &SymbolMap,
+        ) -> Result<Option<Hover>> {
+            /// Returns the name of the function `node` is part of, e.g. `add` for its parameters
+            fn get_function_name<'a>(node: Node, source: &'a [u8]) -> Option<&'a str> {
+                let mut curr = Some(node);
+                while let Some(node) = curr {
+                    if node.kind() == "function_definition" {
+                        let name = node
+                            .child_by_field_name("declarator")
+                            .and_then(symbols::get_function_declarator)
+                            .and_then(|d| d.child_by_field_name("declarator"))
+                            .and_then(symbols::get_declarator_identifier)?;
+                        return name.utf8_text(source).ok();
+                    }
+                    curr = node.parent();
+                }
+                None
+            }
+
+            let doc = match doc_type {
+                TextDocumentType::Source(doc) => doc,
+                TextDocumentType::Ignored => return Ok(None),
+            };
+            let source = doc.get_source().get_raw().as_bytes();
+            let out = symbols::get_identifier_at(doc.get_syntax_tree()?, pos).and_then(|node| {
+                let name = node.utf8_text(source).ok()?;
+                let value = symbol_map.get_hover_text(get_function_name(node, source), name)?;
+                Some(Hover {
+                    contents: HoverContents::Markup(MarkupContent {
+                        kind: MarkupKind::Markdown,
+                        value,
+                    }),
+                    range: Some(utils::get_range(&node)),
+                })
+            });
+            Ok(out)
+        }
+        fn get_listing_hover(
+            pos: Point,
+            path: &Path,
+            docs: &HashMap<PathBuf, TextDocumentType>,
+            listing: &Listing,
+        ) -> Result<Option<Hover>> {
+            /// Returns the rows of the statement at `pos`, e.g. of an entire `for` loop
+            fn get_statement_rows(tree: &Tree, pos: Point) -> (usize, usize) {
+                let mut curr = tree.root_node().named_descendant_for_point_range(pos, pos);
+                while let Some(node) = curr {
+                    let is_statement = node.kind().ends_with("_statement")
+                        && node.kind() != "compound_statement";
+                    if is_statement || node.kind() == "declaration" {
+                        return (node.start_position().row, node.end_position().row);
+                    }
+                    curr = node.parent();
+                }
+                (pos.row, pos.row)
+            }
+
+            let doc = match symbols::get_source_doc(docs, path) {
+                Some(doc) => doc,
+                None => return Ok(None),
+            };
+            let (start, end) = get_statement_rows(doc.get_syntax_tree()?, pos);
+            let lines = listing.get_disassembly(docs, path, start as u32, end as u32);
+            let out = listing.get_hover_text(&lines).map(|value| Hover {
+                contents: HoverContents::Markup(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value,
+                }),
+                range: None,
+            });
+            Ok(out)
+        }
+        fn get_macro_hover(expansion: Option<(Range, String)>) -> Option<Hover> {
+            let (range, text) = expansion?;
+            Some(Hover {
+                contents: HoverContents::Markup(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value: format!("```c\n{}\n```", text),
+                }),
+                range: Some(range),
+            })
+        }
+
         let (line, character, uri) = deconstruct_input(params);
 
         let data = self.get_inner();
-        let doc_type = data.get_doc(&utils::get_path(&uri)?)?;
+        let path = utils::get_path(&uri)?;
+        let doc_type = data.get_doc(&path)?;
         let pos = Point::new(line as usize, character as usize);
 
-        get_hover_information(pos, doc_type)
+        if let Some(hover) = get_hover_information(pos, doc_type)? {
+            return Ok(Some(hover));
+        }
+        // The device header defines pins etc. as plain numbers, its own hover is more helpful.
+        // Project symbols like a variable `c` are not the carry bit `C` of the device though
+        if let Some(db) = data.get_device_database() {
+            let is_project_symbol = symbols::find_definitions(data.get_docs(), &path, pos)?
+                .iter()
+                .any(|l| utils::get_path(&l.uri).ok().as_ref() != Some(&db.path));
+            if !is_project_symbol {
+                if let Some(hover) = get_device_hover(pos, doc_type, db)? {
+                    return Ok(Some(hover));
+                }
+            }
+        }
+        let device = data.get_mcp().ok().map(|mcp| mcp.device.as_str());
+        let expansion = preprocessor::find_macro_expansion(data.get_docs(), &path, pos, device)?;
+        if let Some(hover) = get_macro_hover(expansion) {
+            return Ok(Some(hover));
+        }
+        if let Some(symbol_map) = data.get_symbol_map() {
+            if let Some(hover) = get_symbol_map_hover(pos, doc_type, symbol_map)? {
+                return Ok(Some(hover));
+            }
+        }
+        match data.get_listing() {
+            Some(listing) => get_listing_hover(pos, &path, data.get_docs(), listing),
+            None => Ok(None),
+        }
+    }
+
+    async fn completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
+        fn deconstruct_input(params: CompletionParams) -> (Position, Url) {
+            let CompletionParams {
+                text_document_position:
+                TextDocumentPositionParams {
+                    position,
+                    text_document: TextDocumentIdentifier { uri },
+                },
+                ..
+            } = params;
+            (position, uri)
+        }
+
+        let (position, uri) = deconstruct_input(params);
+
+        let data = self.get_inner();
+        let path = utils::get_path(&uri)?;
+        let items = completion::get_completion_items(
+            data.get_docs(),
+            &path,
+            utils::get_point(&position),
+            data.get_device_database(),
+        )?;
+
+        Ok(Some(CompletionResponse::Array(items)))
+    }
+
+    async fn signature_help(&self, params: SignatureHelpParams) -> Result<Option<SignatureHelp>> {
+        fn deconstruct_input(params: SignatureHelpParams) -> (Position, Url) {
+            let SignatureHelpParams {
+                text_document_position_params:
+                TextDocumentPositionParams {
+                    position,
+                    text_document: TextDocumentIdentifier { uri },
+                },
+                ..
+            } = params;
+            (position, uri)
+        }
+
+        let (position, uri) = deconstruct_input(params);
+
+        let data = self.get_inner();
+        let path = utils::get_path(&uri)?;
+
+        completion::get_signature_help(data.get_docs(), &path, utils::get_point(&position))
+    }
+
+    async fn goto_definition(
+        &self,
+        params: GotoDefinitionParams,
+    ) -> Result<Option<GotoDefinitionResponse>> {
+        fn deconstruct_input(params: GotoDefinitionParams) -> (Position, Url) {
+            let GotoDefinitionParams {
+                text_document_position_params:
+                TextDocumentPositionParams {
+                    position,
+                    text_document: TextDocumentIdentifier { uri },
+                },
+                ..
+            } = params;
+            (position, uri)
+        }
+
+        let (position, uri) = deconstruct_input(params);
+
+        let data = self.get_inner();
+        let path = utils::get_path(&uri)?;
+        let mut locations =
+            symbols::find_definitions(data.get_docs(), &path, utils::get_point(&position))?;
+
+        let out = match locations.len() {
+            0 => None,
+            1 => Some(GotoDefinitionResponse::Scalar(locations.remove(0))),
+            _ => Some(GotoDefinitionResponse::Array(locations)),
+        };
+        Ok(out)
+    }
+
+    async fn references(&self, params: ReferenceParams) -> Result<Option<Vec<Location>>> {
+        fn deconstruct_input(params: ReferenceParams) -> (Position, Url, bool) {
+            let ReferenceParams {
+                text_document_position:
+                TextDocumentPositionParams {
+                    position,
+                    text_document: TextDocumentIdentifier { uri },
+                },
+                context: ReferenceContext {
+                    include_declaration,
+                },
+                ..
+            } = params;
+            (position, uri, include_declaration)
+        }
+
+        let (position, uri, include_declaration) = deconstruct_input(params);
+
+        let data = self.get_inner();
+        let path = utils::get_path(&uri)?;
+        let locations = symbols::find_references(data.get_docs(), &path, utils::get_point(&position))?
+            .into_iter()
+            .filter(|(_, occurrence)| include_declaration || !occurrence.is_declaration())
+            .map(|(p, occurrence)| utils::get_location(p, occurrence.range))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Some(locations))
+    }
+
+    async fn document_highlight(
+        &self,
+        params: DocumentHighlightParams,
+    ) -> Result<Option<Vec<DocumentHighlight>>> {
+        fn deconstruct_input(params: DocumentHighlightParams) -> (Position, Url) {
+            let DocumentHighlightParams {
+                text_document_position_params:
+                TextDocumentPositionParams {
+                    position,
+                    text_document: TextDocumentIdentifier { uri },
+                },
+                ..
+            } = params;
+            (position, uri)
+        }
+        fn get_highlight_kind(role: SymbolOccurrenceRole) -> DocumentHighlightKind {
+            match role {
+                SymbolOccurrenceRole::Declaration => DocumentHighlightKind::Text,
+                SymbolOccurrenceRole::InitializedDeclaration | SymbolOccurrenceRole::Write => {
+                    DocumentHighlightKind::Write
+                }
+                SymbolOccurrenceRole::Read => DocumentHighlightKind::Read,
+            }
+        }
+
+        let (position, uri) = deconstruct_input(params);
+
+        let data = self.get_inner();
+        let path = utils::get_path(&uri)?;
+        let highlights = symbols::find_highlights(data.get_docs(), &path, utils::get_point(&position))?
+            .into_iter()
+            .map(|occurrence| DocumentHighlight {
+                range: occurrence.range,
+                kind: Some(get_highlight_kind(occurrence.role)),
+            })
+            .collect();
+
+        Ok(Some(highlights))
+    }
+
+    async fn prepare_rename(
+        &self,
+        params: TextDocumentPositionParams,
+    ) -> Result<Option<PrepareRenameResponse>> {
+        let TextDocumentPositionParams {
+            position,
+            text_document: TextDocumentIdentifier { uri },
+        } = params;
+
+        let data = self.get_inner();
+        let path = utils::get_path(&uri)?;
+        let range = symbols::prepare_rename(
+            data.get_docs(),
+            &data.get_include_paths(),
+            &path,
+            utils::get_point(&position),
+        )?;
+
+        Ok(range.map(PrepareRenameResponse::Range))
+    }
+
+    async fn rename(&self, params: RenameParams) -> Result<Option<WorkspaceEdit>> {
+        fn deconstruct_input(params: RenameParams) -> (Position, Url, String) {
+            let RenameParams {
+                text_document_position:
+                TextDocumentPositionParams {
+                    position,
+                    text_document: TextDocumentIdentifier { uri },
+                },
+                new_name,
+                ..
+            } = params;
+            (position, uri, new_name)
+        }
+
+        let (position, uri, new_name) = deconstruct_input(params);
+
+        let data = self.get_inner();
+        let path = utils::get_path(&uri)?;
+
+        symbols::rename(
+            data.get_docs(),
+            &data.get_include_paths(),
+            &path,
+            utils::get_point(&position),
+            &new_name,
+        )
+    }
+
+    async fn symbol(
+        &self,
+        params: WorkspaceSymbolParams,
+    ) -> Result<Option<Vec<SymbolInformation>>> {
+        let WorkspaceSymbolParams { query, .. } = params;
+
+        let data = self.get_inner();
+        let symbols = symbols::find_workspace_symbols(data.get_docs(), &query)?;
+
+        Ok(Some(symbols))
+    }
+
+    async fn execute_command(&self, params: ExecuteCommandParams) -> Result<Option<Value>> {
+        fn deconstruct_input(params: ExecuteCommandParams) -> (String, Vec<Value>) {
+            let ExecuteCommandParams {
+                command, arguments, ..
+            } = params;
+            (command, arguments)
+        }
+        fn get_location(arguments: Vec<Value>) -> Result<Location> {
+            let argument = arguments.into_iter().next().ok_or_else(|| {
+                utils::create_server_error(8, "Missing location argument".to_owned())
+            })?;
+            serde_json::from_value(argument)
+                .map_err(|e| utils::create_server_error(8, format!("Invalid location: {}", e)))
+        }
+
+        let (command, arguments) = deconstruct_input(params);
+        match command.as_str() {
+            preprocessor::EXPAND_MACRO_COMMAND => {
+                let Location { uri, range } = get_location(arguments)?;
+                let edit = {
+                    let data = self.get_inner();
+                    let path = utils::get_path(&uri)?;
+                    let device = data.get_mcp().ok().map(|mcp| mcp.device.as_str());
+                    let point = utils::get_point(&range.start);
+                    let (range, text) =
+                        preprocessor::find_macro_expansion(data.get_docs(), &path, point, device)?
+                            .ok_or_else(|| {
+                                utils::create_server_error(
+                                    8,
+                                    "No macro invocation selected".to_owned(),
+                                )
+                            })?;
+                    WorkspaceEdit::new(HashMap::from([(uri, vec![TextEdit::new(range, text)])]))
+                };
+                self.get_client().apply_edit(edit).await?;
+                Ok(None)
+            }
+            compiler_output::PROJECT_SUMMARY_COMMAND => {
+                let data = self.get_inner();
+                let statistics = data.get_statistics().ok_or_else(|| {
+                    utils::create_server_error(
+                        11,
+                        "No .STA file found. Build the project first".to_owned(),
+                    )
+                })?;
+                let summary = serde_json::to_value(statistics)
+                    .map_err(|e| utils::create_server_error(11, e.to_string()))?;
+                Ok(Some(summary))
+            }
+            _ => Err(utils::create_server_error(
+                8,
+                format!("Unknown command: {}", command),
+            )),
+        }
+    }
+
+    async fn prepare_call_hierarchy(
+        &self,
+        params: CallHierarchyPrepareParams,
+    ) -> Result<Option<Vec<CallHierarchyItem>>> {
+        let CallHierarchyPrepareParams {
+            text_document_position_params:
+                TextDocumentPositionParams {
+                    text_document: TextDocumentIdentifier { uri },
+                    position,
+                },
+            ..
+        } = params;
+
+        let data = self.get_inner();
+        symbols::prepare_call_hierarchy(
+            data.get_docs(),
+            &utils::get_path(&uri)?,
+            utils::get_point(&position),
+            data.get_call_tree(),
+        )
+    }
+
+    async fn incoming_calls(
+        &self,
+        params: CallHierarchyIncomingCallsParams,
+    ) -> Result<Option<Vec<CallHierarchyIncomingCall>>> {
+        let CallHierarchyIncomingCallsParams { item, .. } = params;
+
+        let data = self.get_inner();
+        let calls = symbols::find_incoming_calls(data.get_docs(), &item, data.get_call_tree())?;
+
+        Ok(Some(calls))
+    }
+
+    async fn outgoing_calls(
+        &self,
+        params: CallHierarchyOutgoingCallsParams,
+    ) -> Result<Option<Vec<CallHierarchyOutgoingCall>>> {
+        let CallHierarchyOutgoingCallsParams { item, .. } = params;
+
+        let data = self.get_inner();
+        let calls = symbols::find_outgoing_calls(data.get_docs(), &item, data.get_call_tree())?;
+
+        Ok(Some(calls))
+    }
+
+    async fn code_lens(&self, params: CodeLensParams) -> Result<Option<Vec<CodeLens>>> {
+        let CodeLensParams {
+            text_document: TextDocumentIdentifier { uri },
+            ..
+        } = params;
+
+        let data = self.get_inner();
+        let statistics = match data.get_statistics() {
+            Some(statistics) => statistics,
+            None => return Ok(None),
+        };
+        let out = match data.get_doc(&utils::get_path(&uri)?)? {
+            TextDocumentType::Source(doc) => {
+                Some(compiler_output::get_code_lenses(doc, statistics))
+            }
+            TextDocumentType::Ignored => None,
+        };
+        Ok(out)
+    }
+
+    async fn document_symbol(
+        &self,
+        params: DocumentSymbolParams,
+    ) -> Result<Option<DocumentSymbolResponse>> {
+        let DocumentSymbolParams {
+            text_document: TextDocumentIdentifier { uri },
+            ..
+        } = params;
+
+        let data = self.get_inner();
+        let out = match data.get_doc(&utils::get_path(&uri)?)? {
+            TextDocumentType::Source(doc) => {
+                let symbols = symbols::get_document_symbols(
+                    doc.get_syntax_tree()?,
+                    doc.get_source().get_raw().as_bytes(),
+                    doc.get_inactive_regions(),
+                );
+                Some(DocumentSymbolResponse::Nested(symbols))
+            }
+            TextDocumentType::Ignored => None,
+        };
+        Ok(out)
+    }
+
+    async fn semantic_tokens_full(
+        &self,
+        params: SemanticTokensParams,
+    ) -> Result<Option<SemanticTokensResult>> {
+        let SemanticTokensParams {
+            text_document: TextDocumentIdentifier { uri },
+            ..
+        } = params;
+
+        let mut data = self.get_inner();
+        let path = utils::get_path(&uri)?;
+        let device = data.get_device_database();
+        let tokens = semantic_tokens::get_semantic_tokens(data.get_docs(), &path, device)?;
+        let tokens = data.cache_semantic_tokens(path, semantic_tokens::encode_tokens(&tokens));
+
+        Ok(Some(SemanticTokensResult::Tokens(tokens)))
+    }
+
+    async fn semantic_tokens_full_delta(
+        &self,
+        params: SemanticTokensDeltaParams,
+    ) -> Result<Option<SemanticTokensFullDeltaResult>> {
+        let SemanticTokensDeltaParams {
+            text_document: TextDocumentIdentifier { uri },
+            previous_result_id,
+            ..
+        } = params;
+
+        let mut data = self.get_inner();
+        let path = utils::get_path(&uri)?;
+        let device = data.get_device_database();
+        let tokens = semantic_tokens::get_semantic_tokens(data.get_docs(), &path, device)?;
+        let tokens = semantic_tokens::encode_tokens(&tokens);
+        let edits = data
+            .get_cached_semantic_tokens(&path, &previous_result_id)
+            .map(|previous| semantic_tokens::get_semantic_tokens_edits(&previous.data, &tokens));
+        let tokens = data.cache_semantic_tokens(path, tokens);
+
+        let out = match edits {
+            Some(edits) => SemanticTokensFullDeltaResult::TokensDelta(SemanticTokensDelta {
+                result_id: tokens.result_id,
+                edits,
+            }),
+            None => SemanticTokensFullDeltaResult::Tokens(tokens),
+        };
+        Ok(Some(out))
+    }
+
+    async fn semantic_tokens_range(
+        &self,
+        params: SemanticTokensRangeParams,
+    ) -> Result<Option<SemanticTokensRangeResult>> {
+        let SemanticTokensRangeParams {
+            text_document: TextDocumentIdentifier { uri },
+            range,
+            ..
+        } = params;
+
+        let data = self.get_inner();
+        let path = utils::get_path(&uri)?;
+        let device = data.get_device_database();
+        let tokens = semantic_tokens::get_semantic_tokens(data.get_docs(), &path, device)?
+            .into_iter()
+            .filter(|t| {
+                let start = utils::get_position(t.start);
+                range.start <= start && start < range.end
+            })
+            .collect::<Vec<_>>();
+
+        Ok(Some(SemanticTokensRangeResult::Tokens(SemanticTokens {
+            result_id: None,
+            data: semantic_tokens::encode_tokens(&tokens),
+        })))
     }
 }
 
 #[tokio::main]
 async fn main() {
-    let stdin = tokio::io::stdin();
+    let arguments = std::env::args().skip(1).collect::<Vec<_>>();
+    if arguments.first().map(|a| a.as_str()) == Some(compiler_output::HEX_SUBCOMMAND) {
+        std::process::exit(compiler_output::run_hex_subcommand(&arguments[1..]));
+    }
+
+    let stdin = tokio::io::BufReader::new(tokio::io::stdin());
     let stdout = tokio::io::stdout();
 
-    let (service, messages) = LspService::new(server::Backend::new);
-    Server::new(stdin, stdout)
-        .interleave(messages)
+    // Custom requests are answered before the messages reach tower-lsp
+    let data = Arc::new(Mutex::new(BackendInner::default()));
+    let (input, forwarded) = tokio::io::duplex(64 * 1024);
+    let (responses, custom_responses) = mpsc::unbounded();
+    tokio::spawn(server::intercept_custom_requests(
+        stdin,
+        input,
+        data.clone(),
+        responses,
+    ));
+
+    let (service, messages) = LspService::new(|client| Backend::new(client, data));
+    Server::new(forwarded, stdout)
+        .interleave(stream::select(messages, custom_responses))
         .serve(service)
         .await;
 }
@@ -25,11 +25,10 @@ impl MPLABFile {
 
 // TODO: Implement more fields
 pub struct MPLABProjectConfig {
-    pub file_version: String,
     pub device: String,
+    /// `dir_inc` of `PATH_INFO`, relative to the project directory or absolute
+    pub include_directories: Vec<String>,
     pub files: HashMap<String, MPLABFile>,
-    pub suite_guid: String,
-    pub tool_settings: Vec<(String, String)>,
 }
 
 type SResult<T> = Result<T, String>;
@@ -41,9 +40,8 @@ impl MPLABProjectConfig {
 
     pub fn from_ini(ini: &Ini) -> SResult<Self> {
         fn get_section<'i>(ini: &'i Ini, section: &str) -> SResult<&'i Properties> {
-            Ok(ini
-                .section(Some(section))
-                .ok_or(format!("Section '{}' not found in .mcp", section))?)
+            ini.section(Some(section))
+                .ok_or(format!("Section '{}' not found in .mcp", section))
         }
         fn get_field(section: &Properties, field: &str) -> SResult<String> {
             Ok(String::from(
@@ -52,11 +50,15 @@ impl MPLABProjectConfig {
                     .ok_or(format!("INI field '{}' not found...", field))?,
             ))
         }
-        fn get_all_fields_in_section(ini: &Ini, section: &str) -> SResult<Vec<(String, String)>> {
-            Ok(get_section(ini, section)?
-                .iter()
-                .map(|(k, v)| (String::from(k), String::from(v)))
-                .collect())
+        fn get_include_directories(ini: &Ini) -> Vec<String> {
+            ini.section(Some("PATH_INFO"))
+                .and_then(|section| section.get("dir_inc"))
+                .unwrap_or_default()
+                .split(';')
+                .map(str::trim)
+                .filter(|d| !d.is_empty())
+                .map(str::to_owned)
+                .collect()
         }
         fn get_files(ini: &Ini) -> SResult<HashMap<String, MPLABFile>> {
             type MPLABMap<'a> = HashMap<&'a str, MPLABFile>;
@@ -104,21 +106,15 @@ impl MPLABProjectConfig {
         }
 
         let header = get_section(ini, "HEADER")?;
-        let file_version = get_field(header, "file_version")?;
         let device = get_field(header, "device")?;
+        let include_directories = get_include_directories(ini);
 
-        let suite_info = get_section(ini, "SUITE_INFO")?;
-        let suite_guid = get_field(suite_info, "suite_guid")?;
-
-        let tool_settings = get_all_fields_in_section(ini, "TOOL_SETTINGS")?;
         let files = get_files(ini)?;
 
         Ok(Self {
-            file_version,
             device,
+            include_directories,
             files,
-            suite_guid,
-            tool_settings,
         })
     }
 }
@@ -0,0 +1,196 @@
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::path::PathBuf;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::notification::Notification;
+use tower_lsp::lsp_types::{Position, TextDocumentIdentifier};
+use tree_sitter::Node;
+
+use crate::directives::{CcsDirective, DirectiveKind};
+use crate::docs::text_document_type::TextDocumentTypeTrait;
+use crate::docs::TextDocumentType;
+use crate::preprocessor;
+use crate::symbols;
+
+type Docs = HashMap<PathBuf, TextDocumentType>;
+
+lazy_static! {
+    static ref DEVICE_HEADER_MATCHER: Regex =
+        Regex::new(r"^<(?P<device>\d+[A-Za-z]+\d+[A-Za-z]*)\.[hH]>$").unwrap();
+}
+
+/// Lines of a conditional branch which is not compiled
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InactiveRegion {
+    /// From the start of the first line to the end of the last line
+    pub range: tower_lsp::lsp_types::Range,
+    pub bytes: Range<usize>,
+}
+
+impl InactiveRegion {
+    pub fn from_rows(source: &[u8], rows: Range<usize>) -> Self {
+        let mut line_starts = vec![0];
+        line_starts.extend(
+            source
+                .iter()
+                .enumerate()
+                .filter(|(_, c)| **c == b'\n')
+                .map(|(idx, _)| idx + 1),
+        );
+        let get_line_start = |row: usize| line_starts.get(row).copied().unwrap_or(source.len());
+
+        let last_row = rows.end - 1;
+        let start = get_line_start(rows.start);
+        let end = get_line_start(last_row + 1).max(start);
+        let line = &source[get_line_start(last_row).min(end)..end];
+        let last_column = line
+            .iter()
+            .rposition(|c| *c != b'\n' && *c != b'\r')
+            .map_or(0, |idx| idx + 1);
+
+        Self {
+            range: tower_lsp::lsp_types::Range::new(
+                Position::new(rows.start as u32, 0),
+                Position::new(last_row as u32, last_column as u32),
+            ),
+            bytes: start..end,
+        }
+    }
+
+    pub fn contains_line(&self, line: u32) -> bool {
+        self.range.start.line <= line && line <= self.range.end.line
+    }
+}
+
+/// Returns true, if `line` is part of a branch which is not compiled
+pub fn is_inactive_line(regions: &[InactiveRegion], line: u32) -> bool {
+    regions.iter().any(|r| r.contains_line(line))
+}
+
+/// Sent whenever the inactive regions of a document change
+pub enum InactiveRegionsNotification {}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InactiveRegionsParams {
+    pub text_document: TextDocumentIdentifier,
+    pub regions: Vec<tower_lsp::lsp_types::Range>,
+}
+
+impl Notification for InactiveRegionsNotification {
+    type Params = InactiveRegionsParams;
+    const METHOD: &'static str = "ccsc/inactiveRegions";
+}
+
+/// Returns the device the project is compiled for. The device header (`#include <16F883.h>`)
+/// and `#device` take precedence over the device of the MPLAB project
+pub fn find_device(docs: &Docs, project_device: Option<&str>) -> Option<String> {
+    fn find_in_node(node: Node, source: &[u8]) -> Option<String> {
+        match node.kind() {
+            "preproc_include" => {
+                let path = node.child_by_field_name("path")?.utf8_text(source).ok()?;
+                let captures = DEVICE_HEADER_MATCHER.captures(path)?;
+                Some(captures["device"].to_owned())
+            }
+            "preproc_device" => CcsDirective::from_node(node, source)
+                .filter(|d| d.kind == DirectiveKind::Device)
+                .and_then(|d| d.target)
+                .map(|t| t.text),
+            _ => {
+                let mut cursor = node.walk();
+                let out = node
+                    .named_children(&mut cursor)
+                    .find_map(|child| find_in_node(child, source));
+                out
+            }
+        }
+    }
+
+    let mut paths = docs.keys().collect::<Vec<_>>();
+    paths.sort();
+    paths
+        .into_iter()
+        .filter_map(|p| symbols::get_source_doc(docs, p))
+        .find_map(|doc| {
+            let tree = doc.get_syntax_tree().ok()?;
+            find_in_node(tree.root_node(), doc.get_source().get_raw().as_bytes())
+        })
+        .or_else(|| project_device.map(String::from))
+}
+
+/// Evaluates the preprocessor directives of every document. Each document starts with the
+/// predefined macros and the macros of the files it includes. Returns the paths of the
+/// documents whose inactive regions changed
+pub fn update_inactive_regions(docs: &mut Docs, project_device: Option<&str>) -> Vec<PathBuf> {
+    let predefined = preprocessor::get_project_predefined_macros(docs, project_device);
+
+    let mut cache = HashMap::new();
+    let regions = docs
+        .keys()
+        .filter_map(|p| {
+            let mut visiting = HashSet::from([p.clone()]);
+            preprocessor::evaluate_document(docs, p, &predefined, &mut cache, &mut visiting)
+                .map(|(_, regions)| (p.clone(), regions))
+        })
+        .collect::<Vec<_>>();
+
+    let mut out = vec![];
+    for (path, regions) in regions {
+        if let Some(TextDocumentType::Source(doc)) = docs.get_mut(&path) {
+            if doc.update_inactive_regions(regions) {
+                out.push(path);
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::test_utils::create_docs;
+
+    #[test]
+    fn test_macros_of_included_files() {
+        let mut docs = create_docs(&[
+            (
+                "/project/main.c",
+                "#include \"config.h\"\n#ifdef DEBUG\nint a;\n#else\nint b;\n#endif\n",
+            ),
+            ("/project/config.h", "#define DEBUG\n"),
+        ]);
+
+        let changed = update_inactive_regions(&mut docs, None);
+        assert_eq!(changed, vec![PathBuf::from("/project/main.c")]);
+        let doc = symbols::get_source_doc(&docs, Path::new("/project/main.c")).unwrap();
+        assert_eq!(doc.get_inactive_regions()[0].range.start.line, 4);
+        let names = doc
+            .get_symbol_index()
+            .definitions
+            .iter()
+            .map(|def| def.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["a"]);
+
+        assert!(update_inactive_regions(&mut docs, None).is_empty());
+    }
+
+    #[test]
+    fn test_region_from_rows() {
+        let source = b"#if 0\nint a;\r\nint bc;\n#endif\n";
+        let region = InactiveRegion::from_rows(source, 1..3);
+
+        assert_eq!(
+            region.range,
+            tower_lsp::lsp_types::Range::new(Position::new(1, 0), Position::new(2, 7))
+        );
+        assert_eq!(&source[region.bytes.clone()], b"int a;\r\nint bc;\n");
+        assert!(region.contains_line(2));
+        assert!(!region.contains_line(3));
+    }
+}
@@ -0,0 +1,417 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use tower_lsp::jsonrpc::Result;
+use tree_sitter::Point;
+
+use crate::docs::text_document_type::TextDocumentTypeTrait;
+use crate::docs::TextDocumentType;
+use crate::preprocessor::{self, MacroDefinition, MacroTable};
+use crate::symbols;
+use crate::utils;
+
+type Docs = HashMap<PathBuf, TextDocumentType>;
+
+lazy_static! {
+    static ref DEFINE_MATCHER: Regex = Regex::new(r"^\s*#\s*define\s+$").unwrap();
+}
+
+/// Replaces a macro invocation with its expansion. Takes a `Location` selecting the invocation
+pub const EXPAND_MACRO_COMMAND: &str = "ccsc.expandMacro";
+
+/// A macro invocation and the text it expands to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MacroExpansion {
+    pub name: String,
+    /// Byte range of the invocation including its arguments
+    pub bytes: Range<usize>,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token<'a> {
+    Word(&'a str),
+    /// Numbers, string and character literals
+    Literal(&'a str),
+    /// Whitespace, comments and line continuations
+    Space(&'a str),
+    Punctuation(&'a str),
+}
+
+impl<'a> Token<'a> {
+    fn get_text(&self) -> &'a str {
+        match self {
+            Token::Word(text)
+            | Token::Literal(text)
+            | Token::Space(text)
+            | Token::Punctuation(text) => text,
+        }
+    }
+}
+
+/// Returns the expansion of the macro invocation at `point`, using the macros defined there
+pub fn find_macro_expansion(
+    docs: &Docs,
+    path: &Path,
+    point: Point,
+    project_device: Option<&str>,
+) -> Result<Option<(tower_lsp::lsp_types::Range, String)>> {
+    let doc = match symbols::get_source_doc(docs, path) {
+        Some(doc) => doc,
+        None => return Ok(None),
+    };
+    let source = doc.get_source();
+    let byte = source.get_offset_for_point(&point)?;
+    let expansion = preprocessor::get_macros_at(docs, path, byte, project_device)
+        .and_then(|macros| expand_invocation_at(source.get_raw(), byte, &macros));
+
+    let out = match expansion {
+        Some(expansion) => {
+            let start = source.get_point_from_byte_idx(expansion.bytes.start)?;
+            let end = source.get_point_from_byte_idx(expansion.bytes.end)?;
+            let range = tower_lsp::lsp_types::Range::new(
+                utils::get_position(start),
+                utils::get_position(end),
+            );
+            Some((range, expansion.text))
+        }
+        None => None,
+    };
+    Ok(out)
+}
+
+/// Expands the macro named at `byte`. On the name of a `#define`, the body of the macro is
+/// expanded instead
+pub fn expand_invocation_at(
+    source: &str,
+    byte: usize,
+    macros: &MacroTable,
+) -> Option<MacroExpansion> {
+    let bytes = source.as_bytes();
+    let is_word = |c: u8| c.is_ascii_alphanumeric() || c == b'_';
+    let mut start = byte.min(bytes.len());
+    while start > 0 && is_word(bytes[start - 1]) {
+        start -= 1;
+    }
+    let mut end = start;
+    while end < bytes.len() && is_word(bytes[end]) {
+        end += 1;
+    }
+    if start == end || bytes[start].is_ascii_digit() {
+        return None;
+    }
+
+    let name = &source[start..end];
+    let definition = macros.get(name)?;
+    let line_start = source[..start].rfind('\n').map_or(0, |idx| idx + 1);
+    if DEFINE_MATCHER.is_match(&source[line_start..start]) {
+        // Parameters are not expanded even if they share the name of a macro
+        let mut hidden = vec![definition.name.clone()];
+        hidden.extend(definition.parameters.iter().flatten().cloned());
+        return Some(MacroExpansion {
+            name: name.to_owned(),
+            bytes: start..end,
+            text: expand(&definition.body, macros, &mut hidden),
+        });
+    }
+
+    let tokens = tokenize(&source[start..]);
+    let (text, end_idx) = expand_invocation(&tokens, 0, definition, macros, &mut vec![])?;
+    let len = tokens[..end_idx]
+        .iter()
+        .map(|t| t.get_text().len())
+        .sum::<usize>();
+    Some(MacroExpansion {
+        name: name.to_owned(),
+        bytes: start..start + len,
+        text,
+    })
+}
+
+/// Expands every macro in `text` recursively. Macros in `hidden` are currently being expanded
+/// and are left as they are
+pub fn expand(text: &str, macros: &MacroTable, hidden: &mut Vec<String>) -> String {
+    let tokens = tokenize(text);
+    let mut out = String::new();
+    let mut idx = 0;
+    while idx < tokens.len() {
+        let token = tokens[idx];
+        idx += 1;
+        let definition = match token {
+            Token::Word(name) => macros.get(name).filter(|d| !hidden.contains(&d.name)),
+            Token::Space(_) => {
+                if !out.is_empty() && !out.ends_with(' ') {
+                    out.push(' ');
+                }
+                continue;
+            }
+            _ => None,
+        };
+        match definition.and_then(|d| expand_invocation(&tokens, idx - 1, d, macros, hidden)) {
+            Some((text, end)) => {
+                out.push_str(&text);
+                idx = end;
+            }
+            None => out.push_str(token.get_text()),
+        }
+    }
+    out.trim_end().to_owned()
+}
+
+/// Expands the invocation of `definition` at `tokens[start]`. Returns the expansion and the
+/// index of the first token after the invocation. `None` if the arguments do not match
+fn expand_invocation(
+    tokens: &[Token],
+    start: usize,
+    definition: &MacroDefinition,
+    macros: &MacroTable,
+    hidden: &mut Vec<String>,
+) -> Option<(String, usize)> {
+    let (body, end) = match &definition.parameters {
+        None => (definition.body.clone(), start + 1),
+        Some(parameters) => {
+            let (mut arguments, end) = parse_arguments(tokens, start + 1)?;
+            if parameters.is_empty() && arguments.len() == 1 && arguments[0].is_empty() {
+                arguments.clear();
+            }
+            if parameters.len() != arguments.len() {
+                return None;
+            }
+            let body = substitute(&definition.body, parameters, &arguments, macros, hidden);
+            (body, end)
+        }
+    };
+
+    hidden.push(definition.name.clone());
+    let out = expand(&body, macros, hidden);
+    hidden.pop();
+    Some((out, end))
+}
+
+/// Parses the parenthesized arguments starting at `tokens[start]`. Returns the arguments and
+/// the index of the first token after the closing parenthesis
+fn parse_arguments(tokens: &[Token], start: usize) -> Option<(Vec<String>, usize)> {
+    let mut idx = start;
+    while let Some(Token::Space(_)) = tokens.get(idx) {
+        idx += 1;
+    }
+    if tokens.get(idx) != Some(&Token::Punctuation("(")) {
+        return None;
+    }
+
+    let mut out = vec![];
+    let mut argument = String::new();
+    let mut depth = 0;
+    for (offset, token) in tokens[idx..].iter().enumerate() {
+        match token {
+            Token::Punctuation("(") => {
+                depth += 1;
+                if depth == 1 {
+                    continue;
+                }
+            }
+            Token::Punctuation(")") => {
+                depth -= 1;
+                if depth == 0 {
+                    out.push(argument.trim().to_owned());
+                    return Some((out, idx + offset + 1));
+                }
+            }
+            Token::Punctuation(",") if depth == 1 => {
+                out.push(argument.trim().to_owned());
+                argument.clear();
+                continue;
+            }
+            _ => {}
+        }
+        argument.push_str(token.get_text());
+    }
+    None
+}
+
+/// Replaces the parameters inside the body of a function-like macro. Arguments are expanded
+/// unless they are stringified (`#x`) or pasted (`x ## y`)
+fn substitute(
+    body: &str,
+    parameters: &[String],
+    arguments: &[String],
+    macros: &MacroTable,
+    hidden: &mut Vec<String>,
+) -> String {
+    let tokens = tokenize(body);
+    let get_argument = |token: Option<&Token>| match token {
+        Some(Token::Word(word)) => parameters
+            .iter()
+            .position(|p| p == word)
+            .map(|idx| arguments[idx].as_str()),
+        _ => None,
+    };
+    let skip_space = |mut idx: usize| {
+        while let Some(Token::Space(_)) = tokens.get(idx) {
+            idx += 1;
+        }
+        idx
+    };
+
+    let mut out = String::new();
+    let mut is_pasting = false;
+    let mut idx = 0;
+    while idx < tokens.len() {
+        let token = tokens[idx];
+        idx += 1;
+        match token {
+            Token::Punctuation("##") => {
+                out.truncate(out.trim_end().len());
+                is_pasting = true;
+                idx = skip_space(idx);
+                continue;
+            }
+            Token::Punctuation("#") => {
+                let next = skip_space(idx);
+                if let Some(argument) = get_argument(tokens.get(next)) {
+                    let escaped = argument.replace('\\', "\\\\").replace('"', "\\\"");
+                    out.push_str(&format!("\"{}\"", escaped));
+                    idx = next + 1;
+                    is_pasting = false;
+                    continue;
+                }
+            }
+            _ => {}
+        }
+
+        let is_pasted =
+            is_pasting || tokens.get(skip_space(idx)) == Some(&Token::Punctuation("##"));
+        is_pasting = false;
+        match get_argument(Some(&token)) {
+            Some(argument) if is_pasted => out.push_str(argument),
+            Some(argument) => out.push_str(&expand(argument, macros, hidden)),
+            None => out.push_str(token.get_text()),
+        }
+    }
+    out
+}
+
+fn tokenize(text: &str) -> Vec<Token<'_>> {
+    let bytes = text.as_bytes();
+    let is_word = |c: u8| c.is_ascii_alphanumeric() || c == b'_';
+    let mut out = vec![];
+    let mut idx = 0;
+    while idx < bytes.len() {
+        let start = idx;
+        let rest = &text[idx..];
+        let c = bytes[idx];
+        let token = if c.is_ascii_alphabetic() || c == b'_' {
+            while idx < bytes.len() && is_word(bytes[idx]) {
+                idx += 1;
+            }
+            Token::Word
+        } else if c.is_ascii_digit() {
+            while idx < bytes.len() && (is_word(bytes[idx]) || bytes[idx] == b'.') {
+                idx += 1;
+            }
+            Token::Literal
+        } else if c == b'"' || c == b'\'' {
+            idx += 1;
+            while idx < bytes.len() && bytes[idx] != c && bytes[idx] != b'\n' {
+                idx += if bytes[idx] == b'\\' { 2 } else { 1 };
+            }
+            idx = (idx + 1).min(bytes.len());
+            Token::Literal
+        } else if rest.starts_with("//") {
+            idx += rest.find('\n').unwrap_or(rest.len());
+            Token::Space
+        } else if let Some(comment) = rest.strip_prefix("/*") {
+            idx += comment.find("*/").map_or(rest.len(), |end| end + 4);
+            Token::Space
+        } else if c.is_ascii_whitespace() || rest.starts_with("\\\n") || rest.starts_with("\\\r\n")
+        {
+            idx += 1;
+            while idx < bytes.len() && bytes[idx].is_ascii_whitespace() {
+                idx += 1;
+            }
+            Token::Space
+        } else if rest.starts_with("##") {
+            idx += 2;
+            Token::Punctuation
+        } else {
+            idx += rest.chars().next().map_or(1, char::len_utf8);
+            Token::Punctuation
+        };
+        out.push(token(&text[start..idx.min(bytes.len())]));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_macros(definitions: &[(&str, Option<&[&str]>, &str)]) -> MacroTable {
+        let mut out = MacroTable::new(true);
+        for (name, parameters, body) in definitions {
+            out.define(MacroDefinition {
+                name: name.to_string(),
+                parameters: parameters.map(|p| p.iter().map(|p| p.to_string()).collect()),
+                body: body.to_string(),
+            });
+        }
+        out
+    }
+
+    #[test]
+    fn test_expand_recursively() {
+        let macros = create_macros(&[
+            ("PORTB", None, "(*(int8*)0x06)"),
+            ("BIT", Some(&["reg", "n"]), "((reg) & (1 << (n)))"),
+            ("LED", None, "BIT(PORTB, LED_PIN)"),
+            ("LED_PIN", None, "3"),
+            ("SELF", None, "SELF + 1"),
+        ]);
+
+        assert_eq!(
+            expand("if (LED) {}", &macros, &mut vec![]),
+            "if ((((*(int8*)0x06)) & (1 << (3)))) {}"
+        );
+        assert_eq!(expand("SELF", &macros, &mut vec![]), "SELF + 1");
+        // Function macros are only expanded when invoked
+        assert_eq!(
+            expand("BIT + \"LED\"", &macros, &mut vec![]),
+            "BIT + \"LED\""
+        );
+    }
+
+    #[test]
+    fn test_stringify_and_paste() {
+        let macros = create_macros(&[
+            ("NAME", Some(&["x"]), "#x"),
+            ("REG", Some(&["n"]), "TMR ## n"),
+            ("ONE", None, "1"),
+        ]);
+
+        assert_eq!(expand("NAME(ONE)", &macros, &mut vec![]), "\"ONE\"");
+        assert_eq!(expand("REG(ONE)", &macros, &mut vec![]), "TMRONE");
+    }
+
+    #[test]
+    fn test_expand_invocation_at() {
+        let macros = create_macros(&[
+            ("MAX", Some(&["a", "b"]), "((a) > (b) ? (a) : (b))"),
+            ("LIMIT", None, "10"),
+        ]);
+        let source = "#define MAX(a, b) ((a) > (b) ? (a) : (b))\nx = MAX(f(1, 2),\n  LIMIT);\n";
+
+        let byte = source.find("MAX(f").unwrap() + 1;
+        let expansion = expand_invocation_at(source, byte, &macros).unwrap();
+        assert_eq!(&source[expansion.bytes], "MAX(f(1, 2),\n  LIMIT)");
+        assert_eq!(expansion.text, "((f(1, 2)) > (10) ? (f(1, 2)) : (10))");
+
+        let expansion = expand_invocation_at(source, 9, &macros).unwrap();
+        assert_eq!(expansion.bytes, 8..11);
+        assert_eq!(expansion.text, "((a) > (b) ? (a) : (b))");
+
+        assert_eq!(expand_invocation_at(source, 1, &macros), None);
+    }
+}
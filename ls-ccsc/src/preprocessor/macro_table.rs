@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::symbols;
+
+lazy_static! {
+    static ref DEVICE_MATCHER: Regex = Regex::new(
+        r"(?i)^(?:PIC)?(?P<family>\d+)(?P<kind>[A-Z]+)(?P<number>\d+)(?P<suffix>[A-Z]*)$"
+    )
+    .unwrap();
+}
+
+/// A macro defined by `#define`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MacroDefinition {
+    pub name: String,
+    /// `None` for object-like macros
+    pub parameters: Option<Vec<String>>,
+    pub body: String,
+}
+
+/// The macros defined at some point of a translation unit
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct MacroTable {
+    macros: HashMap<String, MacroDefinition>,
+    case_sensitive: bool,
+}
+
+impl MacroTable {
+    pub fn new(case_sensitive: bool) -> Self {
+        Self {
+            macros: HashMap::new(),
+            case_sensitive,
+        }
+    }
+
+    pub fn define(&mut self, definition: MacroDefinition) {
+        let key = symbols::get_symbol_key(&definition.name, self.case_sensitive);
+        self.macros.insert(key, definition);
+    }
+
+    pub fn undefine(&mut self, name: &str) {
+        self.macros
+            .remove(&symbols::get_symbol_key(name, self.case_sensitive));
+    }
+
+    pub fn get(&self, name: &str) -> Option<&MacroDefinition> {
+        self.macros
+            .get(&symbols::get_symbol_key(name, self.case_sensitive))
+    }
+
+    pub fn is_defined(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Adds the macros of `other`. Macros of `other` replace macros of the same name
+    pub fn extend(&mut self, other: &MacroTable) {
+        for definition in other.macros.values() {
+            self.define(definition.clone());
+        }
+    }
+}
+
+/// Returns the macros the compiler predefines for `device` (e.g. `PIC16F883`): the compiler
+/// (`__PCM__`), the device number (`__DEVICE__`) and the device itself (`__16F883`)
+pub fn get_predefined_macros(device: Option<&str>, case_sensitive: bool) -> MacroTable {
+    fn create_macro(name: &str, body: &str) -> MacroDefinition {
+        MacroDefinition {
+            name: name.to_owned(),
+            parameters: None,
+            body: body.to_owned(),
+        }
+    }
+
+    let mut out = MacroTable::new(case_sensitive);
+    // Defined by every device header, which is not part of the project
+    out.define(create_macro("TRUE", "1"));
+    out.define(create_macro("FALSE", "0"));
+
+    let captures = match device.and_then(|d| DEVICE_MATCHER.captures(d.trim())) {
+        Some(captures) => captures,
+        None => return out,
+    };
+    let family = &captures["family"];
+    let kind = captures["kind"].to_uppercase();
+    let number = &captures["number"];
+    let suffix = captures["suffix"].to_uppercase();
+
+    let compiler = match family {
+        "18" => "__PCH__",
+        "24" | "30" | "33" => "__PCD__",
+        // Baseline devices with 12-bit instructions
+        "10" => "__PCB__",
+        "12" | "16" if kind == "F" && number.len() == 3 && number.starts_with('5') => "__PCB__",
+        _ => "__PCM__",
+    };
+    out.define(create_macro(compiler, "1"));
+    out.define(create_macro("__DEVICE__", number.trim_start_matches('0')));
+    out.define(create_macro(
+        &format!("__{}{}{}{}", family, kind, number, suffix),
+        "1",
+    ));
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_predefined_macros() {
+        let macros = get_predefined_macros(Some("PIC16F883"), false);
+
+        assert!(macros.is_defined("__PCM__"));
+        assert!(macros.is_defined("__16F883"));
+        assert!(macros.is_defined("__16f883"));
+        assert!(!macros.is_defined("__PCH__"));
+        assert_eq!(macros.get("__DEVICE__").unwrap().body, "883");
+
+        let macros = get_predefined_macros(Some("18F4520"), true);
+        assert!(macros.is_defined("__PCH__"));
+        assert!(!macros.is_defined("__18f4520"));
+    }
+}
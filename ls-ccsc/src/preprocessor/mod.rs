@@ -0,0 +1,11 @@
+pub use crate::preprocessor::inactive_regions::*;
+pub use crate::preprocessor::macro_expansion::*;
+pub use crate::preprocessor::macro_table::*;
+pub use crate::preprocessor::preprocessor_evaluator::*;
+pub use crate::preprocessor::project_macros::*;
+
+pub mod inactive_regions;
+pub mod macro_expansion;
+pub mod macro_table;
+pub mod preprocessor_evaluator;
+pub mod project_macros;
@@ -0,0 +1,429 @@
+use std::convert::TryFrom;
+use std::path::Path;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use tree_sitter::{Node, Parser};
+
+use crate::docs::IncludeDirective;
+use crate::preprocessor::{InactiveRegion, MacroDefinition, MacroTable};
+
+lazy_static! {
+    static ref NUMBER_MATCHER: Regex = Regex::new(
+        r"^(?:0[xX](?P<hex>[0-9a-fA-F]+)|0[bB](?P<bin>[01]+)|(?P<oct>0[0-7]*)|(?P<dec>[1-9]\d*))[uUlL]*$"
+    )
+    .unwrap();
+    static ref WORD_MATCHER: Regex = Regex::new(r"[A-Za-z_]\w*").unwrap();
+}
+
+/// Macros may expand to other macros, but not endlessly
+const MAX_EXPANSION_DEPTH: usize = 16;
+
+/// Walks a syntax tree like the preprocessor does: macros are (un)defined in order, only the
+/// active branch of `#if`, `#ifdef`, ... is entered
+pub struct PreprocessorEvaluator<'a> {
+    source: &'a [u8],
+    /// The `#include`s of the document, resolved against the project
+    includes: &'a [IncludeDirective],
+    macros: MacroTable,
+    inactive_regions: Vec<InactiveRegion>,
+    /// Nodes starting at or after this byte are not visited
+    stop_byte: usize,
+    /// Returns the macros defined by an included file
+    get_included_macros: &'a mut dyn FnMut(&Path) -> Option<MacroTable>,
+}
+
+impl<'a> PreprocessorEvaluator<'a> {
+    pub fn new(
+        source: &'a [u8],
+        includes: &'a [IncludeDirective],
+        macros: MacroTable,
+        get_included_macros: &'a mut dyn FnMut(&Path) -> Option<MacroTable>,
+    ) -> Self {
+        Self {
+            source,
+            includes,
+            macros,
+            inactive_regions: vec![],
+            stop_byte: usize::MAX,
+            get_included_macros,
+        }
+    }
+
+    /// Returns the macros defined at the end of `root` and the branches which are not compiled
+    pub fn evaluate(mut self, root: Node) -> (MacroTable, Vec<InactiveRegion>) {
+        self.visit(root);
+        (self.macros, self.inactive_regions)
+    }
+
+    /// Returns the macros defined at `byte`
+    pub fn evaluate_until(mut self, root: Node, byte: usize) -> MacroTable {
+        self.stop_byte = byte;
+        self.visit(root);
+        self.macros
+    }
+
+    fn get_text(&self, node: Node) -> &'a str {
+        node.utf8_text(self.source).unwrap_or_default()
+    }
+
+    fn visit(&mut self, node: Node) {
+        if node.start_byte() >= self.stop_byte {
+            return;
+        }
+        match node.kind() {
+            "preproc_def" | "preproc_function_def" => self.define(node),
+            "preproc_call" => {
+                let directive = node
+                    .child_by_field_name("directive")
+                    .map(|d| self.get_text(d).trim_start_matches('#').trim());
+                let argument = node
+                    .child_by_field_name("argument")
+                    .map(|a| self.get_text(a).trim());
+                if let (Some(directive), Some(argument)) = (directive, argument) {
+                    if directive.eq_ignore_ascii_case("undef") {
+                        let name = argument.split_whitespace().next().unwrap_or_default();
+                        self.macros.undefine(name);
+                    }
+                }
+            }
+            "preproc_include" => self.include(node),
+            "preproc_if" | "preproc_ifdef" => self.visit_conditional(node),
+            _ => {
+                let mut cursor = node.walk();
+                for child in node.named_children(&mut cursor) {
+                    self.visit(child);
+                }
+            }
+        }
+    }
+
+    fn define(&mut self, node: Node) {
+        let name = match node.child_by_field_name("name") {
+            Some(name) => self.get_text(name).to_owned(),
+            None => return,
+        };
+        let parameters = node.child_by_field_name("parameters").map(|parameters| {
+            let mut cursor = parameters.walk();
+            let out = parameters
+                .named_children(&mut cursor)
+                .map(|p| self.get_text(p).to_owned())
+                .collect();
+            out
+        });
+        let body = node
+            .child_by_field_name("value")
+            .map(|v| self.get_text(v).trim().to_owned())
+            .unwrap_or_default();
+
+        self.macros.define(MacroDefinition {
+            name,
+            parameters,
+            body,
+        });
+    }
+
+    fn include(&mut self, node: Node) {
+        let row = node.start_position().row;
+        let path = self
+            .includes
+            .iter()
+            .find(|i| i.range.start.line as usize == row)
+            .and_then(|i| i.resolved_path.as_deref());
+        if let Some(macros) = path.and_then(|p| (self.get_included_macros)(p)) {
+            self.macros.extend(&macros);
+        }
+    }
+
+    /// Enters the first branch whose condition holds. Branches following a condition which
+    /// cannot be evaluated are all entered, so that no code is hidden by mistake
+    fn visit_conditional(&mut self, node: Node) {
+        let endif_row = match node.child(node.child_count().saturating_sub(1)) {
+            Some(endif) if endif.kind() == "#endif" && !endif.is_missing() => {
+                endif.start_position().row
+            }
+            _ => node.end_position().row + 1,
+        };
+
+        let mut is_taken = false;
+        let mut is_unknown = false;
+        let mut branch = Some(node);
+        while let Some(curr) = branch {
+            let alternative = curr.child_by_field_name("alternative");
+            let end_row = alternative.map_or(endif_row, |a| a.start_position().row);
+
+            let condition = self.evaluate_branch_condition(curr);
+            let is_active = match condition {
+                _ if is_unknown => true,
+                None => {
+                    is_unknown = true;
+                    true
+                }
+                Some(condition) => !is_taken && condition,
+            };
+            is_taken |= is_active;
+
+            if is_active {
+                let mut cursor = curr.walk();
+                let mut has_child = cursor.goto_first_child();
+                while has_child {
+                    let is_content = !matches!(
+                        cursor.field_name(),
+                        Some("condition") | Some("name") | Some("alternative")
+                    );
+                    if is_content && cursor.node().is_named() {
+                        self.visit(cursor.node());
+                    }
+                    has_child = cursor.goto_next_sibling();
+                }
+            } else {
+                let header_row = self.get_header_end_row(curr);
+                if header_row + 1 < end_row {
+                    self.inactive_regions.push(InactiveRegion::from_rows(
+                        self.source,
+                        header_row + 1..end_row,
+                    ));
+                }
+            }
+
+            branch = alternative;
+        }
+    }
+
+    fn evaluate_branch_condition(&self, branch: Node) -> Option<bool> {
+        match branch.kind() {
+            "preproc_if" | "preproc_elif" => {
+                let condition = branch.child_by_field_name("condition")?;
+                self.evaluate_expression(condition, self.source, 0)
+                    .map(|v| v != 0)
+            }
+            "preproc_ifdef" => {
+                let name = self.get_text(branch.child_by_field_name("name")?);
+                let is_ifndef = branch.child(0).is_some_and(|c| c.kind() == "#ifndef");
+                Some(self.macros.is_defined(name) != is_ifndef)
+            }
+            "preproc_else" => Some(true),
+            _ => None,
+        }
+    }
+
+    /// Returns the row of the `#if ...`, `#else`, ... line. Conditions may span several rows
+    fn get_header_end_row(&self, branch: Node) -> usize {
+        branch
+            .child_by_field_name("condition")
+            .or_else(|| branch.child_by_field_name("name"))
+            .or_else(|| branch.child(0))
+            .map_or(branch.start_position().row, |n| n.end_position().row)
+    }
+
+    /// Evaluates a `_preproc_expression`. `None` if it cannot be evaluated, e.g. because it
+    /// contains syntax errors
+    fn evaluate_expression(&self, node: Node, source: &[u8], depth: usize) -> Option<i64> {
+        let get_text = |node: Node| node.utf8_text(source).unwrap_or_default();
+        let evaluate_field = |field: &str| {
+            node.child_by_field_name(field)
+                .and_then(|child| self.evaluate_expression(child, source, depth))
+        };
+
+        match node.kind() {
+            "number_literal" => parse_number(get_text(node)),
+            "char_literal" => parse_char(get_text(node)),
+            "identifier" => match self.macros.get(get_text(node)) {
+                Some(definition) if definition.parameters.is_none() => {
+                    self.evaluate_text(&definition.body, depth + 1)
+                }
+                Some(_) => None,
+                // Undefined identifiers evaluate to 0
+                None => Some(0),
+            },
+            "call_expression" => {
+                let function = get_text(node.child_by_field_name("function")?);
+                let definition = self.macros.get(function)?;
+                let parameters = definition.parameters.as_ref()?;
+                let arguments = node.child_by_field_name("arguments")?;
+                let mut cursor = arguments.walk();
+                let arguments = arguments
+                    .named_children(&mut cursor)
+                    .map(get_text)
+                    .collect::<Vec<_>>();
+                let body = substitute_parameters(&definition.body, parameters, &arguments)?;
+                self.evaluate_text(&body, depth + 1)
+            }
+            "preproc_defined" => {
+                let mut cursor = node.walk();
+                let name = node
+                    .named_children(&mut cursor)
+                    .find(|c| c.kind() == "identifier")?;
+                Some(self.macros.is_defined(get_text(name)) as i64)
+            }
+            "parenthesized_expression" => {
+                self.evaluate_expression(node.named_child(0)?, source, depth)
+            }
+            "unary_expression" => {
+                let argument = evaluate_field("argument")?;
+                match get_text(node.child_by_field_name("operator")?) {
+                    "!" => Some((argument == 0) as i64),
+                    "~" => Some(!argument),
+                    "-" => Some(argument.wrapping_neg()),
+                    "+" => Some(argument),
+                    _ => None,
+                }
+            }
+            "binary_expression" => {
+                let operator = get_text(node.child_by_field_name("operator")?);
+                let left = evaluate_field("left")?;
+                // Short-circuiting operators ignore errors on the right-hand side
+                match operator {
+                    "&&" if left == 0 => return Some(0),
+                    "||" if left != 0 => return Some(1),
+                    _ => {}
+                }
+                let right = evaluate_field("right")?;
+                evaluate_binary(operator, left, right)
+            }
+            _ => None,
+        }
+    }
+
+    /// Evaluates the body of a macro by parsing it as the condition of an `#if`
+    fn evaluate_text(&self, text: &str, depth: usize) -> Option<i64> {
+        if depth > MAX_EXPANSION_DEPTH {
+            return None;
+        }
+        if let Some(out) = parse_number(text.trim()) {
+            return Some(out);
+        }
+
+        let raw = format!("#if {}\n#endif\n", text);
+        let mut parser = Parser::new();
+        parser.set_language(tree_sitter_ccsc::language()).ok()?;
+        let tree = parser.parse(&raw, None)?;
+        let condition = tree
+            .root_node()
+            .named_child(0)
+            .filter(|n| n.kind() == "preproc_if" && !n.has_error())?
+            .child_by_field_name("condition")?;
+
+        self.evaluate_expression(condition, raw.as_bytes(), depth)
+    }
+}
+
+fn parse_number(text: &str) -> Option<i64> {
+    let captures = NUMBER_MATCHER.captures(text)?;
+    if let Some(m) = captures.name("hex") {
+        i64::from_str_radix(m.as_str(), 16).ok()
+    } else if let Some(m) = captures.name("bin") {
+        i64::from_str_radix(m.as_str(), 2).ok()
+    } else if let Some(m) = captures.name("oct") {
+        i64::from_str_radix(m.as_str(), 8).ok()
+    } else {
+        captures.name("dec")?.as_str().parse().ok()
+    }
+}
+
+fn parse_char(text: &str) -> Option<i64> {
+    let inner = text.strip_prefix('\'')?.strip_suffix('\'')?;
+    let mut chars = inner.chars();
+    let out = match (chars.next()?, chars.next()) {
+        ('\\', Some('n')) => '\n',
+        ('\\', Some('r')) => '\r',
+        ('\\', Some('t')) => '\t',
+        ('\\', Some('0')) => '\0',
+        ('\\', Some(c)) => c,
+        (c, None) => c,
+        _ => return None,
+    };
+    Some(out as i64)
+}
+
+fn evaluate_binary(operator: &str, left: i64, right: i64) -> Option<i64> {
+    let out = match operator {
+        "+" => left.wrapping_add(right),
+        "-" => left.wrapping_sub(right),
+        "*" => left.wrapping_mul(right),
+        "/" => left.checked_div(right)?,
+        "%" => left.checked_rem(right)?,
+        "||" => (left != 0 || right != 0) as i64,
+        "&&" => (left != 0 && right != 0) as i64,
+        "|" => left | right,
+        "^" => left ^ right,
+        "&" => left & right,
+        "==" => (left == right) as i64,
+        "!=" => (left != right) as i64,
+        ">" => (left > right) as i64,
+        ">=" => (left >= right) as i64,
+        "<=" => (left <= right) as i64,
+        "<" => (left < right) as i64,
+        "<<" => left.checked_shl(u32::try_from(right).ok()?)?,
+        ">>" => left.checked_shr(u32::try_from(right).ok()?)?,
+        _ => return None,
+    };
+    Some(out)
+}
+
+/// Replaces the parameters of a function-like macro inside its body
+fn substitute_parameters(body: &str, parameters: &[String], arguments: &[&str]) -> Option<String> {
+    if parameters.len() != arguments.len() {
+        return None;
+    }
+
+    let out = WORD_MATCHER.replace_all(body, |captures: &regex::Captures| {
+        let word = &captures[0];
+        parameters
+            .iter()
+            .position(|p| p == word)
+            .map_or_else(|| word.to_owned(), |idx| format!("({})", arguments[idx]))
+    });
+    Some(out.into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::preprocessor::get_predefined_macros;
+    use crate::test_utils::parse;
+
+    fn get_inactive_rows(raw: &str) -> Vec<(u32, u32)> {
+        let tree = parse(raw);
+        let macros = get_predefined_macros(Some("PIC16F883"), false);
+        let mut get_included_macros = |_: &Path| None;
+        let evaluator =
+            PreprocessorEvaluator::new(raw.as_bytes(), &[], macros, &mut get_included_macros);
+
+        let (_, regions) = evaluator.evaluate(tree.root_node());
+        regions
+            .iter()
+            .map(|r| (r.range.start.line, r.range.end.line))
+            .collect()
+    }
+
+    #[test]
+    fn test_device_conditionals() {
+        let raw = "#ifdef __PCH__\nint a;\n#elif defined(__pcm__) && __DEVICE__ == 883\nint b;\n#else\nint c;\nint d;\n#endif\n";
+
+        assert_eq!(get_inactive_rows(raw), vec![(1, 1), (5, 6)]);
+    }
+
+    #[test]
+    fn test_defines_in_order() {
+        let raw = "#define LEVEL 2\n#define HALF(x) ((x) / 2)\n#if HALF(LEVEL) == 1\nint a;\n#endif\n#undef LEVEL\n#ifndef LEVEL\nint b;\n#else\nint c;\n#endif\n";
+
+        assert_eq!(get_inactive_rows(raw), vec![(9, 9)]);
+    }
+
+    #[test]
+    fn test_unknown_conditions_stay_active() {
+        let raw = "#if FOO(\nint a;\n#else\nint b;\n#endif\n";
+
+        assert_eq!(get_inactive_rows(raw), vec![]);
+    }
+
+    #[test]
+    fn test_parse_number() {
+        assert_eq!(parse_number("0x1F"), Some(31));
+        assert_eq!(parse_number("0b101"), Some(5));
+        assert_eq!(parse_number("010"), Some(8));
+        assert_eq!(parse_number("42UL"), Some(42));
+        assert_eq!(parse_number("4Mhz"), None);
+    }
+}
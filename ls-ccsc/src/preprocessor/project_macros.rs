@@ -0,0 +1,86 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::docs::text_document_type::TextDocumentTypeTrait;
+use crate::docs::TextDocumentType;
+use crate::preprocessor::{self, InactiveRegion, MacroTable, PreprocessorEvaluator};
+use crate::symbols;
+
+type Docs = HashMap<PathBuf, TextDocumentType>;
+
+/// Returns the macros the compiler predefines for the device of the project. Their names are
+/// case-sensitive, if a translation unit is, see `symbols::update_case_sensitivity`
+pub fn get_project_predefined_macros(docs: &Docs, project_device: Option<&str>) -> MacroTable {
+    let device = preprocessor::find_device(docs, project_device);
+    let case_sensitive = symbols::get_translation_units(docs)
+        .into_iter()
+        .filter_map(|p| symbols::get_source_doc(docs, p))
+        .any(|doc| doc.get_symbol_index().case_sensitive);
+    preprocessor::get_predefined_macros(device.as_deref(), case_sensitive)
+}
+
+/// Returns the macros defined at the end of the included file `path`. `visiting` contains the
+/// files which are currently being evaluated and guards against cyclic includes
+pub fn get_included_macros(
+    docs: &Docs,
+    path: &Path,
+    predefined: &MacroTable,
+    cache: &mut HashMap<PathBuf, MacroTable>,
+    visiting: &mut HashSet<PathBuf>,
+) -> Option<MacroTable> {
+    if let Some(macros) = cache.get(path) {
+        return Some(macros.clone());
+    }
+    if !visiting.insert(path.to_owned()) {
+        return None;
+    }
+    let result = evaluate_document(docs, path, predefined, cache, visiting);
+    visiting.remove(path);
+    let (macros, _) = result?;
+    cache.insert(path.to_owned(), macros.clone());
+    Some(macros)
+}
+
+/// Evaluates the preprocessor directives of `path`, starting with the predefined macros
+pub fn evaluate_document(
+    docs: &Docs,
+    path: &Path,
+    predefined: &MacroTable,
+    cache: &mut HashMap<PathBuf, MacroTable>,
+    visiting: &mut HashSet<PathBuf>,
+) -> Option<(MacroTable, Vec<InactiveRegion>)> {
+    let doc = symbols::get_source_doc(docs, path)?;
+    let tree = doc.get_syntax_tree().ok()?;
+    let source = doc.get_source().get_raw().as_bytes();
+    let includes = doc.get_includes();
+    let mut get_macros =
+        |included: &Path| get_included_macros(docs, included, predefined, cache, visiting);
+    let evaluator =
+        PreprocessorEvaluator::new(source, includes, predefined.clone(), &mut get_macros);
+
+    Some(evaluator.evaluate(tree.root_node()))
+}
+
+/// Returns the macros defined at `byte` of `path`
+pub fn get_macros_at(
+    docs: &Docs,
+    path: &Path,
+    byte: usize,
+    project_device: Option<&str>,
+) -> Option<MacroTable> {
+    let doc = symbols::get_source_doc(docs, path)?;
+    let tree = doc.get_syntax_tree().ok()?;
+    let source = doc.get_source().get_raw().as_bytes();
+    let includes = doc.get_includes();
+
+    let predefined = get_project_predefined_macros(docs, project_device);
+    let mut cache = HashMap::new();
+    let mut visiting = HashSet::from([path.to_owned()]);
+    let mut get_macros = |included: &Path| {
+        get_included_macros(docs, included, &predefined, &mut cache, &mut visiting)
+    };
+    let evaluator =
+        PreprocessorEvaluator::new(source, includes, predefined.clone(), &mut get_macros);
+
+    Some(evaluator.evaluate_until(tree.root_node(), byte))
+}
@@ -0,0 +1,5 @@
+pub use crate::semantic_tokens::semantic_token_collector::*;
+pub use crate::semantic_tokens::semantic_tokens_delta::*;
+
+pub mod semantic_token_collector;
+pub mod semantic_tokens_delta;
@@ -0,0 +1,557 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::{
+    Range, SemanticToken, SemanticTokenModifier, SemanticTokenType, SemanticTokensLegend,
+};
+use tree_sitter::{Node, Point};
+
+use crate::builtins::BuiltinFunction;
+use crate::completion::CCS_PRIMITIVE_TYPES;
+use crate::devices::DeviceDatabase;
+use crate::directives::{CcsDirective, DirectiveKind, DirectiveWord, DIRECTIVE_KINDS};
+use crate::docs::text_document_type::TextDocumentTypeTrait;
+use crate::docs::{TextDocument, TextDocumentType};
+use crate::preprocessor;
+use crate::symbols::{self, SymbolDefinitionKind};
+use crate::utils;
+
+type Docs = HashMap<PathBuf, TextDocumentType>;
+
+lazy_static! {
+    static ref PIN_MATCHER: Regex = Regex::new(r"^PIN_[A-Z]+\d+$").unwrap();
+    static ref CONSTANT_MATCHER: Regex = Regex::new(r"^[A-Z][A-Z0-9_]*$").unwrap();
+    static ref WORD_MATCHER: Regex = Regex::new(r"[A-Za-z_][A-Za-z0-9_]*").unwrap();
+}
+
+/// Token types in the order of the legend
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenType {
+    Function,
+    Parameter,
+    Variable,
+    Macro,
+    Keyword,
+    Type,
+    Struct,
+    EnumMember,
+    /// Special function registers declared by `#byte`, `#bit`, `#word` or `#locate`
+    Property,
+    /// Libraries of `#use` directives
+    Namespace,
+    /// Lines of branches which are not compiled
+    Comment,
+}
+
+pub const TOKEN_TYPES: &[TokenType] = &[
+    TokenType::Function,
+    TokenType::Parameter,
+    TokenType::Variable,
+    TokenType::Macro,
+    TokenType::Keyword,
+    TokenType::Type,
+    TokenType::Struct,
+    TokenType::EnumMember,
+    TokenType::Property,
+    TokenType::Namespace,
+    TokenType::Comment,
+];
+
+pub const DECLARATION: u32 = 1;
+pub const READONLY: u32 = 1 << 1;
+pub const DEFAULT_LIBRARY: u32 = 1 << 2;
+
+impl TokenType {
+    fn get_semantic_token_type(self) -> SemanticTokenType {
+        match self {
+            TokenType::Function => SemanticTokenType::FUNCTION,
+            TokenType::Parameter => SemanticTokenType::PARAMETER,
+            TokenType::Variable => SemanticTokenType::VARIABLE,
+            TokenType::Macro => SemanticTokenType::MACRO,
+            TokenType::Keyword => SemanticTokenType::KEYWORD,
+            TokenType::Type => SemanticTokenType::TYPE,
+            TokenType::Struct => SemanticTokenType::STRUCT,
+            TokenType::EnumMember => SemanticTokenType::ENUM_MEMBER,
+            TokenType::Property => SemanticTokenType::PROPERTY,
+            TokenType::Namespace => SemanticTokenType::NAMESPACE,
+            TokenType::Comment => SemanticTokenType::COMMENT,
+        }
+    }
+
+    fn get_index(self) -> u32 {
+        TOKEN_TYPES.iter().position(|t| *t == self).unwrap_or(0) as u32
+    }
+}
+
+pub fn get_legend() -> SemanticTokensLegend {
+    SemanticTokensLegend {
+        token_types: TOKEN_TYPES
+            .iter()
+            .map(|t| t.get_semantic_token_type())
+            .collect(),
+        token_modifiers: vec![
+            SemanticTokenModifier::DECLARATION,
+            SemanticTokenModifier::READONLY,
+            SemanticTokenModifier::DEFAULT_LIBRARY,
+        ],
+    }
+}
+
+/// A token with an absolute position. Columns are counted in bytes like the rest of the server
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AbsoluteToken {
+    pub start: Point,
+    pub length: usize,
+    pub token_type: TokenType,
+    pub modifiers: u32,
+}
+
+/// Classifies the identifiers, types and CCS directives of the document at `path`. Everything
+/// else is left to the TextMate grammar. Pins and registers are taken from `device`, if its
+/// header was found
+pub fn get_semantic_tokens(
+    docs: &Docs,
+    path: &Path,
+    device: Option<&DeviceDatabase>,
+) -> Result<Vec<AbsoluteToken>> {
+    let doc = match symbols::get_source_doc(docs, path) {
+        Some(doc) => doc,
+        None => return Ok(vec![]),
+    };
+    let tree = doc.get_syntax_tree()?;
+    let context = TokenContext::new(docs, doc, device);
+
+    let mut out = vec![];
+    collect_tokens(tree.root_node(), &context, &mut out);
+    // Inactive lines are shown as comments as a whole
+    let inactive_regions = doc.get_inactive_regions();
+    out.retain(|t| !preprocessor::is_inactive_line(inactive_regions, t.start.row as u32));
+    for (row, line) in doc.get_source().get_raw().lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        if !line.is_empty() && preprocessor::is_inactive_line(inactive_regions, row as u32) {
+            out.push(AbsoluteToken {
+                start: Point::new(row, 0),
+                length: line.len(),
+                token_type: TokenType::Comment,
+                modifiers: 0,
+            });
+        }
+    }
+    out.sort_by_key(|t| (t.start.row, t.start.column));
+    out.dedup_by_key(|t| t.start);
+
+    Ok(out)
+}
+
+/// Encodes the tokens relative to each other as required by the protocol
+pub fn encode_tokens(tokens: &[AbsoluteToken]) -> Vec<SemanticToken> {
+    let mut previous = Point::new(0, 0);
+    tokens
+        .iter()
+        .map(|t| {
+            let delta_line = t.start.row - previous.row;
+            let delta_start = if delta_line == 0 {
+                t.start.column - previous.column
+            } else {
+                t.start.column
+            };
+            previous = t.start;
+
+            SemanticToken {
+                delta_line: delta_line as u32,
+                delta_start: delta_start as u32,
+                length: t.length as u32,
+                token_type: t.token_type.get_index(),
+                token_modifiers_bitset: t.modifiers,
+            }
+        })
+        .collect()
+}
+
+struct TokenContext<'a> {
+    doc: &'a TextDocument,
+    source: &'a [u8],
+    /// Keyed by `symbols::get_symbol_key`
+    globals: HashMap<String, SymbolDefinitionKind>,
+    registers: HashSet<String>,
+    device: Option<&'a DeviceDatabase>,
+    case_sensitive: bool,
+}
+
+impl<'a> TokenContext<'a> {
+    fn new(docs: &'a Docs, doc: &'a TextDocument, device: Option<&'a DeviceDatabase>) -> Self {
+        let case_sensitive = doc.get_symbol_index().case_sensitive;
+        let mut globals = HashMap::new();
+        let mut registers = HashSet::new();
+        let mut project_docs = docs
+            .values()
+            .filter_map(|doc_type| match doc_type {
+                TextDocumentType::Source(doc) => Some(doc),
+                TextDocumentType::Ignored => None,
+            })
+            .collect::<Vec<_>>();
+        // Definitions of the document itself take precedence
+        project_docs.sort_by_key(|d| d.get_absolute_path() != doc.get_absolute_path());
+
+        for project_doc in project_docs {
+            for def in project_doc
+                .get_symbol_index()
+                .definitions
+                .iter()
+                .filter(|def| def.is_global())
+            {
+                let key = symbols::get_symbol_key(&def.name, case_sensitive);
+                globals.entry(key).or_insert(def.kind);
+            }
+            if let Ok(tree) = project_doc.get_syntax_tree() {
+                let source = project_doc.get_source().get_raw().as_bytes();
+                collect_registers(tree.root_node(), source, &mut registers);
+            }
+        }
+        if let Some(db) = device {
+            registers.extend(db.registers.iter().map(|r| r.name.clone()));
+        }
+        let registers = registers
+            .iter()
+            .map(|r| symbols::get_symbol_key(r, case_sensitive))
+            .collect();
+
+        Self {
+            doc,
+            source: doc.get_source().get_raw().as_bytes(),
+            globals,
+            registers,
+            device,
+            case_sensitive,
+        }
+    }
+
+    fn is_same_name(&self, a: &str, b: &str) -> bool {
+        symbols::is_same_name(a, b, self.case_sensitive)
+    }
+
+    fn classify_identifier(&self, node: Node) -> Option<(TokenType, u32)> {
+        fn get_token_type(kind: SymbolDefinitionKind, node_kind: &str) -> TokenType {
+            match kind {
+                SymbolDefinitionKind::Function | SymbolDefinitionKind::FunctionPrototype => {
+                    TokenType::Function
+                }
+                SymbolDefinitionKind::Parameter => TokenType::Parameter,
+                SymbolDefinitionKind::Variable => TokenType::Variable,
+                SymbolDefinitionKind::Macro | SymbolDefinitionKind::FunctionMacro => {
+                    TokenType::Macro
+                }
+                SymbolDefinitionKind::Type if node_kind == "type_definition" => TokenType::Type,
+                SymbolDefinitionKind::Type => TokenType::Struct,
+                SymbolDefinitionKind::EnumConstant => TokenType::EnumMember,
+            }
+        }
+
+        let name = node.utf8_text(self.source).ok()?;
+        if node.kind() == "type_identifier" && CCS_PRIMITIVE_TYPES.contains(&name) {
+            return Some((TokenType::Type, DEFAULT_LIBRARY));
+        }
+        if node.kind() == "field_identifier" {
+            return Some((TokenType::Property, 0));
+        }
+
+        let index = self.doc.get_symbol_index();
+        if let Some(occurrence) = index.get_occurrence_at(node.start_byte()) {
+            let modifiers = if occurrence.is_declaration() {
+                DECLARATION
+            } else {
+                0
+            };
+            if occurrence.scope.is_some() {
+                let def = index.definitions.iter().find(|def| {
+                    def.scope == occurrence.scope && self.is_same_name(&def.name, name)
+                })?;
+                return Some((get_token_type(def.kind, def.node_kind), modifiers));
+            }
+            let def = index
+                .definitions
+                .iter()
+                .find(|def| def.is_global() && self.is_same_name(&def.name, name));
+            if let Some(def) = def {
+                return Some((get_token_type(def.kind, def.node_kind), modifiers));
+            }
+        }
+        let key = symbols::get_symbol_key(name, self.case_sensitive);
+        if let Some(kind) = self.globals.get(&key) {
+            let node_kind = if node.kind() == "type_identifier" {
+                "type_definition"
+            } else {
+                ""
+            };
+            return Some((get_token_type(*kind, node_kind), 0));
+        }
+
+        let is_pin = match self.device {
+            Some(db) => db
+                .get_pin(name)
+                .is_some_and(|pin| self.is_same_name(&pin.name, name)),
+            // Without the device header, pins are recognised by their names
+            None if self.case_sensitive => PIN_MATCHER.is_match(name),
+            None => PIN_MATCHER.is_match(&name.to_ascii_uppercase()),
+        };
+        if self.registers.contains(&key) {
+            Some((TokenType::Property, 0))
+        } else if BuiltinFunction::find(name, self.case_sensitive).is_some() {
+            Some((TokenType::Function, DEFAULT_LIBRARY))
+        } else if is_pin {
+            Some((TokenType::EnumMember, READONLY | DEFAULT_LIBRARY))
+        } else if CONSTANT_MATCHER.is_match(name) {
+            // Most likely defined by the device header
+            Some((TokenType::Macro, READONLY | DEFAULT_LIBRARY))
+        } else {
+            None
+        }
+    }
+}
+
+/// Collects the names declared by `#byte PORTB = 0x06`, `#bit TMR1IF = 0x0C.0`, ...
+fn collect_registers(node: Node, source: &[u8], out: &mut HashSet<String>) {
+    if DIRECTIVE_KINDS.contains(&node.kind()) {
+        let directive = CcsDirective::from_node(node, source);
+        if let Some(directive) = directive.filter(|d| {
+            matches!(
+                d.kind,
+                DirectiveKind::Byte
+                    | DirectiveKind::Bit
+                    | DirectiveKind::Word
+                    | DirectiveKind::Locate
+            )
+        }) {
+            out.extend(directive.target.map(|t| t.text));
+        }
+        return;
+    }
+
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        collect_registers(child, source, out);
+    }
+}
+
+fn collect_tokens(node: Node, context: &TokenContext, out: &mut Vec<AbsoluteToken>) {
+    fn push_token(node: Node, token_type: TokenType, modifiers: u32, out: &mut Vec<AbsoluteToken>) {
+        if node.start_position().row == node.end_position().row {
+            out.push(AbsoluteToken {
+                start: node.start_position(),
+                length: node.end_byte() - node.start_byte(),
+                token_type,
+                modifiers,
+            });
+        }
+    }
+
+    match node.kind() {
+        "comment" | "string_literal" | "system_lib_string" | "char_literal" => {}
+        "primitive_type" => push_token(node, TokenType::Type, DEFAULT_LIBRARY, out),
+        "identifier" | "type_identifier" | "field_identifier" => {
+            let is_macro_name = node
+                .parent()
+                .filter(|p| matches!(p.kind(), "preproc_def" | "preproc_function_def"))
+                .and_then(|p| p.child_by_field_name("name"))
+                == Some(node);
+            let token = if is_macro_name {
+                Some((TokenType::Macro, DECLARATION))
+            } else {
+                context.classify_identifier(node)
+            };
+            if let Some((token_type, modifiers)) = token {
+                push_token(node, token_type, modifiers, out);
+            }
+        }
+        k if k == "preproc_call" || DIRECTIVE_KINDS.contains(&k) => {
+            collect_directive_tokens(node, context, out)
+        }
+        _ => {
+            let mut cursor = node.walk();
+            for child in node.named_children(&mut cursor) {
+                collect_tokens(child, context, out);
+            }
+        }
+    }
+}
+
+/// Colours CCS directives like `#use delay(...)`, `#fuses XT, NOWDT` and `#int_timer1`
+fn collect_directive_tokens(node: Node, context: &TokenContext, out: &mut Vec<AbsoluteToken>) {
+    fn push_word(
+        word: &DirectiveWord,
+        token_type: TokenType,
+        modifiers: u32,
+        out: &mut Vec<AbsoluteToken>,
+    ) {
+        let Range { start, end } = word.range;
+        if start.line == end.line {
+            out.push(AbsoluteToken {
+                start: utils::get_point(&start),
+                length: (end.character - start.character) as usize,
+                token_type,
+                modifiers,
+            });
+        }
+    }
+
+    let directive = match CcsDirective::from_node(node, context.source) {
+        Some(directive) => directive,
+        None => {
+            if let Some(directive) = node.child_by_field_name("directive") {
+                out.push(AbsoluteToken {
+                    start: directive.start_position(),
+                    length: directive.end_byte() - directive.start_byte(),
+                    token_type: TokenType::Keyword,
+                    modifiers: 0,
+                });
+            }
+            return;
+        }
+    };
+    push_word(&directive.directive, TokenType::Keyword, 0, out);
+
+    match directive.kind {
+        DirectiveKind::Fuses => {
+            for argument in directive.arguments.iter() {
+                push_word(
+                    &argument.key,
+                    TokenType::EnumMember,
+                    READONLY | DEFAULT_LIBRARY,
+                    out,
+                );
+            }
+        }
+        DirectiveKind::Use => {
+            if let Some(library) = directive.target.as_ref() {
+                push_word(library, TokenType::Namespace, DEFAULT_LIBRARY, out);
+            }
+        }
+        DirectiveKind::Byte | DirectiveKind::Bit | DirectiveKind::Word | DirectiveKind::Locate => {
+            if let Some(name) = directive.target.as_ref() {
+                push_word(name, TokenType::Property, DECLARATION, out);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::create_docs;
+
+    fn get_tokens(raw: &str) -> Vec<(String, TokenType, u32)> {
+        get_device_tokens(raw, None)
+    }
+
+    fn get_device_tokens(
+        raw: &str,
+        device: Option<&DeviceDatabase>,
+    ) -> Vec<(String, TokenType, u32)> {
+        let docs = create_docs(&[("/project/main.c", raw)]);
+        let lines = raw.lines().collect::<Vec<_>>();
+
+        get_semantic_tokens(&docs, Path::new("/project/main.c"), device)
+            .unwrap()
+            .into_iter()
+            .map(|t| {
+                let line = lines[t.start.row];
+                let text = line[t.start.column..t.start.column + t.length].to_owned();
+                (text, t.token_type, t.modifiers)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_ccs_tokens() {
+        let tokens = get_tokens(
+            "#fuses XT, NOWDT\n#use delay(clock=4Mhz)\n#byte PORTB = 0x06\n#define LED PIN_C0\n\
+             int16 add(int16 a) {\n\tPORTB = a;\n\toutput_low(LED);\n\treturn a;\n}",
+        );
+
+        assert_eq!(
+            tokens,
+            vec![
+                ("#fuses".to_owned(), TokenType::Keyword, 0),
+                (
+                    "XT".to_owned(),
+                    TokenType::EnumMember,
+                    READONLY | DEFAULT_LIBRARY
+                ),
+                (
+                    "NOWDT".to_owned(),
+                    TokenType::EnumMember,
+                    READONLY | DEFAULT_LIBRARY
+                ),
+                ("#use".to_owned(), TokenType::Keyword, 0),
+                ("delay".to_owned(), TokenType::Namespace, DEFAULT_LIBRARY),
+                ("#byte".to_owned(), TokenType::Keyword, 0),
+                ("PORTB".to_owned(), TokenType::Property, DECLARATION),
+                ("LED".to_owned(), TokenType::Macro, DECLARATION),
+                ("int16".to_owned(), TokenType::Type, DEFAULT_LIBRARY),
+                ("add".to_owned(), TokenType::Function, DECLARATION),
+                ("int16".to_owned(), TokenType::Type, DEFAULT_LIBRARY),
+                ("a".to_owned(), TokenType::Parameter, DECLARATION),
+                ("PORTB".to_owned(), TokenType::Property, 0),
+                ("a".to_owned(), TokenType::Parameter, 0),
+                (
+                    "output_low".to_owned(),
+                    TokenType::Function,
+                    DEFAULT_LIBRARY
+                ),
+                ("LED".to_owned(), TokenType::Macro, 0),
+                ("a".to_owned(), TokenType::Parameter, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pins_and_registers_of_device() {
+        let header = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/devices/16F883.h");
+        let db = DeviceDatabase::from_header(&std::fs::read_to_string(header).unwrap());
+        let raw = "void main() {\n\tSTATUS = 0;\n\toutput_high(PIN_C0);\n\toutput_high(PIN_C9);\n}";
+
+        let tokens = get_device_tokens(raw, Some(&db))
+            .into_iter()
+            .filter(|(text, _, _)| text == "STATUS" || text.starts_with("PIN_"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            vec![
+                ("STATUS".to_owned(), TokenType::Property, 0),
+                (
+                    "PIN_C0".to_owned(),
+                    TokenType::EnumMember,
+                    READONLY | DEFAULT_LIBRARY
+                ),
+                (
+                    "PIN_C9".to_owned(),
+                    TokenType::Macro,
+                    READONLY | DEFAULT_LIBRARY
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_encode_tokens() {
+        let token = |row, column| AbsoluteToken {
+            start: Point::new(row, column),
+            length: 2,
+            token_type: TokenType::Variable,
+            modifiers: 0,
+        };
+        let encoded = encode_tokens(&[token(1, 4), token(1, 8), token(3, 2)]);
+
+        assert_eq!(
+            encoded
+                .iter()
+                .map(|t| (t.delta_line, t.delta_start))
+                .collect::<Vec<_>>(),
+            vec![(1, 4), (0, 4), (2, 2)]
+        );
+    }
+}
@@ -0,0 +1,65 @@
+use tower_lsp::lsp_types::{SemanticToken, SemanticTokensEdit};
+
+/// Returns the single edit turning `previous` into `current`. Unchanged tokens at the start and
+/// the end are kept
+pub fn get_semantic_tokens_edits(
+    previous: &[SemanticToken],
+    current: &[SemanticToken],
+) -> Vec<SemanticTokensEdit> {
+    let prefix = previous
+        .iter()
+        .zip(current.iter())
+        .take_while(|(p, c)| p == c)
+        .count();
+    let suffix = previous[prefix..]
+        .iter()
+        .rev()
+        .zip(current[prefix..].iter().rev())
+        .take_while(|(p, c)| p == c)
+        .count();
+
+    let deleted = previous.len() - prefix - suffix;
+    let inserted = &current[prefix..current.len() - suffix];
+    if deleted == 0 && inserted.is_empty() {
+        return vec![];
+    }
+
+    // Edits address the flattened array, which has five integers per token
+    vec![SemanticTokensEdit {
+        start: (prefix * 5) as u32,
+        delete_count: (deleted * 5) as u32,
+        data: Some(inserted.to_vec()),
+    }]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_tokens(lengths: &[u32]) -> Vec<SemanticToken> {
+        lengths
+            .iter()
+            .map(|length| SemanticToken {
+                delta_line: 1,
+                delta_start: 0,
+                length: *length,
+                token_type: 0,
+                token_modifiers_bitset: 0,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_edit_in_the_middle() {
+        let previous = create_tokens(&[1, 2, 3, 4]);
+        let current = create_tokens(&[1, 5, 6, 4]);
+
+        let edits = get_semantic_tokens_edits(&previous, &current);
+
+        assert_eq!(edits.len(), 1);
+        assert_eq!(edits[0].start, 5);
+        assert_eq!(edits[0].delete_count, 10);
+        assert_eq!(edits[0].data, Some(create_tokens(&[5, 6])));
+        assert!(get_semantic_tokens_edits(&current, &current).is_empty());
+    }
+}
@@ -2,10 +2,11 @@ use std::sync::{Arc, Mutex, MutexGuard};
 
 use tower_lsp::Client;
 use tower_lsp::jsonrpc::Result;
-use tower_lsp::lsp_types::MessageType;
+use tower_lsp::lsp_types::{MessageType, TextDocumentIdentifier};
 use tree_sitter::Parser;
 
 use crate::ccsc_response::CCSCResponse;
+use crate::preprocessor::{InactiveRegionsNotification, InactiveRegionsParams};
 use crate::server::BackendInner;
 
 pub struct Backend {
@@ -15,13 +16,14 @@ pub struct Backend {
 }
 
 impl Backend {
-    pub fn new(client: Client) -> Self {
+    /// `data` is shared with the handlers of custom requests
+    pub fn new(client: Client, data: Arc<Mutex<BackendInner>>) -> Self {
         let mut parser = Parser::new();
         parser.set_language(tree_sitter_ccsc::language()).unwrap();
         let parser = Arc::new(Mutex::new(parser));
         Self {
             client,
-            data: Arc::new(Mutex::new(Default::default())),
+            data,
             parser,
         }
     }
@@ -41,6 +43,8 @@ impl Backend {
             Ok(CCSCResponse {
                 logs,
                 uri_diagnostics,
+                related_diagnostics,
+                inactive_regions,
             }) => {
                 if let Some(logs) = logs {
                     for log in logs {
@@ -48,11 +52,21 @@ impl Backend {
                     }
                 }
 
-                if let Some((uri, diagnostics)) = uri_diagnostics {
+                for (uri, diagnostics) in uri_diagnostics.into_iter().chain(related_diagnostics) {
                     self.get_client()
                         .publish_diagnostics(uri, diagnostics, None)
                         .await
                 }
+
+                for (uri, regions) in inactive_regions {
+                    let params = InactiveRegionsParams {
+                        text_document: TextDocumentIdentifier::new(uri),
+                        regions,
+                    };
+                    self.get_client()
+                        .send_custom_notification::<InactiveRegionsNotification>(params)
+                        .await
+                }
             }
             Err(err) => {
                 self.error(format!("Error code {}: {}", err.code, err.message))
@@ -65,7 +79,7 @@ impl Backend {
         &self.client
     }
 
-    pub fn get_inner(&self) -> MutexGuard<BackendInner> {
+    pub fn get_inner(&self) -> MutexGuard<'_, BackendInner> {
         self.data.lock().unwrap()
     }
 
@@ -74,7 +88,7 @@ impl Backend {
     }
 
     #[allow(dead_code)]
-    pub fn get_parser_as_guard(&self) -> MutexGuard<Parser> {
+    pub fn get_parser_as_guard(&self) -> MutexGuard<'_, Parser> {
         self.parser.lock().unwrap()
     }
 }
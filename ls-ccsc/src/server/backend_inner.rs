@@ -2,15 +2,27 @@ use std::collections::HashMap;
 use std::fs::File;
 use std::io::Read;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 
 use lazy_static::lazy_static;
 use regex::{Captures, Regex};
 use tower_lsp::jsonrpc::Result;
-use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString, Position, Range, Url};
+use tower_lsp::lsp_types::{
+    Diagnostic, DiagnosticSeverity, NumberOrString, Position, Range, SemanticToken,
+    SemanticTokens, Url,
+};
+use tree_sitter::Parser;
 
+use crate::compiler_output::{
+    self, CallTree, DisassembledLine, DisassemblyParams, Listing, ProjectStatistics, SymbolMap,
+};
+use crate::devices::{self, DeviceDatabase};
 use crate::docs::text_document_type::TextDocumentTypeTrait;
-use crate::docs::TextDocumentType;
+use crate::docs::{self, IncludePaths, TextDocumentType};
 use crate::mplab_project_config::MPLABProjectConfig;
+use crate::preprocessor;
+use crate::server::ServerSettings;
+use crate::symbols;
 use crate::utils;
 
 #[derive(Default)]
@@ -18,6 +30,22 @@ pub struct BackendInner {
     root_path: Option<PathBuf>,
     mcp: Option<MPLABProjectConfig>,
     docs: HashMap<PathBuf, TextDocumentType>,
+    semantic_tokens: HashMap<PathBuf, SemanticTokens>,
+    semantic_tokens_counter: u64,
+    settings: ServerSettings,
+    device_database: Option<DeviceDatabase>,
+    /// The device `device_database` was loaded for, even if its header was not found
+    loaded_device: Option<String>,
+    /// Diagnostics last sent to the client for each document
+    published_diagnostics: HashMap<PathBuf, Vec<Diagnostic>>,
+    /// Addresses of the last build
+    symbol_map: Option<SymbolMap>,
+    /// Memory usage of the last build
+    statistics: Option<ProjectStatistics>,
+    /// Call tree of the last build
+    call_tree: Option<CallTree>,
+    /// Instructions of the last build
+    listing: Option<Listing>,
 }
 
 lazy_static! {
@@ -39,7 +67,6 @@ impl BackendInner {
         self.root_path = Some(root_path);
     }
 
-    #[allow(dead_code)]
     pub fn get_root_path(&self) -> Result<&PathBuf> {
         self.root_path
             .as_ref()
@@ -50,7 +77,6 @@ impl BackendInner {
         self.mcp = Some(mplab);
     }
 
-    #[allow(dead_code)]
     pub fn get_mcp(&self) -> Result<&MPLABProjectConfig> {
         self.mcp.as_ref().ok_or(utils::create_server_error(
             4,
@@ -58,8 +84,189 @@ impl BackendInner {
         ))
     }
 
+    pub fn set_settings(&mut self, settings: ServerSettings) {
+        self.settings = settings;
+    }
+
+    /// Returns where `#include`s are searched: the project, `dir_inc` of the .mcp and the
+    /// configured directories
+    pub fn get_include_paths(&self) -> IncludePaths {
+        let root_path = match &self.root_path {
+            Some(root_path) => root_path.clone(),
+            None => return IncludePaths::default(),
+        };
+        let directories = self
+            .mcp
+            .iter()
+            .flat_map(|mcp| mcp.include_directories.iter())
+            .map(|d| utils::normalize_path_separators(d))
+            .collect();
+        IncludePaths::new(
+            root_path,
+            directories,
+            self.settings.get_include_directories(),
+        )
+    }
+
+    pub fn get_device_database(&self) -> Option<&DeviceDatabase> {
+        self.device_database.as_ref()
+    }
+
+    pub fn get_symbol_map(&self) -> Option<&SymbolMap> {
+        self.symbol_map.as_ref()
+    }
+
+    pub fn get_statistics(&self) -> Option<&ProjectStatistics> {
+        self.statistics.as_ref()
+    }
+
+    pub fn get_call_tree(&self) -> Option<&CallTree> {
+        self.call_tree.as_ref()
+    }
+
+    pub fn get_listing(&self) -> Option<&Listing> {
+        self.listing.as_ref()
+    }
+
+    /// Answers the `ccsc/disassembly` request from the listing of the last build
+    pub fn get_disassembly(&self, params: DisassemblyParams) -> Result<Vec<DisassembledLine>> {
+        let listing = self.get_listing().ok_or_else(|| {
+            utils::create_server_error(11, "No .lst file found. Build the project first".to_owned())
+        })?;
+        let path = utils::get_path(&params.text_document.uri)?;
+        let Range { start, end } = params.range;
+        Ok(listing.get_disassembly(&self.docs, &path, start.line, end.line))
+    }
+
+    /// Loads the .sym, .STA, .tre and .lst files the compiler wrote into the project directory.
+    /// Called on every build
+    pub fn update_compiler_output(&mut self) {
+        let root_path = self.root_path.clone();
+        let find_output_file = |extension| {
+            root_path
+                .as_deref()
+                .and_then(|root_path| compiler_output::find_output_file(root_path, extension))
+        };
+        self.symbol_map = find_output_file("sym").and_then(|path| SymbolMap::load(&path).ok());
+        self.statistics =
+            find_output_file("sta").and_then(|path| ProjectStatistics::load(&path).ok());
+        self.call_tree = find_output_file("tre").and_then(|path| CallTree::load(&path).ok());
+        self.listing = find_output_file("lst").and_then(|path| Listing::load(&path).ok());
+    }
+
     pub fn insert_docs(&mut self, docs: HashMap<PathBuf, TextDocumentType>) {
         self.docs.extend(docs);
+        self.update_project();
+    }
+
+    /// Called after every change. Returns the documents whose inactive regions changed
+    pub fn insert_included_docs(&mut self, parser: Arc<Mutex<Parser>>) -> Vec<PathBuf> {
+        let include_paths = self.get_include_paths();
+        docs::update_included_files(&mut self.docs, &include_paths);
+        let docs = TextDocumentType::index_from_includes(&self.docs, &include_paths, parser);
+        self.docs.extend(docs);
+        self.update_project()
+    }
+
+    /// Decides on the case sensitivity and the inactive regions of every document, which both
+    /// depend on the entire project
+    fn update_project(&mut self) -> Vec<PathBuf> {
+        symbols::update_case_sensitivity(&mut self.docs);
+        self.update_device_database();
+        let device = self.mcp.as_ref().map(|mcp| mcp.device.as_str());
+        preprocessor::update_inactive_regions(&mut self.docs, device)
+    }
+
+    /// Loads the header of the device the project is compiled for, if the device changed
+    fn update_device_database(&mut self) {
+        let project_device = self.mcp.as_ref().map(|mcp| mcp.device.as_str());
+        let device = preprocessor::find_device(&self.docs, project_device);
+        let is_loaded = match (&self.loaded_device, &device) {
+            (Some(loaded), Some(device)) => {
+                devices::get_header_name(loaded) == devices::get_header_name(device)
+            }
+            (loaded, device) => loaded.is_none() && device.is_none(),
+        };
+        if is_loaded {
+            return;
+        }
+
+        let directories = self.settings.get_device_header_directories();
+        self.device_database = device
+            .as_deref()
+            .and_then(|d| DeviceDatabase::load(&directories, d).ok());
+        self.loaded_device = device;
+    }
+
+    /// Returns the diagnostics of the document at `path`, including those which depend on the
+    /// device
+    pub fn get_diagnostics(&self, path: &Path) -> Result<Vec<Diagnostic>> {
+        let doc = match self.get_doc(&path.to_path_buf())? {
+            TextDocumentType::Source(doc) => doc,
+            TextDocumentType::Ignored => return Ok(vec![]),
+        };
+
+        let mut out = doc.get_diagnostics()?;
+        out.extend(docs::get_include_diagnostics(&self.docs, path));
+        if let Some(db) = &self.device_database {
+            let tree = doc.get_syntax_tree()?;
+            let source = doc.get_source().get_raw().as_bytes();
+            let inactive_regions = doc.get_inactive_regions();
+            out.extend(devices::validate_fuses(tree, source, db, inactive_regions));
+            out.extend(devices::validate_pins(
+                tree,
+                source,
+                db,
+                doc.get_symbol_index().case_sensitive,
+                inactive_regions,
+            ));
+        }
+        Ok(out)
+    }
+
+    /// Returns the diagnostics of the document at `path` and remembers them as sent to the client
+    pub fn publish_diagnostics(&mut self, path: &Path) -> Result<Vec<Diagnostic>> {
+        let out = self.get_diagnostics(path)?;
+        self.published_diagnostics
+            .insert(path.to_path_buf(), out.clone());
+        Ok(out)
+    }
+
+    /// Returns the diagnostics of every document whose diagnostics changed since they were sent
+    /// to the client. Editing one document may change the diagnostics of others, e.g. by removing
+    /// a file they include or by changing the device
+    pub fn get_changed_diagnostics(&mut self) -> Result<Vec<(Url, Vec<Diagnostic>)>> {
+        let mut out = vec![];
+        for (path, published) in self.published_diagnostics.iter() {
+            let diagnostics = self.get_diagnostics(path)?;
+            if diagnostics != *published {
+                out.push((path.clone(), diagnostics));
+            }
+        }
+
+        out.into_iter()
+            .map(|(path, diagnostics)| {
+                let uri = utils::get_uri(&path)?;
+                self.published_diagnostics.insert(path, diagnostics.clone());
+                Ok((uri, diagnostics))
+            })
+            .collect()
+    }
+
+    /// Returns the inactive regions of the document at `path` as sent to the client
+    pub fn get_inactive_regions(&self, path: &Path) -> Result<(Url, Vec<Range>)> {
+        let regions = match self.get_doc(&path.to_path_buf())? {
+            TextDocumentType::Source(doc) => {
+                doc.get_inactive_regions().iter().map(|r| r.range).collect()
+            }
+            TextDocumentType::Ignored => vec![],
+        };
+
+        Ok((utils::get_uri(path)?, regions))
+    }
+
+    pub fn get_docs(&self) -> &HashMap<PathBuf, TextDocumentType> {
+        &self.docs
     }
 
     pub fn get_doc_or_ignored(&mut self, path: PathBuf) -> &mut TextDocumentType {
@@ -73,9 +280,42 @@ impl BackendInner {
         ))
     }
 
+    /// Remembers the tokens sent for `path`, so later requests can be answered with a delta
+    pub fn cache_semantic_tokens(
+        &mut self,
+        path: PathBuf,
+        data: Vec<SemanticToken>,
+    ) -> SemanticTokens {
+        self.semantic_tokens_counter += 1;
+        let tokens = SemanticTokens {
+            result_id: Some(self.semantic_tokens_counter.to_string()),
+            data,
+        };
+        self.semantic_tokens.insert(path, tokens.clone());
+        tokens
+    }
+
+    pub fn get_cached_semantic_tokens(
+        &self,
+        path: &Path,
+        result_id: &str,
+    ) -> Option<&SemanticTokens> {
+        self.semantic_tokens
+            .get(path)
+            .filter(|tokens| tokens.result_id.as_deref() == Some(result_id))
+    }
+
     pub fn clear(&mut self) {
         self.root_path = None;
         self.docs.clear();
+        self.device_database = None;
+        self.loaded_device = None;
+        self.published_diagnostics.clear();
+        self.symbol_map = None;
+        self.statistics = None;
+        self.call_tree = None;
+        self.listing = None;
+        self.semantic_tokens.clear();
         self.mcp = None;
     }
 
@@ -132,7 +372,7 @@ impl BackendInner {
                 let severity = match severity.as_str() {
                     "Info" => DiagnosticSeverity::Information,
                     "Warning" => DiagnosticSeverity::Warning,
-                    "Error" | _ => DiagnosticSeverity::Error,
+                    _ => DiagnosticSeverity::Error,
                 };
 
                 let diagnostic = Diagnostic {
@@ -162,7 +402,7 @@ impl BackendInner {
             paths
                 .into_iter()
                 .filter_map(|path| File::open(path).ok())
-                .filter_map(|file| read_to_string(file))
+                .filter_map(read_to_string)
                 .flat_map(|contents| {
                     contents
                         .lines()
@@ -197,3 +437,28 @@ impl BackendInner {
         out
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::create_docs;
+
+    #[test]
+    fn test_changed_diagnostics_of_other_documents() {
+        let mut inner = BackendInner::default();
+        inner.insert_docs(create_docs(&[(
+            "/project/main.c",
+            "#include \"config.h\"\n",
+        )]));
+        docs::update_included_files(&mut inner.docs, &IncludePaths::default());
+        let main = Path::new("/project/main.c");
+        assert_eq!(inner.publish_diagnostics(main).unwrap().len(), 1);
+        assert!(inner.get_changed_diagnostics().unwrap().is_empty());
+
+        inner.insert_docs(create_docs(&[("/project/config.h", "")]));
+        docs::update_included_files(&mut inner.docs, &IncludePaths::default());
+        let changed = inner.get_changed_diagnostics().unwrap();
+        assert_eq!(changed, vec![(utils::get_uri(main).unwrap(), vec![])]);
+        assert!(inner.get_changed_diagnostics().unwrap().is_empty());
+    }
+}
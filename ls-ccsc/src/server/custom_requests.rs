@@ -0,0 +1,127 @@
+use std::sync::{Arc, Mutex};
+
+use futures::channel::mpsc::UnboundedSender;
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tower_lsp::jsonrpc::{Error, Id, Outgoing, Response};
+use tower_lsp::lsp_types::request::Request;
+
+use crate::compiler_output::DisassemblyRequest;
+use crate::server::BackendInner;
+
+/// A request tower-lsp has no handler for
+#[derive(Debug, Deserialize)]
+struct CustomRequest {
+    id: Id,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+/// Reads the messages of the client from `input`. Custom requests (e.g. `ccsc/disassembly`) are
+/// answered on `responses`, every other message is passed on to `output` unchanged. tower-lsp
+/// only dispatches the methods of the `LanguageServer` trait, so this sits between stdin and it
+pub async fn intercept_custom_requests<I, O>(
+    mut input: I,
+    mut output: O,
+    data: Arc<Mutex<BackendInner>>,
+    responses: UnboundedSender<Outgoing>,
+) -> std::io::Result<()>
+where
+    I: AsyncBufRead + Unpin,
+    O: AsyncWrite + Unpin,
+{
+    loop {
+        let mut header = String::new();
+        let mut content_length = None;
+        loop {
+            let mut line = String::new();
+            if input.read_line(&mut line).await? == 0 {
+                return Ok(());
+            }
+            header.push_str(&line);
+            if line.trim().is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                if name.trim().eq_ignore_ascii_case("Content-Length") {
+                    content_length = value.trim().parse::<usize>().ok();
+                }
+            }
+        }
+
+        let mut body = vec![0; content_length.unwrap_or_default()];
+        input.read_exact(&mut body).await?;
+        let request = serde_json::from_slice::<CustomRequest>(&body)
+            .ok()
+            .filter(|r| r.method == DisassemblyRequest::METHOD);
+        match request {
+            Some(request) => {
+                let response = handle_disassembly(&data, request);
+                if responses
+                    .unbounded_send(Outgoing::Response(response))
+                    .is_err()
+                {
+                    return Ok(());
+                }
+            }
+            None => {
+                output.write_all(header.as_bytes()).await?;
+                output.write_all(&body).await?;
+                output.flush().await?;
+            }
+        }
+    }
+}
+
+fn handle_disassembly(data: &Mutex<BackendInner>, request: CustomRequest) -> Response {
+    let result = serde_json::from_value(request.params)
+        .map_err(|e| Error::invalid_params(e.to_string()))
+        .and_then(|params| data.lock().unwrap().get_disassembly(params))
+        .and_then(|lines| {
+            serde_json::to_value(lines).map_err(|e| Error::invalid_params(e.to_string()))
+        });
+    Response::from_parts(request.id, result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::channel::mpsc;
+    use futures::StreamExt;
+
+    fn create_frame(body: &str) -> String {
+        format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+    }
+
+    #[tokio::test]
+    async fn test_intercept_custom_requests() {
+        let hover =
+            create_frame(r#"{"jsonrpc":"2.0","id":1,"method":"textDocument/hover","params":{}}"#);
+        let disassembly = create_frame(
+            r#"{"jsonrpc":"2.0","id":2,"method":"ccsc/disassembly","params":{
+                "textDocument":{"uri":"file:///project/main.c"},
+                "range":{"start":{"line":0,"character":0},"end":{"line":2,"character":0}}}}"#,
+        );
+        let input = format!("{}{}{}", hover, disassembly, hover);
+        let mut output = vec![];
+        let (tx, mut rx) = mpsc::unbounded();
+
+        intercept_custom_requests(input.as_bytes(), &mut output, Default::default(), tx)
+            .await
+            .unwrap();
+
+        assert_eq!(String::from_utf8(output).unwrap(), hover.repeat(2));
+        let (id, result) = match rx.next().await {
+            Some(Outgoing::Response(response)) => response.into_parts(),
+            other => panic!("Expected a response, got {:?}", other),
+        };
+        assert_eq!(id, Some(Id::Number(2)));
+        assert_eq!(
+            result.unwrap_err().message,
+            "No .lst file found. Build the project first"
+        );
+        assert!(rx.next().await.is_none());
+    }
+}
@@ -1,5 +1,9 @@
 pub use crate::server::backend::*;
 pub use crate::server::backend_inner::*;
+pub use crate::server::custom_requests::*;
+pub use crate::server::server_settings::*;
 
 pub mod backend;
 pub mod backend_inner;
+pub mod custom_requests;
+pub mod server_settings;
@@ -0,0 +1,38 @@
+use std::path::PathBuf;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::devices;
+
+/// Settings passed by the client as `initializationOptions`
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ServerSettings {
+    /// Directory of the PICC device headers, e.g. `C:\Program Files\PICC\Devices`
+    pub device_header_directory: Option<PathBuf>,
+    /// Directories searched for `#include`s, e.g. `C:\Program Files\PICC\Drivers`
+    pub include_directories: Vec<PathBuf>,
+}
+
+impl ServerSettings {
+    /// Falls back to the default settings if the options are missing or invalid
+    pub fn from_initialization_options(options: Option<Value>) -> Self {
+        options
+            .and_then(|options| serde_json::from_value(options).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn get_device_header_directories(&self) -> Vec<PathBuf> {
+        devices::get_header_directories(self.device_header_directory.as_deref())
+    }
+
+    /// The configured include directories followed by the device header directories
+    pub fn get_include_directories(&self) -> Vec<PathBuf> {
+        self.include_directories
+            .iter()
+            .cloned()
+            .chain(self.get_device_header_directories())
+            .collect()
+    }
+}
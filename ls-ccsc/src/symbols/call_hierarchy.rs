@@ -0,0 +1,381 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::{
+    CallHierarchyIncomingCall, CallHierarchyItem, CallHierarchyOutgoingCall, Position, Range,
+    SymbolKind,
+};
+use tree_sitter::Point;
+
+use crate::builtins::BuiltinFunction;
+use crate::compiler_output::{CallTree, CallTreeNode};
+use crate::docs::text_document_type::TextDocumentTypeTrait;
+use crate::docs::{TextDocument, TextDocumentType};
+use crate::symbols::{self, is_same_name, SymbolDefinition, SymbolDefinitionKind};
+use crate::utils;
+
+type Docs = HashMap<PathBuf, TextDocumentType>;
+
+fn get_source_docs(docs: &Docs) -> impl Iterator<Item = (&Path, &TextDocument)> {
+    docs.iter().filter_map(|(path, doc_type)| match doc_type {
+        TextDocumentType::Source(doc) => Some((path.as_path(), doc.as_ref())),
+        TextDocumentType::Ignored => None,
+    })
+}
+
+/// Returns the definitions of the function `name`. Falls back to its prototypes, if the function
+/// is not defined inside the project
+fn get_function_definitions<'a>(
+    docs: &'a Docs,
+    name: &str,
+) -> Vec<(&'a Path, &'a SymbolDefinition)> {
+    let functions = get_source_docs(docs)
+        .flat_map(|(path, doc)| {
+            let index = doc.get_symbol_index();
+            index
+                .definitions
+                .iter()
+                .filter(move |def| is_same_name(&def.name, name, index.case_sensitive))
+                .map(move |def| (path, def))
+        })
+        .filter(|(_, def)| {
+            matches!(
+                def.kind,
+                SymbolDefinitionKind::Function | SymbolDefinitionKind::FunctionPrototype
+            )
+        })
+        .collect::<Vec<_>>();
+    let has_definition = functions
+        .iter()
+        .any(|(_, def)| def.kind == SymbolDefinitionKind::Function);
+
+    functions
+        .into_iter()
+        .filter(|(_, def)| !has_definition || def.kind == SymbolDefinitionKind::Function)
+        .collect()
+}
+
+/// Returns the definitions `item` was created for
+fn get_item_definitions<'a>(
+    docs: &'a Docs,
+    item: &CallHierarchyItem,
+) -> Vec<(&'a Path, &'a SymbolDefinition)> {
+    let definitions = get_function_definitions(docs, &item.name);
+    let path = item.uri.to_file_path().ok();
+    let exact = definitions
+        .iter()
+        .filter(|(p, def)| {
+            Some(*p) == path.as_deref() && def.selection_range == item.selection_range
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+
+    if exact.is_empty() {
+        definitions
+    } else {
+        exact
+    }
+}
+
+fn create_source_item(
+    path: &Path,
+    def: &SymbolDefinition,
+    call_tree: Option<&CallTree>,
+) -> Result<CallHierarchyItem> {
+    let detail = call_tree
+        .and_then(|tree| tree.get_nodes(&def.name).next())
+        .map(|node| node.get_summary())
+        .or_else(|| path.file_name().map(|n| n.to_string_lossy().into_owned()));
+
+    Ok(CallHierarchyItem {
+        name: def.name.clone(),
+        kind: SymbolKind::Function,
+        tags: None,
+        detail,
+        uri: utils::get_uri(path)?,
+        range: def.range,
+        selection_range: def.selection_range,
+        data: None,
+    })
+}
+
+/// Functions which only the compiler knows of, e.g. `@cinit1`, point to their line in the .tre
+/// file
+fn create_call_tree_item(call_tree: &CallTree, node: &CallTreeNode) -> Result<CallHierarchyItem> {
+    let range = Range::new(Position::new(node.line, 0), Position::new(node.line + 1, 0));
+
+    Ok(CallHierarchyItem {
+        name: node.name.clone(),
+        kind: SymbolKind::Function,
+        tags: None,
+        detail: Some(node.get_summary()),
+        uri: utils::get_uri(&call_tree.path)?,
+        range,
+        selection_range: range,
+        data: None,
+    })
+}
+
+/// Returns the item of the function `name`, preferring its definition over the call tree
+fn create_item(
+    docs: &Docs,
+    name: &str,
+    call_tree: Option<&CallTree>,
+) -> Result<Option<CallHierarchyItem>> {
+    if let Some((path, def)) = get_function_definitions(docs, name).into_iter().next() {
+        return create_source_item(path, def, call_tree).map(Some);
+    }
+    match call_tree.and_then(|tree| tree.get_nodes(name).next().map(|node| (tree, node))) {
+        Some((tree, node)) => create_call_tree_item(tree, node).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Returns the functions named by the identifier at `point`
+pub fn prepare_call_hierarchy(
+    docs: &Docs,
+    path: &Path,
+    point: Point,
+    call_tree: Option<&CallTree>,
+) -> Result<Option<Vec<CallHierarchyItem>>> {
+    let doc = match symbols::get_source_doc(docs, path) {
+        Some(doc) => doc,
+        None => return Ok(None),
+    };
+    let source = doc.get_source().get_raw().as_bytes();
+    let name = match symbols::get_identifier_at(doc.get_syntax_tree()?, point) {
+        Some(identifier) => identifier.utf8_text(source).unwrap_or_default(),
+        None => return Ok(None),
+    };
+
+    let definitions = get_function_definitions(docs, name);
+    if definitions.is_empty() {
+        return Ok(create_item(docs, name, call_tree)?.map(|item| vec![item]));
+    }
+    let items = definitions
+        .into_iter()
+        .map(|(path, def)| create_source_item(path, def, call_tree))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Some(items))
+}
+
+/// Returns the functions calling `item`. Calls inside the project come first, followed by the
+/// callers only the compiler's call tree knows of
+pub fn find_incoming_calls(
+    docs: &Docs,
+    item: &CallHierarchyItem,
+    call_tree: Option<&CallTree>,
+) -> Result<Vec<CallHierarchyIncomingCall>> {
+    let mut out: Vec<CallHierarchyIncomingCall> = vec![];
+    for (path, doc) in get_source_docs(docs) {
+        let index = doc.get_symbol_index();
+        let calls = index.occurrences.iter().filter(|o| {
+            o.argument_count.is_some()
+                && o.scope.is_none()
+                && is_same_name(&o.name, &item.name, index.case_sensitive)
+        });
+        for call in calls {
+            let caller = index.definitions.iter().find(|def| {
+                def.kind == SymbolDefinitionKind::Function
+                    && def.range.start <= call.range.start
+                    && call.range.end <= def.range.end
+            });
+            let caller = match caller {
+                Some(caller) => caller,
+                None => continue,
+            };
+            let from = create_source_item(path, caller, call_tree)?;
+            match out
+                .iter_mut()
+                .find(|c| c.from.uri == from.uri && c.from.selection_range == from.selection_range)
+            {
+                Some(incoming) => incoming.from_ranges.push(call.range),
+                None => out.push(CallHierarchyIncomingCall {
+                    from,
+                    from_ranges: vec![call.range],
+                }),
+            }
+        }
+    }
+
+    let callers = call_tree
+        .map(|tree| tree.get_callers(&item.name))
+        .unwrap_or_default();
+    for caller in callers {
+        if out
+            .iter()
+            .any(|c| c.from.name.eq_ignore_ascii_case(&caller.name))
+        {
+            continue;
+        }
+        if let Some(from) = create_item(docs, &caller.name, call_tree)? {
+            out.push(CallHierarchyIncomingCall {
+                from_ranges: vec![from.selection_range],
+                from,
+            });
+        }
+    }
+    Ok(out)
+}
+
+/// Returns the functions `item` calls. Besides the calls inside of its body, these are the
+/// functions the compiler generated for it, e.g. `@delay_ms1` for `delay_ms()` or `@cinit1`
+pub fn find_outgoing_calls(
+    docs: &Docs,
+    item: &CallHierarchyItem,
+    call_tree: Option<&CallTree>,
+) -> Result<Vec<CallHierarchyOutgoingCall>> {
+    let callees = call_tree
+        .map(|tree| tree.get_callees(&item.name))
+        .unwrap_or_default();
+    let mut emitted = vec![];
+    let mut out: Vec<CallHierarchyOutgoingCall> = vec![];
+    for (path, def) in get_item_definitions(docs, item) {
+        let doc = match symbols::get_source_doc(docs, path) {
+            Some(doc) => doc,
+            None => continue,
+        };
+        let index = doc.get_symbol_index();
+        let calls = index.occurrences.iter().filter(|o| {
+            o.argument_count.is_some()
+                && o.scope.is_none()
+                && def.range.start <= o.range.start
+                && o.range.end <= def.range.end
+        });
+        for call in calls {
+            if let Some(outgoing) = out
+                .iter_mut()
+                .find(|c| is_same_name(&c.to.name, &call.name, index.case_sensitive))
+            {
+                outgoing.from_ranges.push(call.range);
+                continue;
+            }
+
+            // Built-in functions are generated as e.g. `@delay_ms1`, if they are not inlined
+            let generated = callees
+                .iter()
+                .find(|n| n.get_base_name().eq_ignore_ascii_case(&call.name));
+            let to = if let Some((path, def)) = get_function_definitions(docs, &call.name)
+                .into_iter()
+                .next()
+            {
+                create_source_item(path, def, call_tree)?
+            } else if let (Some(tree), Some(node)) = (call_tree, generated) {
+                let mut to = create_call_tree_item(tree, node)?;
+                to.name = call.name.clone();
+                to
+            } else if let Some(builtin) = BuiltinFunction::find(&call.name, index.case_sensitive) {
+                CallHierarchyItem {
+                    name: call.name.clone(),
+                    kind: SymbolKind::Function,
+                    tags: None,
+                    detail: Some(builtin.get_signature()),
+                    uri: utils::get_uri(path)?,
+                    range: call.range,
+                    selection_range: call.range,
+                    data: None,
+                }
+            } else {
+                continue;
+            };
+            emitted.extend(generated.map(|n| n.name.clone()));
+            out.push(CallHierarchyOutgoingCall {
+                to,
+                from_ranges: vec![call.range],
+            });
+        }
+    }
+
+    for callee in callees {
+        let is_known = out
+            .iter()
+            .any(|c| c.to.name.eq_ignore_ascii_case(&callee.name))
+            || emitted.contains(&callee.name);
+        if is_known {
+            continue;
+        }
+        if let Some(to) = create_item(docs, &callee.name, call_tree)? {
+            out.push(CallHierarchyOutgoingCall {
+                to,
+                from_ranges: vec![item.selection_range],
+            });
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::create_docs;
+
+    #[test]
+    fn test_source_calls() {
+        let docs = create_docs(&[(
+            "/project/main.c",
+            "int add(int a, int b) { return a + b; }\n\
+             void main() { int c = add(1, 2); c = add(c, 3); delay_ms(10); }\n",
+        )]);
+        let path = Path::new("/project/main.c");
+
+        let items = prepare_call_hierarchy(&docs, path, Point::new(1, 24), None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].name, "add");
+
+        let incoming = find_incoming_calls(&docs, &items[0], None).unwrap();
+        assert_eq!(incoming.len(), 1);
+        assert_eq!(incoming[0].from.name, "main");
+        assert_eq!(incoming[0].from_ranges.len(), 2);
+
+        let main = &incoming[0].from;
+        let outgoing = find_outgoing_calls(&docs, main, None).unwrap();
+        let names = outgoing
+            .iter()
+            .map(|c| (c.to.name.as_str(), c.from_ranges.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec![("add", 2), ("delay_ms", 1)]);
+    }
+
+    #[test]
+    fn test_call_tree_calls() {
+        let docs = create_docs(&[(
+            "/project/main.c",
+            "int add(int a, int b) { return a + b; }\n\
+             void main() { int c = add(1, 2); delay_ms(10); }\n",
+        )]);
+        let mut call_tree = CallTree::from_tre(
+            "└─main\n   └─MAIN  0/50  Ram=2\n      ├─@cinit1  (Inline)  Ram=0\n\
+             \x20     ├─add  0/5  Ram=2\n      └─@delay_ms1  0/22  Ram=1\n",
+        );
+        call_tree.path = PathBuf::from("/project/main.tre");
+        let path = Path::new("/project/main.c");
+
+        let main = prepare_call_hierarchy(&docs, path, Point::new(1, 6), Some(&call_tree))
+            .unwrap()
+            .unwrap()
+            .remove(0);
+        assert_eq!(main.detail.as_deref(), Some("ROM 50 words, RAM 2 bytes"));
+
+        let outgoing = find_outgoing_calls(&docs, &main, Some(&call_tree)).unwrap();
+        let names = outgoing
+            .iter()
+            .map(|c| (c.to.name.as_str(), c.to.uri.path()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                ("add", "/project/main.c"),
+                ("delay_ms", "/project/main.tre"),
+                ("@cinit1", "/project/main.tre"),
+            ]
+        );
+
+        let cinit = &outgoing[2].to;
+        let incoming = find_incoming_calls(&docs, cinit, Some(&call_tree)).unwrap();
+        assert_eq!(incoming.len(), 1);
+        assert_eq!(incoming[0].from.name, "main");
+    }
+}
@@ -0,0 +1,367 @@
+use tower_lsp::lsp_types::{DocumentSymbol, Range, SymbolKind};
+use tree_sitter::{Node, Tree};
+
+use crate::directives::{CcsDirective, DirectiveKind, DIRECTIVE_KINDS};
+use crate::preprocessor::{self, InactiveRegion};
+use crate::symbols::{get_declarator_identifier, get_function_declarator};
+use crate::utils;
+
+/// Builds the outline of a document. Structs, unions and enums contain their members, `#int_xxx`
+/// directives contain the function handling the interrupt. Symbols of inactive branches are left out
+pub fn get_document_symbols(
+    tree: &Tree,
+    source: &[u8],
+    inactive_regions: &[InactiveRegion],
+) -> Vec<DocumentSymbol> {
+    let mut out = vec![];
+    collect_top_level_symbols(tree.root_node(), source, &mut out);
+    out.retain(|s| !preprocessor::is_inactive_line(inactive_regions, s.selection_range.start.line));
+    out
+}
+
+#[allow(deprecated)]
+fn create_symbol(
+    name: String,
+    detail: Option<String>,
+    kind: SymbolKind,
+    range: Range,
+    selection_range: Range,
+    children: Vec<DocumentSymbol>,
+) -> DocumentSymbol {
+    DocumentSymbol {
+        name,
+        detail,
+        kind,
+        tags: None,
+        deprecated: None,
+        range,
+        selection_range,
+        children: if children.is_empty() {
+            None
+        } else {
+            Some(children)
+        },
+    }
+}
+
+fn get_text(node: Node, source: &[u8]) -> String {
+    node.utf8_text(source).unwrap_or_default().trim().to_owned()
+}
+
+fn collect_top_level_symbols(node: Node, source: &[u8], out: &mut Vec<DocumentSymbol>) {
+    let mut cursor = node.walk();
+    let mut interrupt: Option<DocumentSymbol> = None;
+
+    for child in node.named_children(&mut cursor) {
+        if child.kind() == "comment" {
+            continue;
+        }
+
+        if let Some(mut directive) = interrupt.take() {
+            if child.kind() == "function_definition" {
+                let handler = get_function_symbol(child, source);
+                directive.detail = Some(handler.name.clone());
+                directive.range.end = handler.range.end;
+                directive.children = Some(vec![handler]);
+                out.push(directive);
+                continue;
+            }
+            out.push(directive);
+        }
+
+        match child.kind() {
+            "function_definition" => out.push(get_function_symbol(child, source)),
+            "declaration" => collect_declaration_symbols(child, source, out),
+            "type_definition" => collect_type_definition_symbols(child, source, out),
+            "struct_specifier" | "union_specifier" | "enum_specifier" => {
+                out.extend(get_type_specifier_symbol(child, source))
+            }
+            "preproc_def" | "preproc_function_def" => out.push(get_macro_symbol(child, source)),
+            k if DIRECTIVE_KINDS.contains(&k) => match get_directive_symbol(child, source) {
+                Some(symbol) if symbol.kind == SymbolKind::Event => interrupt = Some(symbol),
+                Some(symbol) => out.push(symbol),
+                None => {}
+            },
+            "preproc_if"
+            | "preproc_ifdef"
+            | "preproc_else"
+            | "preproc_elif"
+            | "linkage_specification"
+            | "declaration_list" => collect_top_level_symbols(child, source, out),
+            _ => {}
+        }
+    }
+
+    out.extend(interrupt);
+}
+
+fn get_function_symbol(node: Node, source: &[u8]) -> DocumentSymbol {
+    let declarator = node.child_by_field_name("declarator");
+    let name = declarator
+        .and_then(get_declarator_identifier)
+        .unwrap_or(node);
+    let detail = node
+        .child_by_field_name("type")
+        .zip(declarator.and_then(get_function_declarator))
+        .and_then(|(t, d)| d.child_by_field_name("parameters").map(|p| (t, p)))
+        .map(|(t, p)| format!("{} {}", get_text(t, source), get_text(p, source)));
+
+    create_symbol(
+        get_text(name, source),
+        detail,
+        SymbolKind::Function,
+        utils::get_range(&node),
+        utils::get_range(&name),
+        vec![],
+    )
+}
+
+fn collect_declaration_symbols(node: Node, source: &[u8], out: &mut Vec<DocumentSymbol>) {
+    fn is_const(node: Node, source: &[u8]) -> bool {
+        let mut cursor = node.walk();
+        let out = node
+            .children(&mut cursor)
+            .any(|c| c.kind() == "type_qualifier" && get_text(c, source) == "const");
+        out
+    }
+
+    let type_specifier = node.child_by_field_name("type");
+    out.extend(type_specifier.and_then(|t| get_type_specifier_symbol(t, source)));
+
+    let type_text = type_specifier.map(|t| get_text(t, source));
+    let mut cursor = node.walk();
+    for declarator in node.children_by_field_name("declarator", &mut cursor) {
+        let name = match get_declarator_identifier(declarator) {
+            Some(name) => name,
+            None => continue,
+        };
+        let kind = if declarator.kind() != "init_declarator"
+            && get_function_declarator(declarator).is_some()
+        {
+            SymbolKind::Function
+        } else if is_const(node, source) {
+            SymbolKind::Constant
+        } else {
+            SymbolKind::Variable
+        };
+
+        out.push(create_symbol(
+            get_text(name, source),
+            type_text.clone(),
+            kind,
+            utils::get_range(&node),
+            utils::get_range(&name),
+            vec![],
+        ));
+    }
+}
+
+fn collect_type_definition_symbols(node: Node, source: &[u8], out: &mut Vec<DocumentSymbol>) {
+    let type_specifier = node.child_by_field_name("type");
+    let mut members = type_specifier
+        .and_then(|t| get_type_specifier_symbol(t, source))
+        .and_then(|symbol| symbol.children)
+        .unwrap_or_default();
+    let kind = match type_specifier.map(|t| t.kind()) {
+        Some("struct_specifier") | Some("union_specifier") => SymbolKind::Struct,
+        Some("enum_specifier") => SymbolKind::Enum,
+        _ => SymbolKind::TypeParameter,
+    };
+
+    let mut cursor = node.walk();
+    for declarator in node.children_by_field_name("declarator", &mut cursor) {
+        if let Some(name) = get_declarator_identifier(declarator) {
+            out.push(create_symbol(
+                get_text(name, source),
+                Some("typedef".to_owned()),
+                kind,
+                utils::get_range(&node),
+                utils::get_range(&name),
+                std::mem::take(&mut members),
+            ));
+        }
+    }
+}
+
+/// Returns a symbol for a `struct_specifier`, `union_specifier` or `enum_specifier` with a body
+fn get_type_specifier_symbol(node: Node, source: &[u8]) -> Option<DocumentSymbol> {
+    fn get_member_symbols(body: Node, source: &[u8]) -> Vec<DocumentSymbol> {
+        let mut cursor = body.walk();
+        let mut out = vec![];
+        for member in body.named_children(&mut cursor) {
+            match member.kind() {
+                "enumerator" => {
+                    if let Some(name) = member.child_by_field_name("name") {
+                        out.push(create_symbol(
+                            get_text(name, source),
+                            member
+                                .child_by_field_name("value")
+                                .map(|v| get_text(v, source)),
+                            SymbolKind::EnumMember,
+                            utils::get_range(&member),
+                            utils::get_range(&name),
+                            vec![],
+                        ));
+                    }
+                }
+                "field_declaration" => {
+                    let type_text = member
+                        .child_by_field_name("type")
+                        .map(|t| get_text(t, source));
+                    let mut field_cursor = member.walk();
+                    for declarator in member.children_by_field_name("declarator", &mut field_cursor)
+                    {
+                        if let Some(name) = get_declarator_identifier(declarator) {
+                            out.push(create_symbol(
+                                get_text(name, source),
+                                type_text.clone(),
+                                SymbolKind::Field,
+                                utils::get_range(&member),
+                                utils::get_range(&name),
+                                vec![],
+                            ));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        out
+    }
+
+    let kind = match node.kind() {
+        "struct_specifier" | "union_specifier" => SymbolKind::Struct,
+        "enum_specifier" => SymbolKind::Enum,
+        _ => return None,
+    };
+    let body = node.child_by_field_name("body")?;
+    let name = node.child_by_field_name("name");
+    let keyword = node.kind().trim_end_matches("_specifier");
+
+    Some(create_symbol(
+        name.map(|n| get_text(n, source))
+            .unwrap_or_else(|| format!("({} anonymous)", keyword)),
+        Some(keyword.to_owned()),
+        kind,
+        utils::get_range(&node),
+        utils::get_range(&name.unwrap_or(node)),
+        get_member_symbols(body, source),
+    ))
+}
+
+fn get_macro_symbol(node: Node, source: &[u8]) -> DocumentSymbol {
+    let name = node.child_by_field_name("name").unwrap_or(node);
+    let detail = match node.child_by_field_name("parameters") {
+        Some(parameters) => Some(format!(
+            "{} {}",
+            get_text(parameters, source),
+            node.child_by_field_name("value")
+                .map(|v| get_text(v, source))
+                .unwrap_or_default()
+        )),
+        None => node
+            .child_by_field_name("value")
+            .map(|v| get_text(v, source)),
+    };
+
+    create_symbol(
+        get_text(name, source),
+        detail,
+        SymbolKind::Constant,
+        utils::get_range(&node),
+        utils::get_range(&name),
+        vec![],
+    )
+}
+
+/// Returns a symbol for CCS specific directives like `#fuses`, `#use delay(...)` and `#int_xxx`
+fn get_directive_symbol(node: Node, source: &[u8]) -> Option<DocumentSymbol> {
+    let directive = CcsDirective::from_node(node, source)?;
+    let CcsDirective {
+        kind,
+        directive: name,
+        target,
+        argument_text,
+        range,
+        ..
+    } = directive;
+    let selection_range = name.range;
+
+    let (name, detail, kind) = match kind {
+        DirectiveKind::Fuses | DirectiveKind::Device => {
+            (name.text, argument_text, SymbolKind::Property)
+        }
+        DirectiveKind::Use => {
+            let library = target.map(|t| t.text).unwrap_or_default();
+            let options = argument_text
+                .trim_start()
+                .trim_start_matches(library.as_str())
+                .trim()
+                .to_owned();
+            (
+                format!("{} {}", name.text, library),
+                options,
+                SymbolKind::Module,
+            )
+        }
+        DirectiveKind::Interrupt => (name.text, argument_text, SymbolKind::Event),
+        _ => return None,
+    };
+
+    Some(create_symbol(
+        name,
+        if detail.is_empty() {
+            None
+        } else {
+            Some(detail)
+        },
+        kind,
+        range,
+        selection_range,
+        vec![],
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::parse;
+
+    fn get_names(symbols: &[DocumentSymbol]) -> Vec<(String, SymbolKind)> {
+        symbols.iter().map(|s| (s.name.clone(), s.kind)).collect()
+    }
+
+    #[test]
+    fn test_ccs_outline() {
+        let raw = "#include <16F883.h>\n#fuses XT, NOWDT\n#use delay(clock=4Mhz)\n\
+                   #define LED PIN_C0\nint16 ticks;\n\n#int_timer1\nvoid timer1_isr() {\n\tticks++;\n}\n\n\
+                   typedef struct { int8 a; int8 b; } pair;\n\nvoid main() {\n}\n";
+        let tree = parse(raw);
+
+        let symbols = get_document_symbols(&tree, raw.as_bytes(), &[]);
+
+        assert_eq!(
+            get_names(&symbols),
+            vec![
+                ("#fuses".to_owned(), SymbolKind::Property),
+                ("#use delay".to_owned(), SymbolKind::Module),
+                ("LED".to_owned(), SymbolKind::Constant),
+                ("ticks".to_owned(), SymbolKind::Variable),
+                ("#int_timer1".to_owned(), SymbolKind::Event),
+                ("pair".to_owned(), SymbolKind::Struct),
+                ("main".to_owned(), SymbolKind::Function),
+            ]
+        );
+        assert_eq!(
+            get_names(symbols[4].children.as_ref().unwrap()),
+            vec![("timer1_isr".to_owned(), SymbolKind::Function)]
+        );
+        assert_eq!(
+            get_names(symbols[5].children.as_ref().unwrap()),
+            vec![
+                ("a".to_owned(), SymbolKind::Field),
+                ("b".to_owned(), SymbolKind::Field)
+            ]
+        );
+    }
+}
@@ -0,0 +1,15 @@
+pub use crate::symbols::call_hierarchy::*;
+pub use crate::symbols::document_symbols::*;
+pub use crate::symbols::symbol_definition::*;
+pub use crate::symbols::symbol_index::*;
+pub use crate::symbols::symbol_lookup::*;
+pub use crate::symbols::symbol_rename::*;
+pub use crate::symbols::workspace_symbols::*;
+
+pub mod call_hierarchy;
+pub mod document_symbols;
+pub mod symbol_definition;
+pub mod symbol_index;
+pub mod symbol_lookup;
+pub mod symbol_rename;
+pub mod workspace_symbols;
@@ -0,0 +1,324 @@
+use std::ops::{Range, RangeInclusive};
+
+use tree_sitter::{Node, Tree};
+
+use crate::utils;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolDefinitionKind {
+    Function,
+    FunctionPrototype,
+    Variable,
+    Parameter,
+    Macro,
+    FunctionMacro,
+    Type,
+    EnumConstant,
+}
+
+/// A symbol introduced by a `function_definition`, `declaration`, `preproc_def`, ... node
+#[derive(Debug, Clone, PartialEq)]
+pub struct SymbolDefinition {
+    pub name: String,
+    pub kind: SymbolDefinitionKind,
+    /// Kind of the tree-sitter node defining the symbol (e.g. `function_definition`)
+    pub node_kind: &'static str,
+    /// Range of the entire defining node
+    pub range: tower_lsp::lsp_types::Range,
+    /// Range of the identifier naming the symbol
+    pub selection_range: tower_lsp::lsp_types::Range,
+    /// Byte range of the identifier naming the symbol
+    pub name_bytes: Range<usize>,
+    /// Byte range in which the symbol is visible. `None` if it is visible in the entire file
+    pub scope: Option<Range<usize>>,
+    /// Number of arguments accepted by functions and function macros. Tells overloads apart
+    pub arity: Option<RangeInclusive<usize>>,
+}
+
+impl SymbolDefinition {
+    pub fn from_tree(tree: &Tree, source: &[u8]) -> Vec<SymbolDefinition> {
+        let mut out = vec![];
+        collect_definitions(tree.root_node(), source, None, &mut out);
+        out
+    }
+
+    pub fn is_global(&self) -> bool {
+        self.scope.is_none()
+    }
+
+    /// Returns true, if the symbol can be referred to from `byte`
+    pub fn is_visible_at(&self, byte: usize) -> bool {
+        match &self.scope {
+            None => true,
+            Some(scope) => scope.contains(&byte) && self.name_bytes.start <= byte,
+        }
+    }
+}
+
+/// Returns the identifier which is named by a (possibly nested) declarator
+pub fn get_declarator_identifier(declarator: Node) -> Option<Node> {
+    match declarator.kind() {
+        "identifier" | "type_identifier" | "field_identifier" => Some(declarator),
+        "parenthesized_declarator" | "attributed_declarator" => declarator
+            .named_child(0)
+            .and_then(get_declarator_identifier),
+        _ => declarator
+            .child_by_field_name("declarator")
+            .and_then(get_declarator_identifier),
+    }
+}
+
+/// Returns the number of arguments a `parameter_list` or `preproc_params` accepts. Parameters
+/// with default values are optional, variadic functions accept any number of arguments
+pub fn get_arity(parameters: Node, source: &[u8]) -> RangeInclusive<usize> {
+    let (mut required, mut optional, mut variadic) = (0, 0, false);
+    let mut cursor = parameters.walk();
+    for parameter in parameters.children(&mut cursor) {
+        match parameter.kind() {
+            "parameter_declaration" => {
+                // `f(void)` takes no arguments
+                let is_void = parameter.child_by_field_name("declarator").is_none()
+                    && parameter
+                        .child_by_field_name("type")
+                        .is_some_and(|t| t.utf8_text(source) == Ok("void"));
+                if !is_void {
+                    required += 1;
+                }
+            }
+            "identifier" => required += 1,
+            "optional_parameter_declaration" => optional += 1,
+            "variadic_parameter" | "..." => variadic = true,
+            _ => {}
+        }
+    }
+
+    let max = if variadic {
+        usize::MAX
+    } else {
+        required + optional
+    };
+    required..=max
+}
+
+/// Returns the `function_declarator` of a (possibly nested) declarator, if there is one
+pub fn get_function_declarator(declarator: Node) -> Option<Node> {
+    match declarator.kind() {
+        "function_declarator" => Some(declarator),
+        "identifier" | "type_identifier" | "field_identifier" => None,
+        "parenthesized_declarator" | "attributed_declarator" => {
+            declarator.named_child(0).and_then(get_function_declarator)
+        }
+        _ => declarator
+            .child_by_field_name("declarator")
+            .and_then(get_function_declarator),
+    }
+}
+
+fn collect_definitions(
+    node: Node,
+    source: &[u8],
+    scope: Option<Range<usize>>,
+    out: &mut Vec<SymbolDefinition>,
+) {
+    fn push_definition(
+        node: Node,
+        name: Node,
+        kind: SymbolDefinitionKind,
+        source: &[u8],
+        scope: Option<Range<usize>>,
+        out: &mut Vec<SymbolDefinition>,
+    ) {
+        let arity = match kind {
+            SymbolDefinitionKind::Function | SymbolDefinitionKind::FunctionPrototype => {
+                // The `function_declarator` is the closest one wrapping the name
+                let mut curr = name.parent();
+                while let Some(c) = curr.filter(|c| c.kind() != "function_declarator") {
+                    curr = if c == node { None } else { c.parent() };
+                }
+                curr.and_then(|d| d.child_by_field_name("parameters"))
+                    .map(|p| get_arity(p, source))
+            }
+            SymbolDefinitionKind::FunctionMacro => node
+                .child_by_field_name("parameters")
+                .map(|p| get_arity(p, source)),
+            _ => None,
+        };
+        if let Ok(text) = name.utf8_text(source) {
+            out.push(SymbolDefinition {
+                name: text.to_owned(),
+                kind,
+                node_kind: node.kind(),
+                range: utils::get_range(&node),
+                selection_range: utils::get_range(&name),
+                name_bytes: name.byte_range(),
+                scope,
+                arity,
+            });
+        }
+    }
+    fn collect_parameters(
+        node: Node,
+        declarator: Node,
+        source: &[u8],
+        out: &mut Vec<SymbolDefinition>,
+    ) {
+        let parameters =
+            get_function_declarator(declarator).and_then(|d| d.child_by_field_name("parameters"));
+        let parameters = match parameters {
+            Some(parameters) => parameters,
+            None => return,
+        };
+
+        let mut cursor = parameters.walk();
+        for parameter in parameters.named_children(&mut cursor) {
+            if let Some(name) = parameter
+                .child_by_field_name("declarator")
+                .and_then(get_declarator_identifier)
+            {
+                let scope = Some(node.byte_range());
+                let kind = SymbolDefinitionKind::Parameter;
+                push_definition(parameter, name, kind, source, scope, out);
+            }
+        }
+    }
+    fn collect_type_specifier(
+        node: Node,
+        source: &[u8],
+        scope: Option<Range<usize>>,
+        out: &mut Vec<SymbolDefinition>,
+    ) {
+        if !matches!(
+            node.kind(),
+            "struct_specifier" | "union_specifier" | "enum_specifier"
+        ) {
+            return;
+        }
+
+        let body = match node.child_by_field_name("body") {
+            Some(body) => body,
+            None => return,
+        };
+        if let Some(name) = node.child_by_field_name("name") {
+            let kind = SymbolDefinitionKind::Type;
+            push_definition(node, name, kind, source, scope.clone(), out);
+        }
+
+        let mut cursor = body.walk();
+        for enumerator in body
+            .named_children(&mut cursor)
+            .filter(|c| c.kind() == "enumerator")
+        {
+            if let Some(name) = enumerator.child_by_field_name("name") {
+                let kind = SymbolDefinitionKind::EnumConstant;
+                push_definition(enumerator, name, kind, source, scope.clone(), out);
+            }
+        }
+    }
+
+    let mut cursor = node.walk();
+    match node.kind() {
+        "function_definition" => {
+            if let Some(declarator) = node.child_by_field_name("declarator") {
+                if let Some(name) = get_declarator_identifier(declarator) {
+                    let kind = SymbolDefinitionKind::Function;
+                    push_definition(node, name, kind, source, scope.clone(), out);
+                }
+                collect_parameters(node, declarator, source, out);
+            }
+            // Parameters and the outermost block of the body share the same scope
+            if let Some(body) = node.child_by_field_name("body") {
+                let scope = Some(node.byte_range());
+                let mut body_cursor = body.walk();
+                for child in body.named_children(&mut body_cursor) {
+                    collect_definitions(child, source, scope.clone(), out);
+                }
+            }
+        }
+        "declaration" | "type_definition" => {
+            if let Some(type_specifier) = node.child_by_field_name("type") {
+                collect_type_specifier(type_specifier, source, scope.clone(), out);
+            }
+            for declarator in node.children_by_field_name("declarator", &mut cursor) {
+                let name = match get_declarator_identifier(declarator) {
+                    Some(name) => name,
+                    None => continue,
+                };
+                let kind = if node.kind() == "type_definition" {
+                    SymbolDefinitionKind::Type
+                } else if declarator.kind() != "init_declarator"
+                    && get_function_declarator(declarator).is_some()
+                {
+                    SymbolDefinitionKind::FunctionPrototype
+                } else {
+                    SymbolDefinitionKind::Variable
+                };
+                push_definition(node, name, kind, source, scope.clone(), out);
+            }
+        }
+        "preproc_def" | "preproc_function_def" => {
+            if let Some(name) = node.child_by_field_name("name") {
+                let kind = if node.kind() == "preproc_def" {
+                    SymbolDefinitionKind::Macro
+                } else {
+                    SymbolDefinitionKind::FunctionMacro
+                };
+                // Macros ignore the scopes of C, so they are resolved file-wide like globals
+                push_definition(node, name, kind, source, None, out);
+            }
+        }
+        "struct_specifier" | "union_specifier" | "enum_specifier" => {
+            collect_type_specifier(node, source, scope, out);
+        }
+        "compound_statement" | "for_statement" => {
+            let scope = Some(node.byte_range());
+            for child in node.named_children(&mut cursor) {
+                collect_definitions(child, source, scope.clone(), out);
+            }
+        }
+        _ => {
+            for child in node.named_children(&mut cursor) {
+                collect_definitions(child, source, scope.clone(), out);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::parse;
+
+    fn get_definitions(raw: &str) -> Vec<SymbolDefinition> {
+        SymbolDefinition::from_tree(&parse(raw), raw.as_bytes())
+    }
+
+    #[test]
+    fn test_reference_and_default_parameters() {
+        let raw = "void lcd_putc(char c, int1 raw = FALSE);\n\
+                   void swap(int8 &a, int8 &b) {\n}\n\
+                   void lcd_gotoxy(int8 &x, int8 y = 1) {\n}\n\
+                   int8 sum(int8 n, ...);\n";
+        let definitions = get_definitions(raw);
+
+        let arities = definitions
+            .iter()
+            .filter_map(|d| d.arity.clone().map(|a| (d.name.as_str(), a)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            arities,
+            vec![
+                ("lcd_putc", 1..=2),
+                ("swap", 2..=2),
+                ("lcd_gotoxy", 1..=2),
+                ("sum", 1..=usize::MAX),
+            ]
+        );
+
+        let parameters = definitions
+            .iter()
+            .filter(|d| d.kind == SymbolDefinitionKind::Parameter)
+            .map(|d| d.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(parameters, vec!["a", "b", "x", "y"]);
+    }
+}
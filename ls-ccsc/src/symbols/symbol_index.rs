@@ -0,0 +1,320 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use tower_lsp::lsp_types::Position;
+use tree_sitter::{Node, Tree};
+
+use crate::preprocessor::InactiveRegion;
+use crate::symbols::SymbolDefinition;
+use crate::utils;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolOccurrenceRole {
+    /// The identifier names the symbol in its `declaration`, `function_definition`, ...
+    Declaration,
+    /// The identifier names the symbol in an `init_declarator`
+    InitializedDeclaration,
+    /// The identifier is the left-hand side of an `assignment_expression` or `update_expression`
+    Write,
+    Read,
+}
+
+/// An `identifier` referring to a symbol
+#[derive(Debug, Clone, PartialEq)]
+pub struct SymbolOccurrence {
+    pub name: String,
+    pub role: SymbolOccurrenceRole,
+    pub range: tower_lsp::lsp_types::Range,
+    pub bytes: Range<usize>,
+    /// Scope of the local definition the identifier refers to. `None` for global symbols
+    pub scope: Option<Range<usize>>,
+    /// Number of arguments, if the identifier is called
+    pub argument_count: Option<usize>,
+}
+
+impl SymbolOccurrence {
+    pub fn is_declaration(&self) -> bool {
+        matches!(
+            self.role,
+            SymbolOccurrenceRole::Declaration | SymbolOccurrenceRole::InitializedDeclaration
+        )
+    }
+
+    /// Returns true, if both occurrences refer to the same symbol
+    pub fn refers_to_same_symbol(&self, other: &SymbolOccurrence, case_sensitive: bool) -> bool {
+        is_same_name(&self.name, &other.name, case_sensitive) && self.scope == other.scope
+    }
+}
+
+/// CCS ignores the case of identifiers unless `#case` is used, so `Output_Low` calls `output_low`
+pub fn is_same_name(a: &str, b: &str, case_sensitive: bool) -> bool {
+    if case_sensitive {
+        a == b
+    } else {
+        a.eq_ignore_ascii_case(b)
+    }
+}
+
+/// Returns the key under which `name` is looked up
+pub fn get_symbol_key(name: &str, case_sensitive: bool) -> String {
+    if case_sensitive {
+        name.to_owned()
+    } else {
+        name.to_ascii_lowercase()
+    }
+}
+
+/// Definitions and occurrences of every symbol inside a single document
+#[derive(Debug, Clone, Default)]
+pub struct DocumentSymbolIndex {
+    pub definitions: Vec<SymbolDefinition>,
+    pub occurrences: Vec<SymbolOccurrence>,
+    /// Whether names were resolved case-sensitively. Decided by the translation units including
+    /// the document
+    pub case_sensitive: bool,
+    /// Position of the first `#case` directive
+    pub case_directive: Option<Position>,
+}
+
+impl DocumentSymbolIndex {
+    /// Symbols inside `inactive_regions` are left out
+    pub fn from_tree(
+        tree: &Tree,
+        source: &[u8],
+        case_sensitive: bool,
+        inactive_regions: &[InactiveRegion],
+    ) -> Self {
+        let is_active = |byte: usize| !inactive_regions.iter().any(|r| r.bytes.contains(&byte));
+        let definitions = SymbolDefinition::from_tree(tree, source)
+            .into_iter()
+            .filter(|def| is_active(def.name_bytes.start))
+            .collect::<Vec<_>>();
+        let mut occurrences = vec![];
+        {
+            let declarations = definitions
+                .iter()
+                .map(|def| (def.name_bytes.start, def))
+                .collect::<HashMap<_, _>>();
+            let mut locals = definitions.iter().filter(|def| !def.is_global()).fold(
+                HashMap::<_, Vec<_>>::new(),
+                |mut map, def| {
+                    let key = get_symbol_key(&def.name, case_sensitive);
+                    map.entry(key).or_default().push(def);
+                    map
+                },
+            );
+            locals
+                .values_mut()
+                .for_each(|defs| defs.sort_by_key(|def| def.scope.as_ref().map(|s| s.len())));
+
+            collect_occurrences(
+                tree.root_node(),
+                source,
+                &declarations,
+                &locals,
+                case_sensitive,
+                &mut occurrences,
+            );
+            occurrences.retain(|o| is_active(o.bytes.start));
+        }
+
+        Self {
+            definitions,
+            occurrences,
+            case_sensitive,
+            case_directive: find_case_directive(tree.root_node(), source),
+        }
+    }
+
+    pub fn get_occurrence_at(&self, byte: usize) -> Option<&SymbolOccurrence> {
+        self.occurrences
+            .iter()
+            .find(|o| o.bytes.start <= byte && byte <= o.bytes.end)
+    }
+}
+
+fn collect_occurrences(
+    node: Node,
+    source: &[u8],
+    declarations: &HashMap<usize, &SymbolDefinition>,
+    locals: &HashMap<String, Vec<&SymbolDefinition>>,
+    case_sensitive: bool,
+    out: &mut Vec<SymbolOccurrence>,
+) {
+    fn is_initialized(identifier: Node) -> bool {
+        let mut curr = identifier;
+        while let Some(parent) = curr.parent() {
+            match parent.kind() {
+                "init_declarator" => return true,
+                "pointer_declarator" | "array_declarator" | "parenthesized_declarator" => {
+                    curr = parent
+                }
+                _ => return false,
+            }
+        }
+        false
+    }
+    fn is_written(identifier: Node) -> bool {
+        let mut curr = identifier;
+        while let Some(parent) = curr.parent() {
+            match parent.kind() {
+                "parenthesized_expression" => curr = parent,
+                "assignment_expression" => {
+                    return parent.child_by_field_name("left") == Some(curr);
+                }
+                "update_expression" => return true,
+                _ => return false,
+            }
+        }
+        false
+    }
+    fn get_role(
+        identifier: Node,
+        declarations: &HashMap<usize, &SymbolDefinition>,
+    ) -> SymbolOccurrenceRole {
+        if declarations.contains_key(&identifier.start_byte()) {
+            if is_initialized(identifier) {
+                SymbolOccurrenceRole::InitializedDeclaration
+            } else {
+                SymbolOccurrenceRole::Declaration
+            }
+        } else if is_written(identifier) {
+            SymbolOccurrenceRole::Write
+        } else {
+            SymbolOccurrenceRole::Read
+        }
+    }
+
+    fn get_argument_count(identifier: Node) -> Option<usize> {
+        let call = identifier
+            .parent()
+            .filter(|p| p.kind() == "call_expression")
+            .filter(|p| p.child_by_field_name("function") == Some(identifier))?;
+        let arguments = call.child_by_field_name("arguments")?;
+        let mut cursor = arguments.walk();
+        let out = arguments
+            .named_children(&mut cursor)
+            .filter(|a| a.kind() != "comment")
+            .count();
+        Some(out)
+    }
+
+    // Macro parameters are only visible inside the macro body, which is not parsed any further
+    if node.kind() == "preproc_params" {
+        return;
+    }
+
+    if matches!(node.kind(), "identifier" | "type_identifier") {
+        let name = match node.utf8_text(source) {
+            Ok(name) => name,
+            Err(_) => return,
+        };
+        let byte = node.start_byte();
+        let scope = match declarations.get(&byte) {
+            Some(def) => def.scope.clone(),
+            None => locals
+                .get(&get_symbol_key(name, case_sensitive))
+                .and_then(|defs| defs.iter().find(|def| def.is_visible_at(byte)))
+                .and_then(|def| def.scope.clone()),
+        };
+
+        out.push(SymbolOccurrence {
+            name: name.to_owned(),
+            role: get_role(node, declarations),
+            range: utils::get_range(&node),
+            bytes: node.byte_range(),
+            scope,
+            argument_count: get_argument_count(node),
+        });
+        return;
+    }
+
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        collect_occurrences(child, source, declarations, locals, case_sensitive, out);
+    }
+}
+
+fn find_case_directive(node: Node, source: &[u8]) -> Option<Position> {
+    // Also matches `#case` at the end of the file, which is not terminated by a newline
+    if node.kind() == "preproc_directive" {
+        let is_case = node
+            .utf8_text(source)
+            .map(|d| d.trim_start_matches('#').trim())
+            .is_ok_and(|d| d.eq_ignore_ascii_case("case"));
+        return is_case.then(|| utils::get_position(node.start_position()));
+    }
+
+    let mut cursor = node.walk();
+    let out = node
+        .named_children(&mut cursor)
+        .find_map(|child| find_case_directive(child, source));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::parse;
+
+    fn create_index(raw: &str) -> DocumentSymbolIndex {
+        DocumentSymbolIndex::from_tree(&parse(raw), raw.as_bytes(), false, &[])
+    }
+
+    #[test]
+    fn test_occurrence_roles() {
+        let index = create_index("int g;\n\nvoid main() {\n\tint c = g;\n\tc = 1;\n\tg++;\n}");
+        let roles = index
+            .occurrences
+            .iter()
+            .filter(|o| o.name == "c" || o.name == "g")
+            .map(|o| (o.name.as_str(), o.role))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            roles,
+            vec![
+                ("g", SymbolOccurrenceRole::Declaration),
+                ("c", SymbolOccurrenceRole::InitializedDeclaration),
+                ("g", SymbolOccurrenceRole::Read),
+                ("c", SymbolOccurrenceRole::Write),
+                ("g", SymbolOccurrenceRole::Write),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_local_symbols_shadow_globals() {
+        let index =
+            create_index("int a;\n\nint f(int a) {\n\treturn a;\n}\n\nint g() {\n\treturn a;\n}");
+        let scopes = index
+            .occurrences
+            .iter()
+            .filter(|o| o.name == "a")
+            .map(|o| o.scope.is_some())
+            .collect::<Vec<_>>();
+
+        assert_eq!(scopes, vec![false, true, true, false]);
+    }
+
+    #[test]
+    fn test_locals_ignore_case() {
+        let raw = "int f(int Count) {\n\treturn COUNT;\n}";
+        let tree = parse(raw);
+
+        let index = DocumentSymbolIndex::from_tree(&tree, raw.as_bytes(), false, &[]);
+        assert!(index
+            .occurrences
+            .iter()
+            .all(|o| o.name == "f" || o.scope.is_some()));
+        assert_eq!(index.case_directive, None);
+
+        let index = DocumentSymbolIndex::from_tree(&tree, raw.as_bytes(), true, &[]);
+        let count = index
+            .occurrences
+            .iter()
+            .find(|o| o.name == "COUNT")
+            .unwrap();
+        assert_eq!(count.scope, None);
+    }
+}
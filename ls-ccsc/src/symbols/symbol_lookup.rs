@@ -0,0 +1,529 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::Location;
+use tree_sitter::{Node, Point, Tree};
+
+use crate::docs::text_document_type::TextDocumentTypeTrait;
+use crate::docs::{TextDocument, TextDocumentType};
+use crate::symbols::{is_same_name, SymbolDefinition, SymbolDefinitionKind, SymbolOccurrence};
+use crate::utils;
+
+type Docs = HashMap<PathBuf, TextDocumentType>;
+
+/// Returns the identifier at `point`. Also matches if `point` is directly behind the identifier
+pub fn get_identifier_at(tree: &Tree, point: Point) -> Option<Node<'_>> {
+    fn is_identifier(node: &Node) -> bool {
+        matches!(
+            node.kind(),
+            "identifier" | "type_identifier" | "field_identifier"
+        )
+    }
+
+    let root = tree.root_node();
+    let node = root.named_descendant_for_point_range(point, point)?;
+    if is_identifier(&node) {
+        return Some(node);
+    }
+
+    if point.column == 0 {
+        return None;
+    }
+    let before = Point::new(point.row, point.column - 1);
+    root.named_descendant_for_point_range(before, before)
+        .filter(is_identifier)
+}
+
+/// Returns `path` followed by every document it (transitively) includes
+pub fn get_include_closure<'a>(docs: &'a Docs, path: &'a Path) -> Vec<&'a Path> {
+    let mut visited = HashSet::new();
+    let mut pending = VecDeque::from(vec![path]);
+    let mut out = vec![];
+
+    while let Some(curr) = pending.pop_front() {
+        if !visited.insert(curr) {
+            continue;
+        }
+        out.push(curr);
+
+        if let Some(TextDocumentType::Source(doc)) = docs.get(curr) {
+            pending.extend(doc.get_included_files().iter().map(|p| p.as_path()));
+        }
+    }
+
+    out
+}
+
+/// Returns the `.c` files which no other document includes, i.e. the files the compiler is run on
+pub fn get_translation_units(docs: &Docs) -> Vec<&Path> {
+    let included = docs
+        .values()
+        .filter_map(|doc_type| match doc_type {
+            TextDocumentType::Source(doc) => Some(doc.get_included_files()),
+            TextDocumentType::Ignored => None,
+        })
+        .flatten()
+        .map(PathBuf::as_path)
+        .collect::<HashSet<_>>();
+    let mut out = docs
+        .iter()
+        .filter(|(_, doc_type)| matches!(doc_type, TextDocumentType::Source(_)))
+        .map(|(path, _)| path.as_path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|e| e.eq_ignore_ascii_case("c"))
+        })
+        .filter(|path| !included.contains(path))
+        .collect::<Vec<_>>();
+    out.sort();
+    out
+}
+
+/// Returns the documents of the translation unit `main` which the compiler reads after a `#case`
+/// directive, i.e. which are case-sensitive. Documents are read in the order of the `#include`s.
+/// A document counts as read after `#case`, if the directive comes before its first identifier
+fn find_case_sensitive_docs<'a>(docs: &'a Docs, main: &'a Path) -> Vec<&'a Path> {
+    fn visit<'a>(
+        docs: &'a Docs,
+        path: &'a Path,
+        has_case: &mut bool,
+        visited: &mut HashSet<&'a Path>,
+        out: &mut Vec<&'a Path>,
+    ) {
+        let doc = match get_source_doc(docs, path) {
+            Some(doc) if visited.insert(path) => doc,
+            _ => return,
+        };
+        let index = doc.get_symbol_index();
+        let first_identifier = index.occurrences.iter().map(|o| o.range.start).min();
+        // `None` stands for the `#case` directive
+        let mut directives = doc
+            .get_includes()
+            .iter()
+            .filter_map(|i| Some((i.range.start, Some(i.resolved_path.as_deref()?))))
+            .chain(index.case_directive.map(|position| (position, None)))
+            .collect::<Vec<_>>();
+        directives.sort_by_key(|(position, _)| *position);
+
+        let mut is_case_sensitive = None;
+        for (position, included) in directives {
+            if is_case_sensitive.is_none() && first_identifier.is_some_and(|f| f < position) {
+                is_case_sensitive = Some(*has_case);
+            }
+            match included {
+                Some(included) => visit(docs, included, has_case, visited, out),
+                None => *has_case = true,
+            }
+        }
+        if is_case_sensitive.unwrap_or(*has_case) {
+            out.push(path);
+        }
+    }
+
+    let mut out = vec![];
+    visit(docs, main, &mut false, &mut HashSet::new(), &mut out);
+    out
+}
+
+/// Switches the documents of the translation units to case-sensitive name resolution, once they
+/// are read after a `#case` directive, and back, once it is removed
+pub fn update_case_sensitivity(docs: &mut Docs) {
+    let case_sensitive = get_translation_units(docs)
+        .into_iter()
+        .flat_map(|main| find_case_sensitive_docs(docs, main))
+        .map(Path::to_owned)
+        .collect::<HashSet<_>>();
+
+    for (path, doc_type) in docs.iter_mut() {
+        if let TextDocumentType::Source(doc) = doc_type {
+            doc.set_case_sensitive(case_sensitive.contains(path));
+        }
+    }
+}
+
+pub fn get_source_doc<'a>(docs: &'a Docs, path: &Path) -> Option<&'a TextDocument> {
+    match docs.get(path) {
+        Some(TextDocumentType::Source(doc)) => Some(doc),
+        _ => None,
+    }
+}
+
+fn get_global_definitions<'a>(
+    docs: &'a Docs,
+    paths: impl Iterator<Item = &'a Path>,
+    name: &str,
+    case_sensitive: bool,
+) -> Vec<(&'a Path, &'a SymbolDefinition)> {
+    paths
+        .filter_map(|p| get_source_doc(docs, p).map(|doc| (p, doc)))
+        .flat_map(|(p, doc)| {
+            doc.get_symbol_index()
+                .definitions
+                .iter()
+                .map(move |def| (p, def))
+        })
+        .filter(|(_, def)| def.is_global() && is_same_name(&def.name, name, case_sensitive))
+        .collect()
+}
+
+/// Returns the arity of the overload which accepts `argument_count` arguments
+fn resolve_overload(
+    definitions: &[(&Path, &SymbolDefinition)],
+    argument_count: usize,
+) -> Option<RangeInclusive<usize>> {
+    definitions
+        .iter()
+        .filter_map(|(_, def)| def.arity.as_ref())
+        .find(|arity| arity.contains(&argument_count))
+        .cloned()
+}
+
+/// Resolves the identifier at `point` to its definitions. Local symbols are preferred over
+/// global ones. Global symbols are first looked up in the included files, then in the entire
+/// project. Calls of overloaded functions resolve to the overloads accepting as many arguments
+pub fn find_definitions(docs: &Docs, path: &Path, point: Point) -> Result<Vec<Location>> {
+    fn prefer_definitions_over_prototypes<'a>(
+        definitions: Vec<(&'a Path, &'a SymbolDefinition)>,
+    ) -> Vec<(&'a Path, &'a SymbolDefinition)> {
+        let has_definition = definitions
+            .iter()
+            .any(|(_, def)| def.kind != SymbolDefinitionKind::FunctionPrototype);
+
+        definitions
+            .into_iter()
+            .filter(|(_, def)| {
+                !has_definition || def.kind != SymbolDefinitionKind::FunctionPrototype
+            })
+            .collect()
+    }
+    fn filter_overloads<'a>(
+        definitions: Vec<(&'a Path, &'a SymbolDefinition)>,
+        argument_count: Option<usize>,
+    ) -> Vec<(&'a Path, &'a SymbolDefinition)> {
+        let arity = match argument_count.and_then(|n| resolve_overload(&definitions, n)) {
+            Some(arity) => arity,
+            // Calls with a wrong number of arguments still resolve to every candidate
+            None => return definitions,
+        };
+
+        definitions
+            .into_iter()
+            .filter(|(_, def)| def.arity.as_ref().is_none_or(|a| *a == arity))
+            .collect()
+    }
+
+    let doc = match get_source_doc(docs, path) {
+        Some(doc) => doc,
+        None => return Ok(vec![]),
+    };
+    let tree = doc.get_syntax_tree()?;
+    let source = doc.get_source().get_raw().as_bytes();
+    let identifier = match get_identifier_at(tree, point) {
+        Some(identifier) => identifier,
+        None => return Ok(vec![]),
+    };
+    let name = identifier.utf8_text(source).unwrap_or_default();
+    let byte = identifier.start_byte();
+    let case_sensitive = doc.get_symbol_index().case_sensitive;
+
+    let local = doc
+        .get_symbol_index()
+        .definitions
+        .iter()
+        .filter(|def| !def.is_global() && def.is_visible_at(byte))
+        .filter(|def| is_same_name(&def.name, name, case_sensitive))
+        .min_by_key(|def| def.scope.as_ref().map(|s| s.len()));
+    if let Some(local) = local {
+        return Ok(vec![utils::get_location(path, local.selection_range)?]);
+    }
+
+    let mut definitions = get_global_definitions(
+        docs,
+        get_include_closure(docs, path).into_iter(),
+        name,
+        case_sensitive,
+    );
+    if definitions.is_empty() {
+        let paths = docs.keys().map(|p| p.as_path());
+        definitions = get_global_definitions(docs, paths, name, case_sensitive);
+    }
+    let argument_count = doc
+        .get_symbol_index()
+        .get_occurrence_at(byte)
+        .and_then(|o| o.argument_count);
+
+    prefer_definitions_over_prototypes(filter_overloads(definitions, argument_count))
+        .into_iter()
+        .map(|(p, def)| utils::get_location(p, def.selection_range))
+        .collect()
+}
+
+/// Returns every occurrence of the symbol at `point`. Local symbols are only searched for
+/// inside their scope, global symbols inside every document of the project. Overloaded functions
+/// only match the calls and declarations of the same overload
+pub fn find_references<'a>(
+    docs: &'a Docs,
+    path: &Path,
+    point: Point,
+) -> Result<Vec<(&'a Path, &'a SymbolOccurrence)>> {
+    fn get_declared_arity(
+        definitions: &[(&Path, &SymbolDefinition)],
+        path: &Path,
+        occurrence: &SymbolOccurrence,
+    ) -> Option<RangeInclusive<usize>> {
+        definitions
+            .iter()
+            .find(|(p, def)| *p == path && def.name_bytes == occurrence.bytes)
+            .and_then(|(_, def)| def.arity.clone())
+    }
+
+    let doc = match get_source_doc(docs, path) {
+        Some(doc) => doc,
+        None => return Ok(vec![]),
+    };
+    let byte = doc.get_source().get_offset_for_point(&point)?;
+    let target = match doc.get_symbol_index().get_occurrence_at(byte) {
+        Some(target) => target,
+        None => return Ok(vec![]),
+    };
+    let case_sensitive = doc.get_symbol_index().case_sensitive;
+
+    let mut out = docs
+        .iter()
+        .filter(|(p, _)| target.scope.is_none() || p.as_path() == path)
+        .filter_map(|(p, doc_type)| match doc_type {
+            TextDocumentType::Source(doc) => Some((p.as_path(), doc)),
+            _ => None,
+        })
+        .flat_map(|(p, doc)| {
+            doc.get_symbol_index()
+                .occurrences
+                .iter()
+                .map(move |occurrence| (p, occurrence))
+        })
+        .filter(|(_, occurrence)| occurrence.refers_to_same_symbol(target, case_sensitive))
+        .collect::<Vec<_>>();
+
+    if target.scope.is_some() {
+        return Ok(out);
+    }
+    let paths = docs.keys().map(|p| p.as_path());
+    let definitions = get_global_definitions(docs, paths, &target.name, case_sensitive);
+    let arities = definitions
+        .iter()
+        .filter_map(|(_, def)| def.arity.clone())
+        .collect::<HashSet<_>>();
+    if arities.len() < 2 {
+        return Ok(out);
+    }
+
+    let get_arity = |p: &Path, occurrence: &SymbolOccurrence| match occurrence.argument_count {
+        Some(n) => resolve_overload(&definitions, n),
+        None => get_declared_arity(&definitions, p, occurrence),
+    };
+    if let Some(arity) = get_arity(path, target) {
+        out.retain(|(p, occurrence)| get_arity(p, occurrence).is_none_or(|a| a == arity));
+    }
+
+    Ok(out)
+}
+
+/// Returns every occurrence of the symbol at `point` inside the document at `path`
+pub fn find_highlights<'a>(
+    docs: &'a Docs,
+    path: &Path,
+    point: Point,
+) -> Result<Vec<&'a SymbolOccurrence>> {
+    let out = find_references(docs, path, point)?
+        .into_iter()
+        .filter(|(p, _)| *p == path)
+        .map(|(_, occurrence)| occurrence)
+        .collect();
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::symbols::SymbolOccurrenceRole;
+    use crate::test_utils::create_docs;
+
+    #[test]
+    fn test_definition_in_included_file() {
+        let docs = create_docs(&[
+            (
+                "/project/main.c",
+                "#include \"sth/add.c\"\n\nvoid main() {\n\tint c = add(4, 4);\n}",
+            ),
+            (
+                "/project/sth/add.c",
+                "#include \"sth/add.h\"\n\nint add(int a, int b) {\n\treturn a + b;\n}",
+            ),
+            ("/project/sth/add.h", "int add(int a, int b);"),
+        ]);
+
+        let locations =
+            find_definitions(&docs, Path::new("/project/main.c"), Point::new(3, 10)).unwrap();
+
+        assert_eq!(locations.len(), 1);
+        assert_eq!(
+            locations[0].uri.to_file_path().unwrap(),
+            PathBuf::from("/project/sth/add.c")
+        );
+        assert_eq!(locations[0].range.start.line, 2);
+    }
+
+    #[test]
+    fn test_definition_prefers_local_symbols() {
+        let docs = create_docs(&[(
+            "/project/main.c",
+            "#define LED PIN_C0\nint a;\n\nvoid f(int a) {\n\toutput_low(LED);\n\ta = 1;\n}",
+        )]);
+        let path = Path::new("/project/main.c");
+
+        let parameter = find_definitions(&docs, path, Point::new(5, 1)).unwrap();
+        assert_eq!(parameter.len(), 1);
+        assert_eq!(
+            parameter[0].range.start,
+            tower_lsp::lsp_types::Position::new(3, 11)
+        );
+
+        let led = find_definitions(&docs, path, Point::new(4, 13)).unwrap();
+        assert_eq!(led.len(), 1);
+        assert_eq!(
+            led[0].range.start,
+            tower_lsp::lsp_types::Position::new(0, 8)
+        );
+    }
+
+    #[test]
+    fn test_references_across_files() {
+        let docs = create_docs(&[
+            (
+                "/project/main.c",
+                "#include \"add.c\"\n\nvoid main() {\n\tint a = add(4, 4);\n\ta = add(a, 1);\n}",
+            ),
+            (
+                "/project/add.c",
+                "int add(int a, int b) {\n\treturn a + b;\n}",
+            ),
+        ]);
+
+        let add = find_references(&docs, Path::new("/project/add.c"), Point::new(0, 5)).unwrap();
+        assert_eq!(add.len(), 3);
+
+        let a = find_highlights(&docs, Path::new("/project/main.c"), Point::new(4, 1)).unwrap();
+        let roles = a.iter().map(|o| o.role).collect::<Vec<_>>();
+        assert_eq!(
+            roles,
+            vec![
+                SymbolOccurrenceRole::InitializedDeclaration,
+                SymbolOccurrenceRole::Write,
+                SymbolOccurrenceRole::Read,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_overloads_resolve_by_argument_count() {
+        let docs = create_docs(&[(
+            "/project/main.c",
+            "int scale(int a) {\n\treturn a;\n}\n\nint scale(int a, int b) {\n\treturn a * b;\n}\n\nvoid main() {\n\tscale(1);\n\tscale(1, 2);\n}",
+        )]);
+        let path = Path::new("/project/main.c");
+
+        let one = find_definitions(&docs, path, Point::new(9, 1)).unwrap();
+        assert_eq!(one.len(), 1);
+        assert_eq!(one[0].range.start.line, 0);
+        let two = find_definitions(&docs, path, Point::new(10, 1)).unwrap();
+        assert_eq!(two.len(), 1);
+        assert_eq!(two[0].range.start.line, 4);
+
+        let lines = find_references(&docs, path, Point::new(4, 5))
+            .unwrap()
+            .iter()
+            .map(|(_, o)| o.range.start.line)
+            .collect::<Vec<_>>();
+        assert_eq!(lines, vec![4, 10]);
+    }
+
+    #[test]
+    fn test_overloads_with_reference_and_default_parameters() {
+        let docs = create_docs(&[(
+            "/project/main.c",
+            "void scale(int8 &a) {\n\ta *= 2;\n}\n\n\
+             void scale(int8 &a, int8 b, int8 c = 1) {\n\ta *= b * c;\n}\n\n\
+             void main() {\n\tint8 x;\n\tscale(x);\n\tscale(x, 2);\n\tscale(x, 2, 3);\n}",
+        )]);
+        let path = Path::new("/project/main.c");
+
+        let lines = [10, 11, 12]
+            .iter()
+            .map(|row| {
+                find_definitions(&docs, path, Point::new(*row, 1))
+                    .unwrap()
+                    .iter()
+                    .map(|l| l.range.start.line)
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(lines, vec![vec![0], vec![4], vec![4]]);
+
+        let parameter = find_definitions(&docs, path, Point::new(1, 1)).unwrap();
+        assert_eq!(parameter.len(), 1);
+        assert_eq!(
+            parameter[0].range.start,
+            tower_lsp::lsp_types::Position::new(0, 17)
+        );
+    }
+
+    #[test]
+    fn test_names_ignore_case_unless_case_directive() {
+        let raw = "int add(int a, int b) {\n\treturn A + b;\n}\n\nvoid main() {\n\tADD(1, 2);\n}";
+        let path = Path::new("/project/main.c");
+
+        let mut docs = create_docs(&[("/project/main.c", raw)]);
+        update_case_sensitivity(&mut docs);
+        let call = find_definitions(&docs, path, Point::new(5, 1)).unwrap();
+        assert_eq!(call.len(), 1);
+        assert_eq!(call[0].range.start.line, 0);
+        let parameter = find_definitions(&docs, path, Point::new(1, 8)).unwrap();
+        assert_eq!(
+            parameter[0].range.start,
+            tower_lsp::lsp_types::Position::new(0, 12)
+        );
+
+        // Neither a header outside of the translation unit nor `#case` behind the identifiers
+        // changes anything
+        let late_case = format!("{}\n#case\n", raw);
+        for files in [
+            [("/project/main.c", raw), ("/project/main.h", "#case")],
+            [("/project/main.c", &late_case), ("/project/main.h", "")],
+        ] {
+            let mut docs = create_docs(&files);
+            update_case_sensitivity(&mut docs);
+            assert_eq!(
+                find_definitions(&docs, path, Point::new(5, 1))
+                    .unwrap()
+                    .len(),
+                1
+            );
+        }
+
+        let included_case = format!("#include \"main.h\"\n{}", raw);
+        let mut docs = create_docs(&[
+            ("/project/main.c", &included_case),
+            ("/project/main.h", "#case"),
+        ]);
+        update_case_sensitivity(&mut docs);
+        assert!(find_definitions(&docs, path, Point::new(6, 1))
+            .unwrap()
+            .is_empty());
+        assert!(find_definitions(&docs, path, Point::new(2, 8))
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,496 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::{Range, TextEdit, WorkspaceEdit};
+use tree_sitter::{Node, Point, Query, QueryCursor};
+
+use crate::builtins::BuiltinFunction;
+use crate::completion::{CCS_PRIMITIVE_TYPES, C_KEYWORDS};
+use crate::docs::text_document_type::TextDocumentTypeTrait;
+use crate::docs::{IncludePaths, TextDocument, TextDocumentType};
+use crate::symbols::{self, SymbolDefinition, SymbolOccurrence};
+use crate::utils;
+
+type Docs = HashMap<PathBuf, TextDocumentType>;
+type References<'a> = Vec<(&'a Path, &'a SymbolOccurrence)>;
+
+lazy_static! {
+    static ref PREPROC_ARG_QUERY: Query =
+        Query::new(tree_sitter_ccsc::language(), "(preproc_arg) @arg").unwrap();
+    static ref IDENTIFIER_MATCHER: Regex = Regex::new(r"^[A-Za-z_][A-Za-z0-9_]*$").unwrap();
+}
+
+/// Returns the range of the symbol at `point`, if it can be renamed
+pub fn prepare_rename(
+    docs: &Docs,
+    include_paths: &IncludePaths,
+    path: &Path,
+    point: Point,
+) -> Result<Option<Range>> {
+    let out = get_rename_target(docs, include_paths, path, point)?.map(|(target, _)| target.range);
+    Ok(out)
+}
+
+/// Renames the symbol at `point` and every reference to it, including references inside the
+/// bodies of `#define` macros
+pub fn rename(
+    docs: &Docs,
+    include_paths: &IncludePaths,
+    path: &Path,
+    point: Point,
+    new_name: &str,
+) -> Result<Option<WorkspaceEdit>> {
+    let (target, references) = match get_rename_target(docs, include_paths, path, point)? {
+        Some(target) => target,
+        None => return Ok(None),
+    };
+    validate_new_name(docs, path, target, &references, new_name)?;
+
+    let mut edits = HashMap::<&Path, Vec<Range>>::new();
+    for (p, occurrence) in references.iter() {
+        edits.entry(p).or_default().push(occurrence.range);
+    }
+    if target.scope.is_none() {
+        for (p, doc) in docs
+            .iter()
+            .filter(|(p, _)| include_paths.is_project_file(p))
+            .filter_map(|(p, doc_type)| match doc_type {
+                TextDocumentType::Source(doc) => Some((p.as_path(), doc)),
+                TextDocumentType::Ignored => None,
+            })
+        {
+            let ranges = find_in_preproc_args(doc, &target.name)?;
+            edits.entry(p).or_default().extend(ranges);
+        }
+    }
+
+    let mut changes = HashMap::new();
+    for (p, mut ranges) in edits.into_iter().filter(|(_, ranges)| !ranges.is_empty()) {
+        ranges.sort_by_key(|r| (r.start.line, r.start.character));
+        ranges.dedup();
+        let text_edits = ranges
+            .into_iter()
+            .map(|range| TextEdit::new(range, new_name.to_owned()))
+            .collect();
+        changes.insert(utils::get_uri(p)?, text_edits);
+    }
+
+    Ok(Some(WorkspaceEdit::new(changes)))
+}
+
+/// Returns the occurrence at `point` and all of its references. Fails for symbols which are not
+/// defined inside the project, like built-in functions and symbols of the device header, and for
+/// symbols which are also declared outside of it
+fn get_rename_target<'a>(
+    docs: &'a Docs,
+    include_paths: &IncludePaths,
+    path: &Path,
+    point: Point,
+) -> Result<Option<(&'a SymbolOccurrence, References<'a>)>> {
+    let doc = match symbols::get_source_doc(docs, path) {
+        Some(doc) => doc,
+        None => return Ok(None),
+    };
+    let byte = doc.get_source().get_offset_for_point(&point)?;
+    let target = match doc.get_symbol_index().get_occurrence_at(byte) {
+        Some(target) => target,
+        None => return Ok(None),
+    };
+
+    let references = symbols::find_references(docs, path, point)?;
+    if !references.iter().any(|(_, o)| o.is_declaration()) {
+        let case_sensitive = doc.get_symbol_index().case_sensitive;
+        let message = if BuiltinFunction::find(&target.name, case_sensitive).is_some() {
+            format!("'{}' is a CCS built-in function", target.name)
+        } else {
+            format!(
+                "'{}' is not defined in the project (e.g. it is part of the device header)",
+                target.name
+            )
+        };
+        return Err(utils::create_server_error(7, message));
+    }
+    let foreign = references
+        .iter()
+        .find(|(p, o)| o.is_declaration() && !include_paths.is_project_file(p));
+    if let Some((p, _)) = foreign {
+        let message = format!(
+            "'{}' is declared outside of the project in '{}'",
+            target.name,
+            p.display()
+        );
+        return Err(utils::create_server_error(7, message));
+    }
+
+    Ok(Some((target, references)))
+}
+
+fn validate_new_name(
+    docs: &Docs,
+    path: &Path,
+    target: &SymbolOccurrence,
+    references: &[(&Path, &SymbolOccurrence)],
+    new_name: &str,
+) -> Result<()> {
+    fn is_nested(
+        inner: &Option<std::ops::Range<usize>>,
+        outer: &Option<std::ops::Range<usize>>,
+    ) -> bool {
+        match (inner, outer) {
+            (Some(inner), Some(outer)) => {
+                inner != outer && outer.start <= inner.start && inner.end <= outer.end
+            }
+            (Some(_), None) => true,
+            (None, _) => false,
+        }
+    }
+    fn get_definitions<'a>(
+        docs: &'a Docs,
+        path: &'a Path,
+    ) -> impl Iterator<Item = (&'a Path, &'a TextDocument, &'a SymbolDefinition)> {
+        symbols::get_source_doc(docs, path)
+            .into_iter()
+            .flat_map(move |doc| {
+                doc.get_symbol_index()
+                    .definitions
+                    .iter()
+                    .map(move |def| (path, doc, def))
+            })
+    }
+    let create_error = |message: String| Err(utils::create_server_error(7, message));
+    let case_sensitive = symbols::get_source_doc(docs, path)
+        .is_some_and(|doc| doc.get_symbol_index().case_sensitive);
+    let is_new_name = |name: &str| symbols::is_same_name(name, new_name, case_sensitive);
+
+    if !IDENTIFIER_MATCHER.is_match(new_name) {
+        return create_error(format!("'{}' is not a valid identifier", new_name));
+    }
+    if C_KEYWORDS.contains(&new_name) || CCS_PRIMITIVE_TYPES.contains(&new_name) {
+        return create_error(format!("'{}' is a reserved keyword", new_name));
+    }
+    // Changing the case only cannot introduce conflicts
+    if is_new_name(&target.name) {
+        return Ok(());
+    }
+    if target.scope.is_none() && BuiltinFunction::find(new_name, case_sensitive).is_some() {
+        return create_error(format!("'{}' is a CCS built-in function", new_name));
+    }
+
+    let paths = match target.scope {
+        Some(_) => vec![path],
+        None => docs.keys().map(|p| p.as_path()).collect(),
+    };
+    let definitions = paths
+        .into_iter()
+        .flat_map(|p| get_definitions(docs, p))
+        .filter(|(_, _, def)| is_new_name(&def.name))
+        .collect::<Vec<_>>();
+
+    // Symbols of the same scope
+    if let Some((p, _, _)) = definitions
+        .iter()
+        .find(|(_, _, def)| def.scope == target.scope)
+    {
+        return create_error(format!(
+            "'{}' is already defined in '{}'",
+            new_name,
+            p.display()
+        ));
+    }
+
+    // Inner symbols which would shadow the renamed symbol
+    for (p, occurrence) in references {
+        let shadowing = definitions.iter().find(|(def_path, _, def)| {
+            def_path == p
+                && is_nested(&def.scope, &target.scope)
+                && def.is_visible_at(occurrence.bytes.start)
+        });
+        if let Some((_, _, def)) = shadowing {
+            return create_error(format!(
+                "'{}' would be shadowed by the definition in line {}",
+                new_name,
+                def.selection_range.start.line + 1
+            ));
+        }
+    }
+
+    // Outer symbols which would be shadowed by the renamed symbol
+    if let (Some(scope), Some(doc)) = (&target.scope, symbols::get_source_doc(docs, path)) {
+        let declaration_start = references
+            .iter()
+            .filter(|(_, o)| o.is_declaration())
+            .map(|(_, o)| o.bytes.start)
+            .min()
+            .unwrap_or(scope.start);
+        let captured = doc.get_symbol_index().occurrences.iter().find(|o| {
+            is_new_name(&o.name)
+                && is_nested(&target.scope, &o.scope)
+                && scope.contains(&o.bytes.start)
+                && declaration_start <= o.bytes.start
+        });
+        if let Some(captured) = captured {
+            return create_error(format!(
+                "'{}' in line {} would refer to the renamed symbol",
+                new_name,
+                captured.range.start.line + 1
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Finds `name` inside macro bodies and the arguments of directives like `#undef`, which
+/// tree-sitter does not parse any further
+fn find_in_preproc_args(doc: &TextDocument, name: &str) -> Result<Vec<Range>> {
+    fn get_macro_parameters<'a>(arg: Node, source: &'a [u8]) -> Vec<&'a str> {
+        let parameters = arg
+            .parent()
+            .filter(|p| p.kind() == "preproc_function_def")
+            .and_then(|p| p.child_by_field_name("parameters"));
+        let parameters = match parameters {
+            Some(parameters) => parameters,
+            None => return vec![],
+        };
+
+        let mut cursor = parameters.walk();
+        let out = parameters
+            .named_children(&mut cursor)
+            .filter_map(|p| p.utf8_text(source).ok())
+            .collect();
+        out
+    }
+    /// Marks the bytes of `text` which are neither part of a string or character literal nor of
+    /// a comment
+    fn get_code_bytes(text: &str) -> Vec<bool> {
+        enum State {
+            Code,
+            Literal(u8),
+            LineComment,
+            BlockComment,
+        }
+
+        let bytes = text.as_bytes();
+        let mut out = vec![false; bytes.len()];
+        let mut state = State::Code;
+        let mut idx = 0;
+        while idx < bytes.len() {
+            let next = bytes.get(idx + 1).copied();
+            match state {
+                State::Code => match (bytes[idx], next) {
+                    (b'/', Some(b'/')) => state = State::LineComment,
+                    (b'/', Some(b'*')) => {
+                        state = State::BlockComment;
+                        idx += 1;
+                    }
+                    (quote @ b'"', _) | (quote @ b'\'', _) => state = State::Literal(quote),
+                    _ => out[idx] = true,
+                },
+                State::Literal(quote) => match bytes[idx] {
+                    b'\\' => idx += 1,
+                    b if b == quote => state = State::Code,
+                    _ => {}
+                },
+                // Line continuations are part of the argument, so the comment ends with it
+                State::LineComment => {}
+                State::BlockComment => {
+                    if bytes[idx] == b'*' && next == Some(b'/') {
+                        state = State::Code;
+                        idx += 1;
+                    }
+                }
+            }
+            idx += 1;
+        }
+        out
+    }
+    let tree = doc.get_syntax_tree()?;
+    let source = doc.get_source().get_raw().as_bytes();
+    let case_sensitive = doc.get_symbol_index().case_sensitive;
+    let flags = if case_sensitive { "" } else { "(?i)" };
+    let matcher = Regex::new(&format!(r"{}\b{}\b", flags, regex::escape(name)))
+        .map_err(|e| utils::create_server_error(7, e.to_string()))?;
+
+    let mut query_cursor = QueryCursor::new();
+    let mut out = vec![];
+    for arg in query_cursor
+        .captures(&PREPROC_ARG_QUERY, tree.root_node(), source)
+        .map(|(m, idx)| m.captures[idx].node)
+    {
+        // A macro parameter hides the symbol inside the macro body
+        if get_macro_parameters(arg, source)
+            .iter()
+            .any(|p| symbols::is_same_name(p, name, case_sensitive))
+        {
+            continue;
+        }
+        let text = arg.utf8_text(source).unwrap_or_default();
+        let code_bytes = get_code_bytes(text);
+        for m in matcher.find_iter(text).filter(|m| code_bytes[m.start()]) {
+            let before = &text[..m.start()];
+            let start = utils::get_point_after(arg.start_position(), before);
+            let end = Point::new(start.row, start.column + name.len());
+            out.push(Range::new(
+                utils::get_position(start),
+                utils::get_position(end),
+            ));
+        }
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::docs;
+    use crate::test_utils::create_docs;
+
+    fn create_include_paths() -> IncludePaths {
+        IncludePaths::new(
+            PathBuf::from("/project"),
+            vec![],
+            vec![PathBuf::from("/picc/devices")],
+        )
+    }
+
+    fn get_edit_ranges(edit: WorkspaceEdit, path: &str) -> Vec<(u32, u32)> {
+        let uri = utils::get_uri(Path::new(path)).unwrap();
+        edit.changes.unwrap()[&uri]
+            .iter()
+            .map(|e| (e.range.start.line, e.range.start.character))
+            .collect()
+    }
+
+    #[test]
+    fn test_rename_macro() {
+        let docs = create_docs(&[(
+            "/project/main.c",
+            "#define LED PIN_C0\n#define BLINK() output_toggle(LED)\n#ifdef LED\n#endif\n\
+             #define ON(LED) output_high(LED)\n\nvoid main() {\n\toutput_low(LED);\n}",
+        )]);
+
+        let edit = rename(
+            &docs,
+            &create_include_paths(),
+            Path::new("/project/main.c"),
+            Point::new(7, 13),
+            "STATUS",
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(
+            get_edit_ranges(edit, "/project/main.c"),
+            vec![(0, 8), (1, 30), (2, 7), (7, 12)]
+        );
+    }
+
+    #[test]
+    fn test_rename_macro_skips_literals_and_comments() {
+        let docs = create_docs(&[(
+            "/project/main.c",
+            "#define LED PIN_C0\n#define MSG \"LED: http://x\" /* LED */ LED\n\
+             #define Q '\"' LED // LED\n\nvoid main() {\n\toutput_low(LED);\n}",
+        )]);
+
+        let edit = rename(
+            &docs,
+            &create_include_paths(),
+            Path::new("/project/main.c"),
+            Point::new(5, 13),
+            "STATUS",
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(
+            get_edit_ranges(edit, "/project/main.c"),
+            vec![(0, 8), (1, 38), (2, 14), (5, 12)]
+        );
+    }
+
+    #[test]
+    fn test_refuse_symbols_outside_of_project() {
+        let mut docs = create_docs(&[
+            (
+                "/project/main.c",
+                "#include <16F883.h>\n#include \"../shared/uart.h\"\n\nvoid main() {\n\
+                 \toutput_low(PIN_C0);\n\tputc(BAUD);\n}",
+            ),
+            ("/picc/devices/16F883.h", "#define PIN_C0 56\n"),
+            ("/shared/uart.h", "#define BAUD 9600\n#define SPEED BAUD\n"),
+        ]);
+        let include_paths = create_include_paths();
+        docs::update_included_files(&mut docs, &include_paths);
+        let path = Path::new("/project/main.c");
+        let get_message = |point: Point| {
+            prepare_rename(&docs, &include_paths, path, point)
+                .unwrap_err()
+                .message
+        };
+
+        assert_eq!(
+            get_message(Point::new(4, 3)),
+            "'output_low' is a CCS built-in function"
+        );
+        assert_eq!(
+            get_message(Point::new(4, 14)),
+            "'PIN_C0' is declared outside of the project in '/picc/devices/16F883.h'"
+        );
+        assert_eq!(
+            get_message(Point::new(5, 7)),
+            "'BAUD' is declared outside of the project in '/shared/uart.h'"
+        );
+        assert!(prepare_rename(&docs, &include_paths, path, Point::new(3, 6))
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn test_rename_skips_macros_outside_of_project() {
+        let docs = create_docs(&[
+            (
+                "/project/main.c",
+                "#define BAUD 9600\n\nvoid main() {\n\tputc(BAUD);\n}",
+            ),
+            ("/shared/uart.h", "#define SPEED BAUD\n"),
+        ]);
+
+        let edit = rename(
+            &docs,
+            &create_include_paths(),
+            Path::new("/project/main.c"),
+            Point::new(3, 7),
+            "RATE",
+        )
+        .unwrap()
+        .unwrap();
+
+        assert!(edit.changes.as_ref().unwrap().len() == 1);
+        assert_eq!(
+            get_edit_ranges(edit, "/project/main.c"),
+            vec![(0, 8), (3, 6)]
+        );
+    }
+
+    #[test]
+    fn test_refuse_collisions() {
+        let docs = create_docs(&[(
+            "/project/main.c",
+            "int a;\nint b;\n\nvoid f(int c) {\n\tint d = a;\n}",
+        )]);
+        let path = Path::new("/project/main.c");
+        let include_paths = create_include_paths();
+
+        assert!(rename(&docs, &include_paths, path, Point::new(0, 4), "b").is_err());
+        assert!(rename(&docs, &include_paths, path, Point::new(3, 11), "d").is_err());
+        assert!(rename(&docs, &include_paths, path, Point::new(0, 4), "c").is_err());
+        assert!(rename(&docs, &include_paths, path, Point::new(3, 11), "a").is_err());
+        assert!(rename(&docs, &include_paths, path, Point::new(3, 11), "b").is_ok());
+        assert!(rename(&docs, &include_paths, path, Point::new(0, 4), "while").is_err());
+    }
+}
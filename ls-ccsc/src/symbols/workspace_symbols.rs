@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::{SymbolInformation, SymbolKind};
+
+use crate::docs::text_document_type::TextDocumentTypeTrait;
+use crate::docs::TextDocumentType;
+use crate::symbols::{SymbolDefinition, SymbolDefinitionKind};
+use crate::utils;
+
+const MAX_WORKSPACE_SYMBOLS: usize = 256;
+
+/// Scores how well `candidate` matches `query`. Every character of `query` has to appear in
+/// `candidate` in the same order (case-insensitive). Consecutive characters and characters at
+/// the start of a word score higher. Returns `None` if `candidate` does not match
+pub fn fuzzy_match(query: &str, candidate: &str) -> Option<i64> {
+    let candidate = candidate.chars().collect::<Vec<_>>();
+    let mut score = 0;
+    let mut next = 0;
+    let mut previous_match: Option<usize> = None;
+
+    for q in query.chars().filter(|c| !c.is_whitespace()) {
+        let is_word_start = |i: usize| {
+            i == 0
+                || matches!(candidate[i - 1], '_' | '@' | '.')
+                || (candidate[i].is_ascii_uppercase() && candidate[i - 1].is_ascii_lowercase())
+        };
+        let is_consecutive = |i: usize| previous_match.map_or(i == 0, |p| p + 1 == i);
+        let matches = (next..candidate.len())
+            .filter(|&i| candidate[i].eq_ignore_ascii_case(&q))
+            .collect::<Vec<_>>();
+        // Prefer continuing the current match or starting at a word over the first occurrence
+        let idx = *matches
+            .iter()
+            .find(|&&i| is_consecutive(i) || is_word_start(i))
+            .or_else(|| matches.first())?;
+
+        score += 1;
+        if is_consecutive(idx) {
+            score += 8;
+        }
+        if is_word_start(idx) {
+            score += 3;
+        }
+        if candidate[idx] == q {
+            score += 1;
+        }
+        score -= (idx - next).min(3) as i64;
+
+        previous_match = Some(idx);
+        next = idx + 1;
+    }
+
+    Some(score)
+}
+
+/// Returns every global symbol of the project matching `query`, best matches first
+pub fn find_workspace_symbols(
+    docs: &HashMap<PathBuf, TextDocumentType>,
+    query: &str,
+) -> Result<Vec<SymbolInformation>> {
+    fn get_symbol_kind(kind: SymbolDefinitionKind) -> SymbolKind {
+        match kind {
+            SymbolDefinitionKind::Function | SymbolDefinitionKind::FunctionPrototype => {
+                SymbolKind::Function
+            }
+            SymbolDefinitionKind::Variable | SymbolDefinitionKind::Parameter => {
+                SymbolKind::Variable
+            }
+            SymbolDefinitionKind::Macro | SymbolDefinitionKind::FunctionMacro => {
+                SymbolKind::Constant
+            }
+            SymbolDefinitionKind::Type => SymbolKind::Struct,
+            SymbolDefinitionKind::EnumConstant => SymbolKind::EnumMember,
+        }
+    }
+    #[allow(deprecated)]
+    fn create_symbol_information(path: &Path, def: &SymbolDefinition) -> Result<SymbolInformation> {
+        Ok(SymbolInformation {
+            name: def.name.clone(),
+            kind: get_symbol_kind(def.kind),
+            tags: None,
+            deprecated: None,
+            location: utils::get_location(path, def.selection_range)?,
+            container_name: path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned()),
+        })
+    }
+
+    let mut matches = docs
+        .iter()
+        .filter_map(|(path, doc_type)| match doc_type {
+            TextDocumentType::Source(doc) => Some((path, doc)),
+            TextDocumentType::Ignored => None,
+        })
+        .flat_map(|(path, doc)| {
+            doc.get_symbol_index()
+                .definitions
+                .iter()
+                .filter(|def| def.is_global())
+                .map(move |def| (path, def))
+        })
+        .filter_map(|(path, def)| fuzzy_match(query, &def.name).map(|score| (score, path, def)))
+        .collect::<Vec<_>>();
+    matches.sort_by(|(score_a, path_a, def_a), (score_b, path_b, def_b)| {
+        score_b
+            .cmp(score_a)
+            .then_with(|| def_a.name.len().cmp(&def_b.name.len()))
+            .then_with(|| path_a.cmp(path_b))
+    });
+
+    matches
+        .into_iter()
+        .take(MAX_WORKSPACE_SYMBOLS)
+        .map(|(_, path, def)| create_symbol_information(path, def))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fuzzy_match_subsequence() {
+        assert!(fuzzy_match("tmr1", "timer1_isr").is_some());
+        assert!(fuzzy_match("ISR", "timer1_isr").is_some());
+        assert!(fuzzy_match("", "timer1_isr").is_some());
+        assert!(fuzzy_match("isrt", "timer1_isr").is_none());
+    }
+
+    #[test]
+    fn test_fuzzy_match_ranking() {
+        let prefix = fuzzy_match("add", "add").unwrap();
+        let word_start = fuzzy_match("add", "uart_add_byte").unwrap();
+        let scattered = fuzzy_match("add", "a_delay_d").unwrap();
+
+        assert!(prefix > word_start);
+        assert!(word_start > scattered);
+    }
+}
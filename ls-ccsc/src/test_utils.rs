@@ -0,0 +1,31 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use tree_sitter::{Parser, Tree};
+
+use crate::docs::text_document_type::TextDocumentTypeTrait;
+use crate::docs::{TextDocument, TextDocumentType};
+
+pub fn create_parser() -> Arc<Mutex<Parser>> {
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_ccsc::language()).unwrap();
+    Arc::new(Mutex::new(parser))
+}
+
+pub fn parse(raw: &str) -> Tree {
+    create_parser().lock().unwrap().parse(raw, None).unwrap()
+}
+
+/// Creates a source document for every `(path, raw)` pair, sharing one parser
+pub fn create_docs(files: &[(&str, &str)]) -> HashMap<PathBuf, TextDocumentType> {
+    let parser = create_parser();
+    files
+        .iter()
+        .map(|(path, raw)| {
+            let path = PathBuf::from(path);
+            let doc = TextDocument::new(path.clone(), raw.to_string(), parser.clone());
+            (path, TextDocumentType::Source(Box::new(doc)))
+        })
+        .collect()
+}
@@ -1,9 +1,10 @@
+use std::io::Read;
 use std::ops::Range;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use tower_lsp::jsonrpc::{Error, ErrorCode};
 use tower_lsp::jsonrpc::Result;
-use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, Position};
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, Location, Position};
 use tree_sitter::{Node, Point};
 
 use crate::{Url, utils};
@@ -17,9 +18,8 @@ pub fn create_server_error(code: i64, message: String) -> Error {
     }
 }
 
-pub fn find_path_to_mcp(p: &PathBuf) -> Result<PathBuf> {
+pub fn find_path_to_mcp(p: &Path) -> Result<PathBuf> {
     let out = p
-        .as_path()
         .read_dir()
         .map_err(|e| utils::create_server_error(4, e.to_string()))?
         .filter_map(|f| f.ok())
@@ -45,9 +45,8 @@ pub fn find_path_to_mcp(p: &PathBuf) -> Result<PathBuf> {
     ))
 }
 
-pub fn find_paths_to_errs(p: &PathBuf) -> Result<Vec<PathBuf>> {
+pub fn find_paths_to_errs(p: &Path) -> Result<Vec<PathBuf>> {
     let out = p
-        .as_path()
         .read_dir()
         .map_err(|e| utils::create_server_error(4, e.to_string()))?
         .filter_map(|f| f.ok())
@@ -68,7 +67,41 @@ pub fn get_path(uri: &Url) -> Result<PathBuf> {
     Ok(path)
 }
 
-pub fn is_source_file(path: &PathBuf) -> bool {
+pub fn get_uri(path: &Path) -> Result<Url> {
+    let uri = Url::from_file_path(path).map_err(|_| {
+        utils::create_server_error(1, format!("Failed to create URI for '{}'", path.display()))
+    })?;
+
+    Ok(uri)
+}
+
+pub fn get_location(path: &Path, range: tower_lsp::lsp_types::Range) -> Result<Location> {
+    Ok(Location::new(get_uri(path)?, range))
+}
+
+/// MPLAB stores paths with Windows separators (e.g. `sth\add.h`)
+pub fn normalize_path_separators(path: &str) -> PathBuf {
+    PathBuf::from(path.replace('\\', std::path::MAIN_SEPARATOR_STR))
+}
+
+pub fn read_string(path: &Path) -> Result<String> {
+    let mut file = std::fs::File::open(path).map_err(|e| {
+        utils::create_server_error(
+            6,
+            format!("Could not open file '{}' ('{}')", path.display(), e),
+        )
+    })?;
+    let mut contents = String::new();
+    file.read_to_string(&mut contents).map_err(|e| {
+        utils::create_server_error(
+            6,
+            format!("Could not read file '{}' ('{}')", path.display(), e),
+        )
+    })?;
+    Ok(contents)
+}
+
+pub fn is_source_file(path: &Path) -> bool {
     let extension = path.extension();
     if extension.is_none() {
         return false;
@@ -106,15 +139,28 @@ pub fn apply_change(target: String, diff: String, range: Range<usize>) -> Result
     }
 
     let out = String::from_utf8(out).map_err(|e| {
-        utils::create_server_error(
-            6,
-            format!("Could not convert bytes to string ('{}')", e.to_string()),
-        )
+        utils::create_server_error(6, format!("Could not convert bytes to string ('{}')", e))
     })?;
 
     Ok(out)
 }
 
+pub fn get_point(position: &Position) -> Point {
+    Point::new(position.line as usize, position.character as usize)
+}
+
+pub fn get_position(point: Point) -> Position {
+    Position::new(point.row as u32, point.column as u32)
+}
+
+/// Returns the point behind `text`, if `text` starts at `start`
+pub fn get_point_after(start: Point, text: &str) -> Point {
+    match text.rfind('\n') {
+        Some(idx) => Point::new(start.row + text.matches('\n').count(), text.len() - idx - 1),
+        None => Point::new(start.row, start.column + text.len()),
+    }
+}
+
 pub fn get_range(node: &Node) -> tower_lsp::lsp_types::Range {
     let tree_sitter::Range {
         start_point:
@@ -31,17 +31,79 @@ pub fn language() -> Language {
 /// The content of the [`node-types.json`][] file for this grammar.
 ///
 /// [`node-types.json`]: https://tree-sitter.github.io/tree-sitter/using-parsers#static-node-types
-pub const NODE_TYPES: &'static str = include_str!("../../src/node-types.json");
+pub const NODE_TYPES: &str = include_str!("../../src/node-types.json");
 
-// Uncomment these to include any queries that this grammar contains
+/// The syntax highlighting query for this language.
+pub const HIGHLIGHTS_QUERY: &str = include_str!("../../queries/highlights.scm");
 
-// pub const HIGHLIGHTS_QUERY: &'static str = include_str!("../../queries/highlights.scm");
-// pub const INJECTIONS_QUERY: &'static str = include_str!("../../queries/injections.scm");
-// pub const LOCALS_QUERY: &'static str = include_str!("../../queries/locals.scm");
-// pub const TAGS_QUERY: &'static str = include_str!("../../queries/tags.scm");
+/// The language injection query for this language.
+pub const INJECTIONS_QUERY: &str = include_str!("../../queries/injections.scm");
+
+/// The local-variable syntax highlighting query for this language.
+pub const LOCALS_QUERY: &str = include_str!("../../queries/locals.scm");
+
+/// The symbol tagging query for this language.
+pub const TAGS_QUERY: &str = include_str!("../../queries/tags.scm");
+
+/// The code folding query for this language.
+pub const FOLDS_QUERY: &str = include_str!("../../queries/folds.scm");
 
 #[cfg(test)]
 mod tests {
+    use tree_sitter::{Parser, Query, QueryCursor};
+
+    const QUERIES: &[(&str, &str)] = &[
+        ("highlights", super::HIGHLIGHTS_QUERY),
+        ("injections", super::INJECTIONS_QUERY),
+        ("locals", super::LOCALS_QUERY),
+        ("tags", super::TAGS_QUERY),
+        ("folds", super::FOLDS_QUERY),
+    ];
+
+    /// Returns the source code of every example in `test/corpus`
+    fn get_corpus_examples() -> Vec<(String, String)> {
+        let corpus_dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("test/corpus");
+        let mut out = vec![];
+
+        for entry in std::fs::read_dir(corpus_dir).unwrap() {
+            let content = std::fs::read_to_string(entry.unwrap().path()).unwrap();
+            let mut lines = content.lines();
+            while let Some(line) = lines.next() {
+                if !line.starts_with("===") {
+                    continue;
+                }
+                let name = lines.next().unwrap_or_default().trim().to_owned();
+                lines.next();
+                let code = lines
+                    .by_ref()
+                    .take_while(|l| !l.starts_with("---"))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                out.push((name, code));
+            }
+        }
+
+        out
+    }
+
+    fn get_captures(query: &Query, code: &str) -> Vec<(String, String)> {
+        let mut parser = Parser::new();
+        parser.set_language(super::language()).unwrap();
+        let tree = parser.parse(code, None).unwrap();
+        let mut cursor = QueryCursor::new();
+
+        cursor
+            .captures(query, tree.root_node(), code.as_bytes())
+            .map(|(m, idx)| m.captures[idx])
+            .map(|c| {
+                (
+                    query.capture_names()[c.index as usize].clone(),
+                    c.node.utf8_text(code.as_bytes()).unwrap().to_owned(),
+                )
+            })
+            .collect()
+    }
+
     #[test]
     fn test_can_load_grammar() {
         let mut parser = tree_sitter::Parser::new();
@@ -49,4 +111,94 @@ mod tests {
             .set_language(super::language())
             .expect("Error loading ccsc language");
     }
+
+    #[test]
+    fn test_queries_run_on_corpus() {
+        let examples = get_corpus_examples();
+        assert!(!examples.is_empty());
+
+        let mut all_captures = std::collections::HashMap::new();
+        for (query_name, source) in QUERIES {
+            let query = Query::new(super::language(), source)
+                .unwrap_or_else(|e| panic!("Invalid {} query: {:?}", query_name, e));
+            for (name, code) in examples.iter() {
+                let captures = get_captures(&query, code);
+                if *query_name == "highlights" {
+                    assert!(!captures.is_empty(), "Nothing highlighted in '{}'", name);
+                }
+                all_captures.insert((*query_name, name.as_str()), captures);
+            }
+        }
+
+        let has_capture = |query_name: &str, example: &str, capture: &str, text: &str| {
+            all_captures[&(query_name, example)]
+                .iter()
+                .any(|(c, t)| c == capture && t.trim_end() == text)
+        };
+        assert!(has_capture(
+            "locals",
+            "Reference parameters",
+            "local.definition",
+            "a"
+        ));
+        assert!(has_capture(
+            "locals",
+            "Default arguments",
+            "local.definition",
+            "y"
+        ));
+        assert!(has_capture("tags", "Interrupts", "name", "#INT_RDA"));
+        assert!(has_capture(
+            "tags",
+            "Interrupts",
+            "definition.interrupt",
+            "#int_timer1"
+        ));
+        assert!(has_capture(
+            "injections",
+            "Driver code",
+            "injection.content",
+            " PIN_D0"
+        ));
+        assert!(has_capture(
+            "folds",
+            "Inline assembly with ASIS and C comments",
+            "fold",
+            "#asm ASIS\n   movlw b'00001111' // low nibble\n   andwf PORTB, W\n#endasm"
+        ));
+    }
+
+    #[test]
+    fn test_ccs_captures() {
+        let code = "#fuses XT, NOWDT\n#use delay(clock=4Mhz)\n\n#int_timer1\nvoid isr() {\n}\n\n\
+                    void main() {\n    int16 ticks;\n    output_low(PIN_C0);\n}";
+
+        let highlights = get_captures(
+            &Query::new(super::language(), super::HIGHLIGHTS_QUERY).unwrap(),
+            code,
+        );
+        let get_first = |text: &str| {
+            highlights
+                .iter()
+                .find(|(_, t)| t == text)
+                .map(|(c, _)| c.as_str())
+        };
+        assert_eq!(get_first("#use"), Some("keyword"));
+        assert_eq!(get_first("XT"), Some("constant.builtin"));
+        assert_eq!(get_first("NOWDT"), Some("constant.builtin"));
+        assert_eq!(get_first("delay"), Some("namespace"));
+        assert_eq!(get_first("int16"), Some("type.builtin"));
+        assert_eq!(get_first("output_low"), Some("function.builtin"));
+        assert_eq!(get_first("PIN_C0"), Some("constant.builtin"));
+
+        let tags = get_captures(
+            &Query::new(super::language(), super::TAGS_QUERY).unwrap(),
+            code,
+        );
+        assert!(tags
+            .iter()
+            .any(|(c, t)| c == "definition.interrupt" && t.starts_with("#int_")));
+        assert!(tags.contains(&("name".to_owned(), "#int_timer1".to_owned())));
+        assert!(tags.contains(&("name".to_owned(), "main".to_owned())));
+    }
 }
@@ -35,6 +35,12 @@ module.exports = grammar({
     $.comment,
   ],
 
+  externals: $ => [
+    $.asm_operand,
+    $.asm_comment,
+    $._error_sentinel,
+  ],
+
   inline: $ => [
     $._statement,
     $._top_level_item,
@@ -74,6 +80,17 @@ module.exports = grammar({
       $.preproc_include,
       $.preproc_def,
       $.preproc_function_def,
+      $.preproc_fuses,
+      $.preproc_use,
+      $.preproc_interrupt,
+      $.preproc_device,
+      $.preproc_org,
+      $.preproc_byte,
+      $.preproc_bit,
+      $.preproc_locate,
+      $.preproc_reserve,
+      $.preproc_rom,
+      $.asm_block,
       $.preproc_call
     ),
 
@@ -115,6 +132,171 @@ module.exports = grammar({
       '\n'
     ),
 
+    // CCS C directives
+
+    preproc_fuses: $ => seq(
+      ccsDirective('fuses'),
+      field('fuses', optional($.fuse_list)),
+      '\n'
+    ),
+
+    // A trailing comma is accepted while the list is being typed
+    fuse_list: $ => seq($.fuse, repeat(seq(optional(','), $.fuse)), optional(',')),
+
+    fuse: $ => seq(
+      field('name', $.identifier),
+      optional(seq('=', field('value', $._directive_value)))
+    ),
+
+    preproc_use: $ => seq(
+      ccsDirective('use'),
+      field('library', $.identifier),
+      field('options', optional($.directive_option_list)),
+      '\n'
+    ),
+
+    directive_option_list: $ => seq('(', commaSep($.directive_option), ')'),
+
+    directive_option: $ => seq(
+      field('key', $.identifier),
+      optional(seq('=', field('value', $._directive_value)))
+    ),
+
+    preproc_interrupt: $ => seq(
+      field('directive', alias(
+        token(prec(1, /#[ \t]*[iI][nN][tT]_\w+/)),
+        $.preproc_directive
+      )),
+      repeat(field('option', $.identifier)),
+      '\n'
+    ),
+
+    preproc_device: $ => seq(
+      ccsDirective('device'),
+      repeat(choice(
+        field('chip', $.identifier),
+        field('option', $.device_option)
+      )),
+      '\n'
+    ),
+
+    device_option: $ => choice(
+      seq(field('key', $.identifier), '=', field('value', $._directive_value)),
+      seq(field('key', '*='), field('value', $.number_literal))
+    ),
+
+    preproc_org: $ => seq(
+      ccsDirective('org'),
+      choice(
+        'default',
+        seq(
+          field('start', $._directive_value),
+          optional(seq(',', field('end', $._directive_value))),
+          repeat(field('option', $.directive_option)),
+          optional(seq('{', '}'))
+        )
+      ),
+      '\n'
+    ),
+
+    preproc_byte: $ => seq(
+      choice(ccsDirective('byte'), ccsDirective('word')),
+      field('name', $.identifier),
+      '=',
+      field('address', $._directive_value),
+      '\n'
+    ),
+
+    preproc_bit: $ => seq(
+      ccsDirective('bit'),
+      field('name', $.identifier),
+      '=',
+      field('address', choice(
+        $.identifier,
+        alias($._register_address, $.number_literal),
+        alias($.directive_call, $.call_expression)
+      )),
+      optional(seq('.', field('bit', $.number_literal))),
+      '\n'
+    ),
+
+    // `0x0C.0` would otherwise be lexed as a single hexadecimal float
+    _register_address: $ => token(prec(1, /0[xX][0-9a-fA-F]+|0[bB][01]+|[0-9]+/)),
+
+    preproc_locate: $ => seq(
+      ccsDirective('locate'),
+      field('name', $.identifier),
+      '=',
+      field('address', $._directive_value),
+      '\n'
+    ),
+
+    preproc_reserve: $ => seq(
+      ccsDirective('reserve'),
+      commaSep1(field('range', choice($._directive_value, $.address_range))),
+      '\n'
+    ),
+
+    address_range: $ => seq(
+      field('start', $._directive_value),
+      ':',
+      field('end', $._directive_value)
+    ),
+
+    preproc_rom: $ => seq(
+      ccsDirective('rom'),
+      optional(field('type', choice($.primitive_type, alias($.identifier, $.type_identifier)))),
+      field('address', $._directive_value),
+      '=',
+      field('value', $.initializer_list),
+      '\n'
+    ),
+
+    // Inline assembly
+
+    asm_block: $ => seq(
+      ccsDirective('asm'),
+      optional(field('option', $.identifier)),
+      '\n',
+      repeat($._asm_line),
+      ccsDirective('endasm')
+    ),
+
+    _asm_line: $ => seq(
+      choice(
+        seq($.asm_label, optional($.asm_instruction), optional($.asm_comment)),
+        seq($.asm_instruction, optional($.asm_comment)),
+        $.asm_comment
+      ),
+      '\n'
+    ),
+
+    asm_label: $ => seq(field('name', $.identifier), ':'),
+
+    asm_instruction: $ => seq(
+      field('mnemonic', alias($.identifier, $.asm_mnemonic)),
+      commaSep(field('operand', $.asm_operand))
+    ),
+
+    _directive_value: $ => choice(
+      $.number_literal,
+      $.number_with_unit,
+      $.identifier,
+      $.string_literal,
+      $.char_literal,
+      alias($.directive_call, $.call_expression)
+    ),
+
+    // `clock=4Mhz`, `clock=20M`, `tick=1ms`
+    number_with_unit: $ => /\d+(\.\d+)?([kKmM]?[hH][zZ]|[kKmM]|[mun]?s)/,
+
+    directive_call: $ => seq(
+      field('function', $.identifier),
+      field('arguments', alias($.directive_argument_list, $.argument_list))
+    ),
+
+    directive_argument_list: $ => seq('(', commaSep($._directive_value), ')'),
+
     ...preprocIf('', $ => $._top_level_item),
     ...preprocIf('_in_field_declaration_list', $ => $._field_declaration_list_item),
 
@@ -468,7 +650,9 @@ module.exports = grammar({
       'const',
       'volatile',
       'restrict',
-      '_Atomic'
+      '_Atomic',
+      'rom',
+      '_readonly'
     ),
 
     _type_specifier: $ => choice(
@@ -508,7 +692,11 @@ module.exports = grammar({
       'charptr_t',
       ...[8, 16, 32, 64].map(n => `int${n}_t`),
       ...[8, 16, 32, 64].map(n => `uint${n}_t`),
-      ...[8, 16, 32, 64].map(n => `char${n}_t`)
+      ...[8, 16, 32, 64].map(n => `char${n}_t`),
+      // CCS C integer and floating point types
+      'boolean',
+      ...[1, 8, 16, 32, 48, 64].map(n => `int${n}`),
+      ...[32, 48, 64].map(n => `float${n}`)
     )),
 
     enum_specifier: $ => seq(
@@ -588,7 +776,11 @@ module.exports = grammar({
 
     parameter_list: $ => seq(
       '(',
-      commaSep(choice($.parameter_declaration, $.variadic_parameter)),
+      commaSep(choice(
+        $.parameter_declaration,
+        $.optional_parameter_declaration,
+        $.variadic_parameter
+      )),
       ')'
     ),
 
@@ -596,10 +788,31 @@ module.exports = grammar({
       $._declaration_specifiers,
       optional(field('declarator', choice(
         $._declarator,
-        $._abstract_declarator
+        $._abstract_declarator,
+        $.reference_declarator,
+        $.abstract_reference_declarator
       )))
     ),
 
+    // CCS C accepts C++ like default arguments: `void f(int8 x, int8 y = 0)`
+    optional_parameter_declaration: $ => seq(
+      $._declaration_specifiers,
+      field('declarator', choice($._declarator, $.reference_declarator)),
+      '=',
+      field('default_value', $._expression)
+    ),
+
+    // CCS C accepts C++ like reference parameters: `void f(int8 &x)`
+    reference_declarator: $ => prec.dynamic(1, prec.right(seq(
+      '&',
+      field('declarator', $._declarator)
+    ))),
+
+    abstract_reference_declarator: $ => prec.right(seq(
+      '&',
+      optional(field('declarator', $._abstract_declarator))
+    )),
+
     // Statements
 
     attributed_statement: $ => seq(
@@ -1080,6 +1293,15 @@ function preprocessor (command) {
   return alias(new RegExp('#[ \t]*' + command), '#' + command)
 }
 
+// CCS directives are case-insensitive (`#FUSES` and `#fuses` are equivalent)
+function ccsDirective (command) {
+  const pattern = command
+    .split('')
+    .map(c => /[a-z]/.test(c) ? `[${c}${c.toUpperCase()}]` : c)
+    .join('')
+  return alias(new RegExp('#[ \t]*' + pattern), '#' + command)
+}
+
 function commaSep (rule) {
   return optional(commaSep1(rule))
 }
@@ -0,0 +1,22 @@
+[
+  (function_definition)
+  (compound_statement)
+  (struct_specifier)
+  (union_specifier)
+  (enum_specifier)
+  (field_declaration_list)
+  (enumerator_list)
+  (initializer_list)
+  (if_statement)
+  (for_statement)
+  (while_statement)
+  (do_statement)
+  (switch_statement)
+  (case_statement)
+  (preproc_if)
+  (preproc_ifdef)
+  (preproc_elif)
+  (preproc_else)
+  (asm_block)
+  (comment)
+] @fold
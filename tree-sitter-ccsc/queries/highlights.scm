@@ -0,0 +1,157 @@
+; Keywords
+
+"break" @keyword
+"case" @keyword
+"const" @keyword
+"continue" @keyword
+"default" @keyword
+"do" @keyword
+"else" @keyword
+"enum" @keyword
+"extern" @keyword
+"for" @keyword
+"goto" @keyword
+"if" @keyword
+"inline" @keyword
+"register" @keyword
+"return" @keyword
+"sizeof" @keyword
+"static" @keyword
+"struct" @keyword
+"switch" @keyword
+"typedef" @keyword
+"union" @keyword
+"volatile" @keyword
+"rom" @keyword
+"_readonly" @keyword
+"while" @keyword
+
+"#define" @keyword
+"#elif" @keyword
+"#else" @keyword
+"#endif" @keyword
+"#if" @keyword
+"#ifdef" @keyword
+"#ifndef" @keyword
+"#include" @keyword
+
+"#fuses" @keyword
+"#use" @keyword
+"#device" @keyword
+"#org" @keyword
+"#byte" @keyword
+"#word" @keyword
+"#bit" @keyword
+"#locate" @keyword
+"#reserve" @keyword
+"#rom" @keyword
+"#asm" @keyword
+"#endasm" @keyword
+
+; Interrupt directives like `#int_timer1` and directives without a rule of their own
+(preproc_directive) @keyword
+
+; Operators and punctuation
+
+"--" @operator
+"-" @operator
+"-=" @operator
+"->" @operator
+"=" @operator
+"!=" @operator
+"*" @operator
+"&" @operator
+"&&" @operator
+"+" @operator
+"++" @operator
+"+=" @operator
+"<" @operator
+"==" @operator
+">" @operator
+"||" @operator
+
+"." @delimiter
+";" @delimiter
+
+; Literals
+
+(string_literal) @string
+(system_lib_string) @string
+(escape_sequence) @string.escape
+
+(null) @constant
+(true) @constant.builtin
+(false) @constant.builtin
+(number_literal) @number
+(char_literal) @number
+
+(comment) @comment
+(asm_comment) @comment
+
+; Types
+
+(primitive_type) @type.builtin
+(sized_type_specifier) @type.builtin
+(type_identifier) @type
+
+; Functions
+
+((call_expression
+  function: (identifier) @function.builtin)
+ (#match? @function.builtin "^(output_[a-z]+|input(_[a-z]+)?|delay_(ms|us|cycles)|setup_[a-z0-9_]+|set_[a-z0-9_]+|get_timer[0-9]|(enable|disable)_interrupts|clear_interrupt|interrupt_active|read_[a-z_]+|write_[a-z_]+|restart_[a-z]+|bit_(set|clear|test)|make(8|16|32)|port_b_pullups|ext_int_edge|i2c_[a-z]+|spi_[a-z]+)$"))
+(call_expression
+  function: (identifier) @function)
+(call_expression
+  function: (field_expression
+    field: (field_identifier) @function))
+(function_declarator
+  declarator: (identifier) @function)
+(preproc_function_def
+  name: (identifier) @function.macro)
+
+; CCS arguments
+
+(fuse
+  name: (identifier) @constant.builtin)
+(preproc_use
+  library: (identifier) @namespace)
+(directive_option
+  key: (identifier) @property)
+(device_option
+  key: (identifier) @property)
+(preproc_interrupt
+  option: (identifier) @attribute)
+
+; Inline assembly
+
+(asm_mnemonic) @function.builtin
+(asm_label
+  name: (identifier) @label)
+
+; Identifiers
+
+(field_identifier) @property
+(statement_identifier) @label
+
+((identifier) @constant.builtin
+ (#match? @constant.builtin "^PIN_[A-Z]+[0-9]+$"))
+((identifier) @constant
+ (#match? @constant "^[A-Z][A-Z0-9_]*$"))
+(preproc_def
+  name: (identifier) @constant)
+
+(parameter_declaration
+  declarator: (identifier) @variable.parameter)
+(parameter_declaration
+  declarator: (pointer_declarator
+    declarator: (identifier) @variable.parameter))
+(parameter_declaration
+  declarator: (reference_declarator
+    declarator: (identifier) @variable.parameter))
+(optional_parameter_declaration
+  declarator: (identifier) @variable.parameter)
+(optional_parameter_declaration
+  declarator: (reference_declarator
+    declarator: (identifier) @variable.parameter))
+
+(identifier) @variable
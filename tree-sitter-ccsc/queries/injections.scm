@@ -0,0 +1,8 @@
+; Macro bodies are not parsed any further by the grammar
+((preproc_def
+  value: (preproc_arg) @injection.content)
+ (#set! injection.language "ccsc"))
+
+((preproc_function_def
+  value: (preproc_arg) @injection.content)
+ (#set! injection.language "ccsc"))
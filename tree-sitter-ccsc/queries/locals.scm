@@ -0,0 +1,58 @@
+; Scopes
+
+(translation_unit) @local.scope
+(function_definition) @local.scope
+(compound_statement) @local.scope
+(for_statement) @local.scope
+
+; Definitions
+
+(parameter_declaration
+  declarator: (identifier) @local.definition)
+(parameter_declaration
+  declarator: (pointer_declarator
+    declarator: (identifier) @local.definition))
+(parameter_declaration
+  declarator: (array_declarator
+    declarator: (identifier) @local.definition))
+(parameter_declaration
+  declarator: (reference_declarator
+    declarator: (identifier) @local.definition))
+(optional_parameter_declaration
+  declarator: (identifier) @local.definition)
+(optional_parameter_declaration
+  declarator: (reference_declarator
+    declarator: (identifier) @local.definition))
+
+(declaration
+  declarator: (identifier) @local.definition)
+(declaration
+  declarator: (pointer_declarator
+    declarator: (identifier) @local.definition))
+(declaration
+  declarator: (array_declarator
+    declarator: (identifier) @local.definition))
+(init_declarator
+  declarator: (identifier) @local.definition)
+(init_declarator
+  declarator: (pointer_declarator
+    declarator: (identifier) @local.definition))
+(init_declarator
+  declarator: (array_declarator
+    declarator: (identifier) @local.definition))
+
+(function_declarator
+  declarator: (identifier) @local.definition)
+(type_definition
+  declarator: (type_identifier) @local.definition)
+(enumerator
+  name: (identifier) @local.definition)
+(preproc_def
+  name: (identifier) @local.definition)
+(preproc_function_def
+  name: (identifier) @local.definition)
+
+; References
+
+(identifier) @local.reference
+(type_identifier) @local.reference
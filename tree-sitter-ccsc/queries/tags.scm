@@ -0,0 +1,35 @@
+(struct_specifier
+  name: (type_identifier) @name
+  body: (_)) @definition.class
+
+(union_specifier
+  name: (type_identifier) @name
+  body: (_)) @definition.class
+
+(enum_specifier
+  name: (type_identifier) @name
+  body: (_)) @definition.type
+
+(type_definition
+  declarator: (type_identifier) @name) @definition.type
+
+(function_definition
+  declarator: (function_declarator
+    declarator: (identifier) @name)) @definition.function
+
+(declaration
+  declarator: (function_declarator
+    declarator: (identifier) @name)) @definition.function
+
+(preproc_def
+  name: (identifier) @name) @definition.constant
+
+(preproc_function_def
+  name: (identifier) @name) @definition.macro
+
+; CCS interrupt handlers, e.g. `#int_timer1`
+(preproc_interrupt
+  directive: (preproc_directive) @name) @definition.interrupt
+
+(call_expression
+  function: (identifier) @name) @reference.call
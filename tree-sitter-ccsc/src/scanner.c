@@ -0,0 +1,86 @@
+#include "tree_sitter/parser.h"
+
+// Scans the parts of `#asm ... #endasm` blocks which do not follow C's lexical rules:
+// operands like `PORTB,3` or `b'0101'` and `;` comments
+
+enum TokenType {
+  ASM_OPERAND,
+  ASM_COMMENT,
+  ERROR_SENTINEL,
+};
+
+void *tree_sitter_ccsc_external_scanner_create() { return NULL; }
+
+void tree_sitter_ccsc_external_scanner_destroy(void *payload) {}
+
+unsigned tree_sitter_ccsc_external_scanner_serialize(void *payload, char *buffer) { return 0; }
+
+void tree_sitter_ccsc_external_scanner_deserialize(void *payload, const char *buffer,
+                                                   unsigned length) {}
+
+static bool is_line_end(TSLexer *lexer) {
+  return lexer->eof(lexer) || lexer->lookahead == '\n' || lexer->lookahead == '\r';
+}
+
+static bool is_horizontal_space(int32_t c) { return c == ' ' || c == '\t'; }
+
+static bool scan_comment(TSLexer *lexer) {
+  lexer->advance(lexer, false);
+  while (!is_line_end(lexer)) {
+    lexer->advance(lexer, false);
+  }
+  lexer->mark_end(lexer);
+  lexer->result_symbol = ASM_COMMENT;
+  return true;
+}
+
+// An operand ends at `,`, `;`, a C comment or the end of the line. Trailing spaces are excluded
+static bool scan_operand(TSLexer *lexer) {
+  bool has_content = false;
+
+  while (!is_line_end(lexer) && lexer->lookahead != ',' && lexer->lookahead != ';') {
+    if (lexer->lookahead == '/') {
+      lexer->advance(lexer, false);
+      if (lexer->lookahead == '/' || lexer->lookahead == '*') {
+        break;
+      }
+      has_content = true;
+      lexer->mark_end(lexer);
+      continue;
+    }
+
+    bool is_space = is_horizontal_space(lexer->lookahead);
+    lexer->advance(lexer, false);
+    if (!is_space) {
+      has_content = true;
+      lexer->mark_end(lexer);
+    }
+  }
+
+  lexer->result_symbol = ASM_OPERAND;
+  return has_content;
+}
+
+bool tree_sitter_ccsc_external_scanner_scan(void *payload, TSLexer *lexer,
+                                            const bool *valid_symbols) {
+  // Every symbol is valid during error recovery
+  if (valid_symbols[ERROR_SENTINEL]) {
+    return false;
+  }
+
+  while (is_horizontal_space(lexer->lookahead)) {
+    lexer->advance(lexer, true);
+  }
+
+  if (valid_symbols[ASM_COMMENT] && lexer->lookahead == ';') {
+    return scan_comment(lexer);
+  }
+
+  // No operand starts with `:`, which ends a label like `loop:` instead
+  if (valid_symbols[ASM_OPERAND] && !is_line_end(lexer) && lexer->lookahead != ',' &&
+      lexer->lookahead != ';' && lexer->lookahead != ':') {
+    return scan_operand(lexer);
+  }
+
+  return false;
+}
@@ -1,9 +1,10 @@
 import * as path from 'path';
-import { window, ExtensionContext, commands, workspace } from 'vscode';
+import { window, ExtensionContext, commands, workspace, Range, ViewColumn } from 'vscode';
 import * as cp from 'child_process';
 
 import {
     Executable,
+    ExecuteCommandRequest,
     LanguageClient,
     LanguageClientOptions,
     ServerOptions,
@@ -25,6 +26,10 @@ export function activate(context: ExtensionContext) {
 
     let clientOptions: LanguageClientOptions = {
         documentSelector: [{ scheme: 'file', language: 'ccsc' }],
+        initializationOptions: {
+            deviceHeaderDirectory: workspace.getConfiguration('ls-ccsc').get<string>('deviceHeaderDirectory') || undefined,
+            includeDirectories: workspace.getConfiguration('ls-ccsc').get<string[]>('includeDirectories'),
+        },
     };
 
 
@@ -37,6 +42,58 @@ export function activate(context: ExtensionContext) {
 
     client.start();
 
+    // Dims branches of #if, #ifdef, ... which are not compiled
+    const inactiveDecoration = window.createTextEditorDecorationType({ opacity: '0.5' });
+    const inactiveRegions = new Map<string, Range[]>();
+    const decorate = () => {
+        for (const editor of window.visibleTextEditors) {
+            editor.setDecorations(inactiveDecoration, inactiveRegions.get(editor.document.uri.toString()) ?? []);
+        }
+    };
+    client.onReady().then(() => {
+        client.onNotification('ccsc/inactiveRegions', (params: { textDocument: { uri: string }, regions: any[] }) => {
+            const uri = client.protocol2CodeConverter.asUri(params.textDocument.uri).toString();
+            inactiveRegions.set(uri, params.regions.map((r) => client.protocol2CodeConverter.asRange(r)));
+            decorate();
+        });
+    });
+    context.subscriptions.push(inactiveDecoration, window.onDidChangeVisibleTextEditors(decorate));
+
+    // Replaces the selected macro invocation with its expansion
+    context.subscriptions.push(commands.registerTextEditorCommand('vscode-ccsc.expandMacro', (editor) => {
+        client.sendRequest(ExecuteCommandRequest.type, {
+            command: 'ccsc.expandMacro',
+            arguments: [{
+                uri: client.code2ProtocolConverter.asUri(editor.document.uri),
+                range: client.code2ProtocolConverter.asRange(editor.selection),
+            }],
+        }).then(undefined, (error) => window.showErrorMessage(error.message));
+    }));
+
+    // Shows the instructions the last build generated for the selected lines
+    context.subscriptions.push(commands.registerTextEditorCommand('vscode-ccsc.disassembly', (editor) => {
+        client.sendRequest('ccsc/disassembly', {
+            textDocument: client.code2ProtocolConverter.asTextDocumentIdentifier(editor.document),
+            range: client.code2ProtocolConverter.asRange(editor.selection),
+        })
+            .then((lines: { line: number, source: string, instructions: { address: number, mnemonic: string, operands: string }[] }[]) => {
+                const content = lines.map((l) => [`; ${l.line + 1}: ${l.source.trim()}`]
+                    .concat(l.instructions.map((i) => `${i.address.toString(16).toUpperCase().padStart(4, '0')}:  ${i.mnemonic.padEnd(6)} ${i.operands}`))
+                    .join('\n')).join('\n\n');
+                return workspace.openTextDocument({ content });
+            })
+            .then((document) => window.showTextDocument(document, { preview: true, viewColumn: ViewColumn.Beside }))
+            .then(undefined, (error) => window.showErrorMessage(error.message));
+    }));
+
+    // Shows the memory usage and metrics of the last build
+    context.subscriptions.push(commands.registerCommand('vscode-ccsc.projectSummary', () => {
+        client.sendRequest(ExecuteCommandRequest.type, { command: 'ccsc.projectSummary' })
+            .then((summary) => workspace.openTextDocument({ language: 'json', content: JSON.stringify(summary, null, 4) }))
+            .then((document) => window.showTextDocument(document))
+            .then(undefined, (error) => window.showErrorMessage(error.message));
+    }));
+
     if (workspace.workspaceFolders === undefined) {
         window.showErrorMessage("No workspace folders found! Exiting...");
         return;